@@ -1,5 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use grainx::analytics::{calculate_correlation, predict_next_value, evaluate_metric_formula};
+use grainx::forecast::{forecast, ForecastConfig, ForecastModel};
 use grainx::performance::PerformanceMonitor;
 use std::collections::HashMap;
 
@@ -24,6 +25,17 @@ fn benchmark_prediction(c: &mut Criterion) {
     });
 }
 
+fn benchmark_forecast(c: &mut Criterion) {
+    let history: Vec<f64> = (0..1000).map(|i| 50.0 + (i % 24) as f64).collect();
+
+    for model in [ForecastModel::LinearRegression, ForecastModel::Holt, ForecastModel::HoltWinters] {
+        let config = ForecastConfig { model, horizon: 10, season_length: 24, ..ForecastConfig::default() };
+        c.bench_function(&format!("forecast_{}_1000_points", model.label()), |b| {
+            b.iter(|| forecast(black_box(&history), black_box(&config)))
+        });
+    }
+}
+
 fn benchmark_metric_formula(c: &mut Criterion) {
     let mut metrics = HashMap::new();
     metrics.insert("cpu_usage", 75.5);
@@ -67,6 +79,7 @@ criterion_group!(
     benches, 
     benchmark_correlation, 
     benchmark_prediction, 
+    benchmark_forecast,
     benchmark_metric_formula,
     benchmark_performance_monitor
 );
@@ -0,0 +1,395 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use crate::expr::{Condition, ExprError, MetricSource};
+
+/// How many fired/resolved transitions are kept for the "recent" list.
+const RECENT_EVENTS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+/// One alert rule from the config, e.g. `{ "condition": "cpu > 90 for 30s", "clear": 80 }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertRuleConfig {
+    /// Display name, the condition text when empty
+    #[serde(default)]
+    pub name: String,
+    pub condition: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// Value the metric has to fall back past before a firing alert resolves.
+    /// Defaults to the trigger threshold (no hysteresis).
+    #[serde(default)]
+    pub clear: Option<f64>,
+}
+
+impl AlertRuleConfig {
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() { &self.condition } else { &self.name }
+    }
+
+    /// Why `clear` does not fit the condition, if it does not. It has to be
+    /// past the threshold on the resolving side, otherwise the alert would
+    /// resolve while the condition still holds and fire again right away.
+    pub fn clear_problem(&self) -> Option<String> {
+        let clear = self.clear?;
+        let condition = Condition::parse(&self.condition).ok()?;
+        let (wrong_side, side) = if condition.comparison.is_upper_bound() {
+            (clear > condition.threshold, "above")
+        } else {
+            (clear < condition.threshold, "below")
+        };
+        wrong_side.then(|| format!("{} is {} the threshold {} of '{}'", clear, side, condition.threshold, self.condition))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Inactive,
+    /// The condition holds but has not held for the rule's duration yet
+    Pending { since: DateTime<Utc> },
+    Firing { since: DateTime<Utc> },
+    Resolved { at: DateTime<Utc> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertEventKind {
+    Firing,
+    Resolved,
+}
+
+/// A rule changing between firing and resolved.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub condition: String,
+    pub severity: Severity,
+    pub kind: AlertEventKind,
+    pub value: f64,
+    pub threshold: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AlertEvent {
+    pub fn describe(&self) -> String {
+        match self.kind {
+            AlertEventKind::Firing => format!("{} firing ({:.1})", self.rule, self.value),
+            AlertEventKind::Resolved => format!("{} resolved ({:.1})", self.rule, self.value),
+        }
+    }
+}
+
+pub struct AlertRule {
+    pub config: AlertRuleConfig,
+    /// The parsed condition, or why it did not parse
+    pub condition: Result<Condition, ExprError>,
+    pub state: AlertState,
+    pub last_value: Option<f64>,
+    /// Why the last evaluation failed, e.g. a disk that is not mounted
+    pub last_error: Option<ExprError>,
+    /// Set by an operator while the rule fires; cleared when it stops firing
+    pub acknowledged: bool,
+}
+
+impl AlertRule {
+    pub fn new(config: AlertRuleConfig) -> Self {
+        let condition = Condition::parse(&config.condition);
+        AlertRule { config, condition, state: AlertState::Inactive, last_value: None, last_error: None, acknowledged: false }
+    }
+
+    pub fn is_firing(&self) -> bool {
+        matches!(self.state, AlertState::Firing { .. })
+    }
+
+    /// Advance the state machine with this tick's value, returning a transition
+    /// if the rule started firing or resolved.
+    fn update(&mut self, condition: &Condition, value: f64, now: DateTime<Utc>) -> Option<AlertEventKind> {
+        let triggered = condition.comparison.holds(value, condition.threshold);
+        let clear = self.config.clear.unwrap_or(condition.threshold);
+        // Hysteresis: once firing, the value has to get back past `clear`
+        let cleared = if condition.comparison.is_upper_bound() { value < clear } else { value > clear };
+        let hold = chrono::Duration::from_std(condition.hold).unwrap_or(chrono::Duration::zero());
+
+        let (state, event) = match self.state {
+            AlertState::Inactive | AlertState::Resolved { .. } if triggered => {
+                if hold.is_zero() {
+                    (AlertState::Firing { since: now }, Some(AlertEventKind::Firing))
+                } else {
+                    (AlertState::Pending { since: now }, None)
+                }
+            }
+            AlertState::Pending { since } if triggered => {
+                if now - since >= hold {
+                    (AlertState::Firing { since: now }, Some(AlertEventKind::Firing))
+                } else {
+                    (self.state, None)
+                }
+            }
+            AlertState::Pending { .. } => (AlertState::Inactive, None),
+            AlertState::Firing { .. } if cleared => (AlertState::Resolved { at: now }, Some(AlertEventKind::Resolved)),
+            state => (state, None),
+        };
+        self.state = state;
+        if !self.is_firing() {
+            self.acknowledged = false;
+        }
+        event
+    }
+
+    /// The condition could not be evaluated, so nothing says it still holds:
+    /// a firing rule resolves and a pending one starts over.
+    fn fail(&mut self, now: DateTime<Utc>) -> Option<AlertEventKind> {
+        let (state, event) = match self.state {
+            AlertState::Firing { .. } => (AlertState::Resolved { at: now }, Some(AlertEventKind::Resolved)),
+            AlertState::Pending { .. } => (AlertState::Inactive, None),
+            state => (state, None),
+        };
+        self.state = state;
+        self.acknowledged = false;
+        event
+    }
+}
+
+/// Evaluates the configured alert rules every tick and remembers recent transitions.
+pub struct AlertEngine {
+    pub rules: Vec<AlertRule>,
+    recent: VecDeque<AlertEvent>,
+}
+
+impl AlertEngine {
+    pub fn new(configs: &[AlertRuleConfig]) -> Self {
+        AlertEngine {
+            rules: configs.iter().cloned().map(AlertRule::new).collect(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Switch to a new rule set. Rules whose config did not change keep
+    /// their state, so a reload does not re-fire or forget pending alerts.
+    pub fn reconfigure(&mut self, configs: &[AlertRuleConfig]) {
+        let mut old = std::mem::take(&mut self.rules);
+        self.rules = configs.iter()
+            .map(|config| match old.iter().position(|rule| rule.config == *config) {
+                Some(index) => old.swap_remove(index),
+                None => AlertRule::new(config.clone()),
+            })
+            .collect();
+    }
+
+    /// Acknowledge the firing rule called `name`.
+    pub fn acknowledge(&mut self, name: &str) -> Result<(), String> {
+        let rule = self.rules.iter_mut()
+            .find(|rule| rule.config.display_name() == name)
+            .ok_or_else(|| format!("no alert rule '{}'", name))?;
+        if !rule.is_firing() {
+            return Err(format!("alert '{}' is not firing", name));
+        }
+        rule.acknowledged = true;
+        Ok(())
+    }
+
+    pub fn evaluate(&mut self, source: &dyn MetricSource, now: DateTime<Utc>) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for rule in &mut self.rules {
+            let Ok(condition) = rule.condition.clone() else { continue };
+            let (kind, value) = match condition.expr.eval(source) {
+                Ok(value) => {
+                    rule.last_value = Some(value);
+                    rule.last_error = None;
+                    (rule.update(&condition, value, now), value)
+                }
+                // e.g. a disk that was unmounted; resolves with the last value seen
+                Err(error) => {
+                    rule.last_error = Some(error);
+                    (rule.fail(now), rule.last_value.unwrap_or(f64::NAN))
+                }
+            };
+
+            if let Some(kind) = kind {
+                events.push(AlertEvent {
+                    rule: rule.config.display_name().to_string(),
+                    condition: rule.config.condition.clone(),
+                    severity: rule.config.severity,
+                    kind,
+                    value,
+                    threshold: condition.threshold,
+                    timestamp: now,
+                });
+            }
+        }
+
+        for event in &events {
+            self.recent.push_front(event.clone());
+        }
+        self.recent.truncate(RECENT_EVENTS);
+        events
+    }
+
+    /// Rules currently firing, most severe first.
+    pub fn firing(&self) -> Vec<&AlertRule> {
+        let mut firing: Vec<&AlertRule> = self.rules.iter().filter(|rule| rule.is_firing()).collect();
+        firing.sort_by_key(|rule| std::cmp::Reverse(rule.config.severity));
+        firing
+    }
+
+    pub fn pending(&self) -> Vec<&AlertRule> {
+        self.rules.iter().filter(|rule| matches!(rule.state, AlertState::Pending { .. })).collect()
+    }
+
+    /// Recent fired/resolved transitions, newest first.
+    pub fn recent(&self) -> impl Iterator<Item = &AlertEvent> {
+        self.recent.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn rule(condition: &str, clear: Option<f64>) -> AlertRuleConfig {
+        AlertRuleConfig { name: String::new(), condition: condition.to_string(), severity: Severity::Warning, clear }
+    }
+
+    fn run(engine: &mut AlertEngine, metric: &'static str, values: &[f64]) -> Vec<Vec<AlertEvent>> {
+        let start = Utc::now();
+        values.iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let metrics: HashMap<&str, f64> = HashMap::from([(metric, *value)]);
+                engine.evaluate(&metrics, start + chrono::Duration::seconds(i as i64 * 10))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fires_immediately_without_duration() {
+        let mut engine = AlertEngine::new(&[rule("mem_pct > 85", None)]);
+        let events = run(&mut engine, "mem_pct", &[80.0, 90.0, 84.0]);
+        assert!(events[0].is_empty());
+        assert_eq!(events[1][0].kind, AlertEventKind::Firing);
+        assert_eq!(events[1][0].rule, "mem_pct > 85");
+        assert_eq!(events[2][0].kind, AlertEventKind::Resolved);
+        assert!(matches!(engine.rules[0].state, AlertState::Resolved { .. }));
+    }
+
+    #[test]
+    fn test_duration_requires_sustained_condition() {
+        let mut engine = AlertEngine::new(&[rule("cpu > 90 for 30s", None)]);
+        // Samples 10s apart: a dip resets the pending timer
+        let events = run(&mut engine, "cpu", &[95.0, 95.0, 50.0, 95.0, 95.0, 95.0, 95.0]);
+        assert!(events[..6].iter().all(|e| e.is_empty()));
+        assert_eq!(events[6][0].kind, AlertEventKind::Firing);
+
+        let mut engine = AlertEngine::new(&[rule("cpu > 90 for 30s", None)]);
+        run(&mut engine, "cpu", &[95.0, 95.0]);
+        assert_eq!(engine.pending().len(), 1);
+        assert!(engine.firing().is_empty());
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut engine = AlertEngine::new(&[rule("cpu > 90", Some(80.0))]);
+        let events = run(&mut engine, "cpu", &[95.0, 85.0, 89.0, 79.0]);
+        assert_eq!(events[0][0].kind, AlertEventKind::Firing);
+        assert!(events[1].is_empty() && events[2].is_empty());
+        assert_eq!(events[3][0].kind, AlertEventKind::Resolved);
+    }
+
+    #[test]
+    fn test_lower_bound_rules() {
+        let mut engine = AlertEngine::new(&[rule("free_pct < 10", Some(15.0))]);
+        let events = run(&mut engine, "free_pct", &[20.0, 5.0, 12.0, 16.0]);
+        assert_eq!(events[1][0].kind, AlertEventKind::Firing);
+        assert!(events[2].is_empty());
+        assert_eq!(events[3][0].kind, AlertEventKind::Resolved);
+    }
+
+    #[test]
+    fn test_missing_metrics_and_bad_rules() {
+        let mut engine = AlertEngine::new(&[rule("disk[\"/data\"] > 95", None), rule("cpu >", None)]);
+        run(&mut engine, "cpu", &[99.0]);
+        assert_eq!(engine.rules[0].last_error, Some(ExprError::UnknownMetric("disk[/data]".to_string())));
+        assert!(engine.rules[1].condition.is_err());
+        assert!(engine.firing().is_empty());
+    }
+
+    #[test]
+    fn test_failing_evaluation_resolves() {
+        let mut engine = AlertEngine::new(&[rule("disk > 90", None)]);
+        run(&mut engine, "disk", &[95.0]);
+        assert!(engine.rules[0].is_firing());
+        let events = run(&mut engine, "cpu", &[10.0]);
+        assert_eq!(events[0][0].kind, AlertEventKind::Resolved);
+        assert_eq!(events[0][0].value, 95.0);
+        assert!(engine.rules[0].last_error.is_some());
+        assert!(run(&mut engine, "cpu", &[10.0])[0].is_empty());
+    }
+
+    #[test]
+    fn test_clear_on_the_wrong_side() {
+        assert_eq!(rule("cpu > 90", Some(80.0)).clear_problem(), None);
+        assert_eq!(rule("cpu > 90", Some(95.0)).clear_problem().unwrap(), "95 is above the threshold 90 of 'cpu > 90'");
+        assert_eq!(rule("free_pct < 10", Some(15.0)).clear_problem(), None);
+        assert_eq!(rule("free_pct < 10", Some(5.0)).clear_problem().unwrap(), "5 is below the threshold 10 of 'free_pct < 10'");
+        assert_eq!(rule("cpu >", Some(5.0)).clear_problem(), None);
+    }
+
+    #[test]
+    fn test_recent_is_newest_first_and_firing_sorted_by_severity() {
+        let mut critical = rule("cpu > 50", None);
+        critical.severity = Severity::Critical;
+        let mut engine = AlertEngine::new(&[rule("cpu > 10", None), critical]);
+        run(&mut engine, "cpu", &[60.0, 20.0]);
+        let firing = engine.firing();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(engine.recent().next().unwrap().kind, AlertEventKind::Resolved);
+
+        run(&mut engine, "cpu", &[60.0]);
+        assert_eq!(engine.firing()[0].config.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn test_acknowledge_lasts_until_resolved() {
+        let mut engine = AlertEngine::new(&[rule("cpu > 90", None)]);
+        assert_eq!(engine.acknowledge("cpu > 90").unwrap_err(), "alert 'cpu > 90' is not firing");
+        assert_eq!(engine.acknowledge("mem").unwrap_err(), "no alert rule 'mem'");
+
+        run(&mut engine, "cpu", &[95.0]);
+        engine.acknowledge("cpu > 90").unwrap();
+        run(&mut engine, "cpu", &[96.0]);
+        assert!(engine.rules[0].acknowledged);
+
+        run(&mut engine, "cpu", &[50.0, 95.0]);
+        assert!(engine.rules[0].is_firing());
+        assert!(!engine.rules[0].acknowledged);
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_unchanged_rules() {
+        let mut engine = AlertEngine::new(&[rule("cpu > 90", None), rule("mem_pct > 85", None)]);
+        run(&mut engine, "cpu", &[95.0]);
+        engine.reconfigure(&[rule("load1 > 4", None), rule("cpu > 90", None)]);
+        assert_eq!(engine.rules[0].state, AlertState::Inactive);
+        assert!(engine.rules[1].is_firing());
+        assert_eq!(engine.rules.len(), 2);
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::VecDeque;
 use std::sync::Arc;
 use parking_lot::RwLock;
 use std::collections::HashMap;
+use crate::expr::Expr;
 
 pub struct AnomalyDetectorConfig {
     pub threshold_multiplier: f64,
@@ -128,38 +129,115 @@ pub fn calculate_correlation(data1: &[f64], data2: &[f64]) -> Option<f64> {
     }
 }
 
-pub fn evaluate_metric_formula(formula: &str, metrics: &HashMap<&str, f64>) -> Option<f64> {
-    // Very basic formula evaluation for prototype
-    // Supports only 'cpu_usage' and simple arithmetic (+, -, *, /)
-    let mut result = formula.to_string();
+/// Best Pearson correlation between `leader` and `follower` over lags `-max_lag..=max_lag`.
+///
+/// A positive lag means `follower` trails `leader` by that many samples. Returns
+/// `(lag, correlation)` for the lag with the largest absolute correlation.
+pub fn lagged_correlation(leader: &[f64], follower: &[f64], max_lag: usize) -> Option<(isize, f64)> {
+    if leader.len() != follower.len() {
+        return None;
+    }
 
-    // Replace metric names with their values
-    for (name, value) in metrics {
-        result = result.replace(name, &value.to_string());
+    let n = leader.len();
+    let mut best: Option<(isize, f64)> = None;
+    for lag in -(max_lag as isize)..=(max_lag as isize) {
+        let shift = lag.unsigned_abs();
+        // Require enough overlap for the coefficient to mean anything
+        if n < shift + 3 {
+            continue;
+        }
+        let (a, b) = if lag >= 0 {
+            (&leader[..n - shift], &follower[shift..])
+        } else {
+            (&leader[shift..], &follower[..n - shift])
+        };
+        if let Some(r) = calculate_correlation(a, b) && best.is_none_or(|(_, best_r)| r.abs() > best_r.abs()) {
+            best = Some((lag, r));
+        }
     }
+    best
+}
 
-    // Evaluate the expression (very basic, no operator precedence, just left to right)
-    let parts: Vec<&str> = result.split_whitespace().collect();
-    if parts.is_empty() { return None; }
+/// Pairs sharing fewer samples than this get no coefficient: two points
+/// always correlate perfectly.
+pub const MIN_CORRELATION_SAMPLES: usize = 10;
 
-    let mut current_value = parts[0].parse::<f64>().ok()?;
+/// Pairwise correlations between a set of named series.
+pub struct CorrelationMatrix {
+    pub names: Vec<String>,
+    /// `values[i][j]` is the correlation between series `i` and `j`, if defined
+    pub values: Vec<Vec<Option<f64>>>,
+}
+
+/// A notable relationship found in a correlation matrix.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelationPair {
+    pub first: String,
+    pub second: String,
+    pub correlation: f64,
+    /// Lag (in samples) at which the lagged correlation peaks
+    pub lag: isize,
+    pub lagged_correlation: f64,
+}
 
-    let mut i = 1;
-    while i < parts.len() {
-        let operator = parts[i];
-        let operand = parts[i+1].parse::<f64>().ok()?;
+impl CorrelationMatrix {
+    /// Correlate every pair of series. `aligned(a, b)` returns the values of
+    /// `a` and `b` at the samples they share, so gaps in one series only
+    /// shorten the pairs it is part of.
+    pub fn compute<F>(names: Vec<String>, aligned: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<(Vec<f64>, Vec<f64>)>,
+    {
+        let values = names.iter()
+            .map(|a| names.iter()
+                .map(|b| aligned(a, b)
+                    .filter(|(values, _)| values.len() >= MIN_CORRELATION_SAMPLES)
+                    .and_then(|(x, y)| calculate_correlation(&x, &y)))
+                .collect())
+            .collect();
+        CorrelationMatrix { names, values }
+    }
 
-        match operator {
-            "+" => current_value += operand,
-            "-" => current_value -= operand,
-            "*" => current_value *= operand,
-            "/" => current_value /= operand,
-            _ => return None, // Unsupported operator
+    pub fn get(&self, i: usize, j: usize) -> Option<f64> {
+        self.values.get(i)?.get(j).copied().flatten()
+    }
+
+    /// Pairs whose correlation reaches `threshold` in absolute value, strongest
+    /// first, annotated with their best lag over the same aligned samples.
+    pub fn strong_pairs<F>(&self, threshold: f64, max_lag: usize, aligned: F) -> Vec<CorrelationPair>
+    where
+        F: Fn(&str, &str) -> Option<(Vec<f64>, Vec<f64>)>,
+    {
+        let mut pairs = Vec::new();
+        for i in 0..self.names.len() {
+            for j in (i + 1)..self.names.len() {
+                let Some(correlation) = self.get(i, j) else { continue };
+                if correlation.abs() < threshold {
+                    continue;
+                }
+                let (lag, lagged) = aligned(&self.names[i], &self.names[j])
+                    .and_then(|(a, b)| lagged_correlation(&a, &b, max_lag))
+                    .unwrap_or((0, correlation));
+                pairs.push(CorrelationPair {
+                    first: self.names[i].clone(),
+                    second: self.names[j].clone(),
+                    correlation,
+                    lag,
+                    lagged_correlation: lagged,
+                });
+            }
         }
-        i += 2;
+        pairs.sort_by(|a, b| b.correlation.abs().partial_cmp(&a.correlation.abs()).unwrap_or(std::cmp::Ordering::Equal));
+        pairs
     }
+}
 
-    Some(current_value)
+/// Evaluate a formula once against the given metrics.
+///
+/// Returns `None` for formulas that fail to parse or evaluate; use `expr::Expr`
+/// directly to get the error or to compile a formula once and evaluate it every tick.
+pub fn evaluate_metric_formula(formula: &str, metrics: &HashMap<&str, f64>) -> Option<f64> {
+    Expr::parse(formula).ok()?.eval(metrics).ok()
 }
 
 pub fn predict_next_value(history: &[f64], window_size: usize) -> Option<f64> {
@@ -191,6 +269,45 @@ mod tests {
         assert!(correlation.is_some());
     }
 
+    #[test]
+    fn test_lagged_correlation_finds_delay() {
+        let leader: Vec<f64> = (0..40).map(|i| ((i * 7) % 13) as f64).collect();
+        let mut follower = vec![0.0; 3];
+        follower.extend_from_slice(&leader[..37]);
+        let (lag, r) = lagged_correlation(&leader, &follower, 5).unwrap();
+        assert_eq!(lag, 3);
+        assert!((r - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_correlation_matrix_strong_pairs() {
+        let base: Vec<f64> = (0..20).map(|i| ((i * 5) % 7) as f64).collect();
+        let series: HashMap<&str, Vec<f64>> = HashMap::from([
+            ("cpu", base.clone()),
+            ("load1", base.iter().map(|v| v * 0.1 + 1.0).collect()),
+            ("net_rx", (0..20).map(|i| ((i * 3) % 4) as f64).collect()),
+            // Too short to say anything yet
+            ("proc", base[..5].to_vec()),
+        ]);
+        let aligned = |a: &str, b: &str| {
+            let (a, b) = (series.get(a)?, series.get(b)?);
+            let n = a.len().min(b.len());
+            Some((a[..n].to_vec(), b[..n].to_vec()))
+        };
+        let names = ["cpu", "load1", "net_rx", "proc", "missing"].map(String::from).to_vec();
+        let matrix = CorrelationMatrix::compute(names, aligned);
+        assert!((matrix.get(0, 0).unwrap() - 1.0).abs() < 1e-9);
+        assert!((matrix.get(0, 1).unwrap() - 1.0).abs() < 1e-9);
+        assert!(matrix.get(0, 2).is_some());
+        assert_eq!(matrix.get(0, 3), None);
+        assert_eq!(matrix.get(0, 4), None);
+
+        let pairs = matrix.strong_pairs(0.9, 3, aligned);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].first.as_str(), pairs[0].second.as_str()), ("cpu", "load1"));
+        assert_eq!(pairs[0].lag, 0);
+    }
+
     #[test]
     fn test_prediction_basic() {
         let history = vec![10.0, 20.0, 30.0];
@@ -213,4 +330,13 @@ mod tests {
         let result = evaluate_metric_formula("cpu_usage + 20.0", &metrics).unwrap();
         assert_eq!(result, 50.0);
     }
+
+    #[test]
+    fn test_evaluate_formula_rejects_malformed_input() {
+        let mut metrics = HashMap::new();
+        metrics.insert("cpu_usage", 30.0);
+        assert_eq!(evaluate_metric_formula("cpu_usage *", &metrics), None);
+        assert_eq!(evaluate_metric_formula("unknown + 1", &metrics), None);
+        assert_eq!(evaluate_metric_formula("2 + cpu_usage * 2", &metrics), Some(62.0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,281 @@
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use crate::alerts::{AlertEngine, AlertState};
+use crate::history::MetricHistory;
+use crate::http::{self, Handler, LastError, Request, Response};
+use crate::snapshot::Snapshot;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    pub listen: String,
+    /// Samples kept per metric for `/api/history`
+    pub history_size: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig { enabled: false, listen: "127.0.0.1:9185".to_string(), history_size: 3600 }
+    }
+}
+
+/// What the API serves, replaced every tick.
+struct Published {
+    snapshot: Option<Snapshot>,
+    alerts: Value,
+    history: MetricHistory,
+}
+
+/// The alert rules with their state, and the recent transitions.
+pub fn alerts_json(alerts: &AlertEngine) -> Value {
+    let rules: Vec<Value> = alerts.rules.iter()
+        .map(|rule| {
+            let (state, since) = match rule.state {
+                AlertState::Inactive => ("inactive", None),
+                AlertState::Pending { since } => ("pending", Some(since)),
+                AlertState::Firing { since } => ("firing", Some(since)),
+                AlertState::Resolved { at } => ("resolved", Some(at)),
+            };
+            json!({
+                "name": rule.config.display_name(),
+                "condition": rule.config.condition,
+                "severity": rule.config.severity,
+                "state": state,
+                "since": since,
+                "value": rule.last_value,
+                "error": rule.last_error.as_ref().map(|e| e.to_string()),
+                "acknowledged": rule.acknowledged,
+            })
+        })
+        .collect();
+    json!({ "rules": rules, "recent": alerts.recent().collect::<Vec<_>>() })
+}
+
+/// `since` as an RFC 3339 time, Unix seconds, or an age such as `90s`,
+/// `15m`, `2h` or `1d`.
+pub fn parse_since(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(secs) = text.parse::<i64>() {
+        return DateTime::from_timestamp(secs, 0).ok_or_else(|| format!("timestamp {} is out of range", secs));
+    }
+    let unit_at = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (amount, unit) = text.split_at(unit_at);
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(format!("invalid since '{}': expected an RFC 3339 time, Unix seconds or an age like 15m", text)),
+    };
+    let amount: i64 = amount.parse().map_err(|_| format!("invalid since '{}'", text))?;
+    amount.checked_mul(seconds)
+        .and_then(chrono::TimeDelta::try_seconds)
+        .and_then(|age| now.checked_sub_signed(age))
+        .ok_or_else(|| format!("since '{}' is out of range", text))
+}
+
+fn error(status: u16, message: String) -> Response {
+    Response::json(status, &json!({ "error": message }))
+}
+
+fn processes(snapshot: &Snapshot, query: &BTreeMap<String, String>) -> Response {
+    let mut processes = snapshot.processes.clone();
+    match query.get("sort").map(|s| s.as_str()).unwrap_or("cpu") {
+        "cpu" => processes.sort_by(|a, b| b.2.total_cmp(&a.2)),
+        "mem" => processes.sort_by_key(|p| std::cmp::Reverse(p.3)),
+        "pid" => processes.sort_by_key(|p| p.0),
+        "name" => processes.sort_by(|a, b| a.1.cmp(&b.1)),
+        other => return error(400, format!("invalid sort '{}': expected cpu, mem, pid or name", other)),
+    }
+    if let Some(limit) = query.get("limit") {
+        match limit.parse() {
+            Ok(limit) => processes.truncate(limit),
+            Err(_) => return error(400, format!("invalid limit '{}'", limit)),
+        }
+    }
+    let processes: Vec<Value> = processes.iter()
+        .map(|(pid, name, cpu, memory)| json!({ "pid": pid, "name": name, "cpu": cpu, "memory": memory }))
+        .collect();
+    Response::json(200, &json!({ "timestamp": snapshot.timestamp, "processes": processes }))
+}
+
+fn history(history: &MetricHistory, metric: &str, query: &BTreeMap<String, String>) -> Response {
+    if history.samples(metric).is_none() {
+        return error(404, format!("no history for metric '{}'", metric));
+    }
+    let since = match query.get("since").map(|since| parse_since(since, Utc::now())) {
+        Some(Ok(since)) => since,
+        Some(Err(message)) => return error(400, message),
+        None => DateTime::<Utc>::MIN_UTC,
+    };
+    let samples: Vec<Value> = history.since(metric, since).iter()
+        .map(|(timestamp, value)| json!({ "timestamp": timestamp, "value": value }))
+        .collect();
+    Response::json(200, &json!({ "metric": metric, "samples": samples }))
+}
+
+fn route(published: &Published, request: &Request) -> Response {
+    let Some(path) = request.path.strip_prefix("/api/") else {
+        return Response::not_found();
+    };
+    if request.method != "GET" {
+        return error(405, "only GET is supported".to_string());
+    }
+    let snapshot = || published.snapshot.as_ref().ok_or_else(|| error(503, "no snapshot taken yet".to_string()));
+    let result = match path {
+        "snapshot" => snapshot().map(|snapshot| Response::json(200, snapshot)),
+        "processes" => snapshot().map(|snapshot| processes(snapshot, &request.query)),
+        "alerts" => Ok(Response::json(200, &published.alerts)),
+        // Metric names may contain slashes, e.g. `disk[/]`
+        path => match path.strip_prefix("history/") {
+            Some(metric) if !metric.is_empty() => Ok(history(&published.history, metric, &request.query)),
+            _ => Ok(Response::not_found()),
+        },
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+/// Serves the latest snapshot, processes, metric history and alerts as
+/// JSON on the tokio runtime.
+pub struct ApiServer {
+    published: Arc<Mutex<Published>>,
+    addr: SocketAddr,
+    last_error: LastError,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Must be called from within the tokio runtime.
+    pub fn start(config: &ApiConfig) -> io::Result<Self> {
+        let published = Arc::new(Mutex::new(Published {
+            snapshot: None,
+            alerts: json!({ "rules": [], "recent": [] }),
+            history: MetricHistory::new(config.history_size),
+        }));
+        let handler: Handler = {
+            let published = published.clone();
+            Arc::new(move |request: &Request| route(&published.lock(), request))
+        };
+        let last_error = LastError::default();
+        let (addr, task) = http::spawn_on_runtime(&config.listen, handler, last_error.clone())?;
+        Ok(ApiServer { published, addr, last_error, task })
+    }
+
+    /// Make this tick's snapshot, metrics and alert states visible.
+    pub fn publish(&self, snapshot: &Snapshot, metrics: &BTreeMap<String, f64>, alerts: &AlertEngine) {
+        let alerts = alerts_json(alerts);
+        let mut published = self.published.lock();
+        published.history.record_all(snapshot.timestamp, metrics);
+        // Series of exited processes stop receiving samples; drop them once
+        // they fall behind the oldest system sample like the dashboard does
+        if let Some((oldest, _)) = published.history.samples("cpu").and_then(|samples| samples.front()) {
+            let oldest = *oldest;
+            published.history.prune_stale(oldest);
+        }
+        published.snapshot = Some(snapshot.clone());
+        published.alerts = alerts;
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Why accepting connections is currently failing, if it is.
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().clone()
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertRuleConfig;
+    use crate::snapshot::sample;
+    use std::collections::HashMap;
+
+    fn snapshot(secs: i64, cpu: f32) -> Snapshot {
+        Snapshot {
+            processes: vec![(1, "init".to_string(), 0.5, 4096), (42, "db".to_string(), 30.0, 1024), (7, "web".to_string(), 10.0, 8192)],
+            ..sample(secs, cpu)
+        }
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> (u16, Value) {
+        let (status, body) = http::request(addr, "GET", path, &[], b"").unwrap();
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(parse_since("15m", now).unwrap(), now - chrono::Duration::minutes(15));
+        assert_eq!(parse_since("1699999000", now).unwrap().timestamp(), 1_699_999_000);
+        assert_eq!(parse_since("2023-11-14T22:00:00Z", now).unwrap().timestamp(), 1_699_999_200);
+        assert!(parse_since("yesterday", now).is_err());
+        assert!(parse_since("5w", now).is_err());
+        assert!(parse_since("9999999999999999d", now).is_err());
+        assert!(parse_since("99999999999999s", now).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_endpoints() {
+        let api = ApiServer::start(&ApiConfig { enabled: true, listen: "127.0.0.1:0".to_string(), history_size: 10 }).unwrap();
+        let addr = api.local_addr();
+        let (status, _) = tokio::task::spawn_blocking(move || get(addr, "/api/snapshot")).await.unwrap();
+        assert_eq!(status, 503);
+
+        let mut alerts = AlertEngine::new(&[AlertRuleConfig { name: "High CPU".to_string(), condition: "cpu > 20".to_string(), severity: Default::default(), clear: None }]);
+        for (secs, cpu) in [(0, 10.0), (60, 25.0), (120, 30.0)] {
+            let snapshot = snapshot(secs, cpu);
+            let metrics = snapshot.metrics();
+            alerts.evaluate(&HashMap::from([("cpu", cpu as f64)]), snapshot.timestamp);
+            api.publish(&snapshot, &metrics, &alerts);
+        }
+
+        tokio::task::spawn_blocking(move || {
+            let (status, body) = get(addr, "/api/snapshot");
+            assert_eq!(status, 200);
+            assert_eq!(body["cpu_usage"], 30.0);
+
+            let (_, body) = get(addr, "/api/processes?sort=mem&limit=2");
+            let names: Vec<&str> = body["processes"].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap()).collect();
+            assert_eq!(names, ["web", "init"]);
+            assert_eq!(get(addr, "/api/processes?sort=size").0, 400);
+
+            let (_, body) = get(addr, "/api/history/cpu?since=1700000060");
+            let values: Vec<f64> = body["samples"].as_array().unwrap().iter().map(|s| s["value"].as_f64().unwrap()).collect();
+            assert_eq!(values, [25.0, 30.0]);
+            assert_eq!(get(addr, "/api/history/nonsense").0, 404);
+            assert_eq!(get(addr, "/api/history/cpu?since=later").0, 400);
+            assert_eq!(get(addr, "/api/history/cpu?since=9999999999999999d").0, 400);
+
+            let (_, body) = get(addr, "/api/alerts");
+            assert_eq!(body["rules"][0]["state"], "firing");
+            assert_eq!(body["recent"][0]["rule"], "High CPU");
+
+            assert_eq!(http::request(addr, "DELETE", "/api/snapshot", &[], b"").unwrap().0, 405);
+            assert_eq!(http::request(addr, "GET", "/metrics", &[], b"").unwrap().0, 404);
+        }).await.unwrap();
+    }
+
+    #[test]
+    fn test_needs_runtime() {
+        let error = ApiServer::start(&ApiConfig::default()).err().unwrap();
+        assert!(error.to_string().contains("without a tokio runtime"));
+    }
+}
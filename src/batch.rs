@@ -0,0 +1,152 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+use crate::alerts::{AlertEvent, AlertEventKind};
+use crate::config::DashboardConfig;
+use crate::events::{self, Event};
+use crate::monitor::SystemMonitor;
+use crate::snapshot::Snapshot;
+use crate::ui::{DashboardState, Tick};
+
+/// Scalar metrics written on every sample line, in order.
+const SAMPLE_METRICS: [&str; 6] = ["cpu", "mem_pct", "swap_pct", "load1", "net_rx", "net_tx"];
+
+pub fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// `<time> sample host=<host> cpu=12.5 mem_pct=40.1 ... <custom metrics>`
+pub fn format_sample(snapshot: &Snapshot, tick: &Tick) -> String {
+    let mut line = format!("{} sample host={}", timestamp(snapshot.timestamp), snapshot.hostname);
+    for name in SAMPLE_METRICS {
+        if let Some(value) = tick.metrics.get(name) {
+            line.push_str(&format!(" {}={:.2}", name, value));
+        }
+    }
+    for value in &tick.custom_values {
+        if let Ok(v) = value.value {
+            line.push_str(&format!(" {}={:.2}", value.name, v));
+        }
+    }
+    line
+}
+
+pub fn format_event(event: &Event) -> String {
+    format!("{} event {} {}", timestamp(event.timestamp), event.severity().label(), event.describe())
+}
+
+/// `format_event` for a server logging several hosts.
+pub fn format_host_event(host: &str, event: &Event) -> String {
+    format!("{} event {} host={} {}", timestamp(event.timestamp), event.severity().label(), host, event.describe())
+}
+
+pub fn format_alert(event: &AlertEvent) -> String {
+    alert_line(None, event)
+}
+
+/// `format_alert` for a server logging several hosts.
+pub fn format_host_alert(host: &str, event: &AlertEvent) -> String {
+    alert_line(Some(host), event)
+}
+
+fn alert_line(host: Option<&str>, event: &AlertEvent) -> String {
+    let state = match event.kind {
+        AlertEventKind::Firing => "firing",
+        AlertEventKind::Resolved => "resolved",
+    };
+    let host = host.map(|host| format!("host={} ", host)).unwrap_or_default();
+    format!("{} alert {}{} {} rule=\"{}\" value={:.2} threshold={}",
+        timestamp(event.timestamp), host, event.severity.label(), state, event.rule, event.value, event.threshold)
+}
+
+/// Sample the system without the dashboard, writing one line per sample,
+/// detected event and alert transition to `out`.
+pub fn run_batch(config: &DashboardConfig, iterations: Option<u64>, out: &mut impl Write) -> io::Result<()> {
+    let (sender, receiver) = events::channel();
+    let mut monitor = SystemMonitor::new().with_events(sender);
+    let mut state = DashboardState::new(config)?;
+    let interval = Duration::from_millis(config.refresh_interval_ms);
+
+    let mut count = 0;
+    while iterations.is_none_or(|n| count < n) {
+        let snapshot = monitor.snapshot();
+        let tick = state.update(&snapshot);
+
+        writeln!(out, "{}", format_sample(&snapshot, &tick))?;
+        for event in receiver.drain().iter().chain(&tick.events) {
+            writeln!(out, "{}", format_event(event))?;
+        }
+        for event in &tick.alert_events {
+            writeln!(out, "{}", format_alert(event))?;
+        }
+        out.flush()?;
+
+        count += 1;
+        if iterations.is_none_or(|n| count < n) {
+            thread::sleep(interval);
+        }
+    }
+
+    if state.learner.config.enabled {
+        state.learner.save()?;
+    }
+    if let Some(store) = &mut state.store {
+        store.close()?;
+    }
+    if let Some(export) = &mut state.export {
+        export.close()?;
+    }
+    if let Some(push) = state.push.take() {
+        push.shutdown();
+    }
+    if let Some(otlp) = state.otlp.take() {
+        otlp.shutdown();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Severity;
+    use crate::custom_metrics::{CustomMetricValue, ThresholdLevel};
+    use crate::events::EventKind;
+    use crate::patterns::PatternKind;
+    use crate::snapshot::sample;
+    use std::collections::BTreeMap;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_format_lines() {
+        let snapshot = sample(0, 12.5);
+        let tick = Tick {
+            metrics: BTreeMap::from([("cpu".to_string(), 12.5), ("load1".to_string(), 0.5)]),
+            custom_values: vec![CustomMetricValue { name: "cpu_x2".to_string(), value: Ok(25.0), level: ThresholdLevel::Normal }],
+            alert_events: Vec::new(),
+            events: Vec::new(),
+        };
+        assert_eq!(format_sample(&snapshot, &tick), "2023-11-14T22:13:20Z sample host=web-1 cpu=12.50 load1=0.50 cpu_x2=25.00");
+
+        let event = Event {
+            timestamp: at(5),
+            kind: EventKind::Pattern { metric: "cpu".to_string(), pattern: PatternKind::Flapping, period_secs: Some(6.0), amplitude: 50.0 },
+        };
+        assert_eq!(format_event(&event), "2023-11-14T22:13:25Z event warning cpu flapping every 6s, amplitude 50.0");
+
+        let alert = AlertEvent {
+            rule: "High CPU".to_string(),
+            condition: "cpu > 90".to_string(),
+            severity: Severity::Critical,
+            kind: AlertEventKind::Firing,
+            value: 95.0,
+            threshold: 90.0,
+            timestamp: at(10),
+        };
+        assert_eq!(format_alert(&alert), "2023-11-14T22:13:30Z alert critical firing rule=\"High CPU\" value=95.00 threshold=90");
+        assert_eq!(format_host_alert("db-1", &alert), "2023-11-14T22:13:30Z alert host=db-1 critical firing rule=\"High CPU\" value=95.00 threshold=90");
+    }
+}
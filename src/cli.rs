@@ -0,0 +1,227 @@
+pub const USAGE: &str = "\
+Usage: grainx [OPTIONS]
+       grainx record <FILE> [OPTIONS]
+       grainx replay <FILE> [OPTIONS]
+       grainx agent [SERVER] [OPTIONS]
+       grainx server [LISTEN] [OPTIONS]
+       grainx ctl <COMMAND> [ARG] [OPTIONS]
+       grainx config <dump-default|check> [OPTIONS]
+
+Commands:
+  record <FILE>           Run the dashboard and save every snapshot and event to FILE
+  replay <FILE>           Play a recording back in the dashboard
+                          (space pauses, +/- change speed, left/right seek 10s)
+  agent [SERVER]          Stream snapshots to a grainx server [default: network.agent.server]
+  server [LISTEN]         Receive snapshots from agents and show every host in one table,
+                          or log them per host with --batch [default: network.server.listen]
+  ctl <COMMAND> [ARG]     Control a running dashboard through its control socket:
+                          status, pause, resume, snapshot [FILE], refresh <MS|auto>,
+                          reload, ack <RULE>, query <METRIC>
+  config dump-default     Print the built-in default config
+  config check            Check the config file and list every problem with its line and column
+
+Options:
+  -c, --config <PATH>     Dashboard config file [default: dashboard_config.json]
+  -b, --batch             Log samples, events and alerts as text lines instead of drawing the dashboard
+  -n, --iterations <N>    Stop after N samples (batch mode)
+  -h, --help              Show this help
+";
+
+#[cfg(unix)]
+use crate::control::ControlRequest;
+
+pub const DEFAULT_CONFIG_PATH: &str = "dashboard_config.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// The interactive terminal dashboard
+    Dashboard,
+    /// Headless logging to stdout
+    Batch { iterations: Option<u64> },
+    /// The dashboard, saving the snapshot stream to a file
+    Record { path: String },
+    /// The dashboard, driven by a recording instead of the live system
+    Replay { path: String },
+    /// Stream snapshots to a server, overriding the configured address
+    Agent { server: Option<String> },
+    /// Receive snapshots from agents, overriding the configured listen
+    /// address; `batch` logs them instead of drawing the host overview
+    Server { listen: Option<String>, batch: bool },
+    /// Send one command to a running dashboard
+    #[cfg(unix)]
+    Ctl { request: ControlRequest },
+    Config { action: ConfigAction },
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigAction {
+    /// Print the built-in config
+    DumpDefault,
+    /// Validate the config file without starting
+    Check,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cli {
+    pub config_path: String,
+    pub command: Command,
+}
+
+/// Parse the command line, without the program name.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Cli, String> {
+    let mut config_path = DEFAULT_CONFIG_PATH.to_string();
+    let mut batch = false;
+    let mut iterations = None;
+    let mut subcommand = None;
+
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-c" | "--config" => config_path = value(&arg)?,
+            "-b" | "--batch" => batch = true,
+            "-n" | "--iterations" => {
+                let text = value(&arg)?;
+                iterations = Some(text.parse().map_err(|_| format!("invalid iteration count '{}'", text))?);
+            }
+            "-h" | "--help" => return Ok(Cli { config_path, command: Command::Help }),
+            "record" | "replay" if subcommand.is_none() => {
+                let path = value(&arg)?;
+                subcommand = Some(if arg == "record" { Command::Record { path } } else { Command::Replay { path } });
+            }
+            "agent" | "server" if subcommand.is_none() => {
+                let address = args.next_if(|next| !next.starts_with('-'));
+                subcommand = Some(if arg == "agent" { Command::Agent { server: address } } else { Command::Server { listen: address, batch: false } });
+            }
+            #[cfg(not(unix))]
+            "ctl" => return Err("control socket is not supported on this platform".to_string()),
+            #[cfg(unix)]
+            "ctl" if subcommand.is_none() => {
+                let name = value(&arg)?;
+                let mut argument = |what: &str| args.next().ok_or_else(|| format!("ctl {} needs {}", name, what));
+                let request = match name.as_str() {
+                    "status" => ControlRequest::Status,
+                    "pause" => ControlRequest::Pause,
+                    "resume" => ControlRequest::Resume,
+                    "reload" => ControlRequest::Reload,
+                    "snapshot" => ControlRequest::Snapshot { path: args.next_if(|next| !next.starts_with('-')) },
+                    "refresh" => {
+                        let text = argument("an interval in ms or 'auto'")?;
+                        let interval_ms = match text.as_str() {
+                            "auto" => None,
+                            _ => Some(text.parse().map_err(|_| format!("invalid refresh interval '{}'", text))?),
+                        };
+                        ControlRequest::Refresh { interval_ms }
+                    }
+                    "ack" => ControlRequest::Ack { rule: argument("a rule name")? },
+                    "query" => ControlRequest::Query { metric: argument("a metric name")? },
+                    other => return Err(format!("unknown ctl command '{}'", other)),
+                };
+                subcommand = Some(Command::Ctl { request });
+            }
+            "config" if subcommand.is_none() => {
+                let action = match value(&arg)?.as_str() {
+                    "dump-default" => ConfigAction::DumpDefault,
+                    "check" => ConfigAction::Check,
+                    other => return Err(format!("unknown config command '{}'", other)),
+                };
+                subcommand = Some(Command::Config { action });
+            }
+            other => return Err(format!("unexpected argument '{}'", other)),
+        }
+    }
+
+    if iterations.is_some() && !batch {
+        return Err("--iterations only applies to --batch".to_string());
+    }
+    let command = match subcommand {
+        Some(Command::Server { listen, .. }) if iterations.is_none() => Command::Server { listen, batch },
+        Some(_) if batch => return Err("--batch cannot be combined with a command".to_string()),
+        Some(command) => command,
+        None if batch => Command::Batch { iterations },
+        None => Command::Dashboard,
+    };
+    Ok(Cli { config_path, command })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_defaults_to_dashboard() {
+        let cli = parse(&[]).unwrap();
+        assert_eq!(cli.command, Command::Dashboard);
+        assert_eq!(cli.config_path, DEFAULT_CONFIG_PATH);
+    }
+
+    #[test]
+    fn test_batch_mode() {
+        let cli = parse(&["--batch", "-n", "3", "--config", "other.json"]).unwrap();
+        assert_eq!(cli.command, Command::Batch { iterations: Some(3) });
+        assert_eq!(cli.config_path, "other.json");
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        assert_eq!(parse(&["record", "out.grx"]).unwrap().command, Command::Record { path: "out.grx".to_string() });
+        let cli = parse(&["-c", "other.json", "replay", "out.grx"]).unwrap();
+        assert_eq!(cli.command, Command::Replay { path: "out.grx".to_string() });
+        assert_eq!(cli.config_path, "other.json");
+
+        assert!(parse(&["record"]).is_err());
+        assert!(parse(&["record", "a.grx", "replay", "b.grx"]).is_err());
+        assert!(parse(&["replay", "out.grx", "--batch"]).is_err());
+    }
+
+    #[test]
+    fn test_agent_and_server() {
+        assert_eq!(parse(&["agent"]).unwrap().command, Command::Agent { server: None });
+        assert_eq!(parse(&["agent", "10.0.0.5:9190"]).unwrap().command, Command::Agent { server: Some("10.0.0.5:9190".to_string()) });
+        let cli = parse(&["server", "-c", "other.json"]).unwrap();
+        assert_eq!(cli.command, Command::Server { listen: None, batch: false });
+        assert_eq!(cli.config_path, "other.json");
+        assert_eq!(parse(&["server", ":9000", "--batch"]).unwrap().command, Command::Server { listen: Some(":9000".to_string()), batch: true });
+        assert!(parse(&["server", "--batch", "-n", "3"]).is_err());
+        assert!(parse(&["agent", "a:1", "server"]).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_ctl() {
+        assert_eq!(parse(&["ctl", "pause"]).unwrap().command, Command::Ctl { request: ControlRequest::Pause });
+        assert_eq!(parse(&["ctl", "ack", "High CPU", "-c", "other.json"]).unwrap().command,
+            Command::Ctl { request: ControlRequest::Ack { rule: "High CPU".to_string() } });
+        assert_eq!(parse(&["ctl", "refresh", "auto"]).unwrap().command, Command::Ctl { request: ControlRequest::Refresh { interval_ms: None } });
+        assert_eq!(parse(&["ctl", "snapshot"]).unwrap().command, Command::Ctl { request: ControlRequest::Snapshot { path: None } });
+        assert_eq!(parse(&["ctl", "refresh", "fast"]).unwrap_err(), "invalid refresh interval 'fast'");
+        assert_eq!(parse(&["ctl", "query"]).unwrap_err(), "ctl query needs a metric name");
+        assert_eq!(parse(&["ctl", "reboot"]).unwrap_err(), "unknown ctl command 'reboot'");
+        assert!(parse(&["ctl", "status", "--batch"]).is_err());
+    }
+
+    #[test]
+    fn test_config_commands() {
+        assert_eq!(parse(&["config", "dump-default"]).unwrap().command, Command::Config { action: ConfigAction::DumpDefault });
+        let cli = parse(&["config", "check", "-c", "other.json"]).unwrap();
+        assert_eq!(cli.command, Command::Config { action: ConfigAction::Check });
+        assert_eq!(cli.config_path, "other.json");
+        assert_eq!(parse(&["config", "fix"]).unwrap_err(), "unknown config command 'fix'");
+        assert_eq!(parse(&["config"]).unwrap_err(), "config needs a value");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--batch", "-n", "many"]).is_err());
+        assert!(parse(&["-n", "3"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+        assert_eq!(parse(&["--verbose", "--help"]).unwrap_err(), "unexpected argument '--verbose'");
+        assert_eq!(parse(&["-h"]).unwrap().command, Command::Help);
+    }
+}
@@ -1,9 +1,66 @@
 use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use std::fmt;
 use std::fs;
-use std::io;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use crate::alerts::{AlertRuleConfig, Severity};
+use crate::api::ApiConfig;
+use crate::control::ControlConfig;
+use crate::custom_metrics::CustomMetricConfig;
+use crate::exhaustion::ExhaustionConfig;
+use crate::forecast::ForecastConfig;
+use crate::export::ExportConfig;
+use crate::learning::LearningConfig;
+use crate::network::NetworkConfig;
+use crate::otlp::OtlpConfig;
+use crate::patterns::PatternConfig;
+use crate::prometheus::PrometheusConfig;
+use crate::push::PushConfig;
+use crate::tsdb::StorageConfig;
+use crate::notify::NotifierConfig;
 
+/// How often the running dashboard looks for changes to its config file.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Version of the config schema written by this build. Files of older
+/// versions are upgraded by `MIGRATIONS` when they are loaded.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Shortest refresh interval the dashboard can be set to.
+pub const MIN_REFRESH_INTERVAL_MS: u64 = 100;
+
+/// Panel names accepted in `layout`.
+pub const LAYOUT_PANELS: [&str; 5] = ["cpu_graph", "memory_usage", "network_stats", "process_list", "analytics"];
+
+/// A problem in a config file: which field, where it is and what is wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    /// Field path such as `layout[2]` or `alerts[0].severity`, empty when
+    /// the file itself is malformed
+    pub field: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.field.is_empty() {
+            write!(f, "{}: ", self.field)?;
+        }
+        write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+    }
+}
+
+/// Missing fields take their value from `default_config`, so files only
+/// need the options they change.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct DashboardConfig {
+    /// Schema version the file was written for, see `CONFIG_VERSION`
+    pub version: u32,
     pub name: String,
     pub layout: Vec<String>,
     pub refresh_interval_ms: u64,
@@ -13,13 +70,184 @@ pub struct DashboardConfig {
     pub show_correlations: bool,
     pub max_processes: usize,
     pub graph_history_size: usize,
+    pub forecast: ForecastConfig,
+    pub exhaustion: ExhaustionConfig,
+    pub custom_metrics: Vec<CustomMetricConfig>,
+    pub alerts: Vec<AlertRuleConfig>,
+    pub notifiers: Vec<NotifierConfig>,
+    pub learning: LearningConfig,
+    pub patterns: PatternConfig,
+    pub storage: StorageConfig,
+    pub prometheus: PrometheusConfig,
+    pub export: ExportConfig,
+    pub push: Vec<PushConfig>,
+    pub otlp: OtlpConfig,
+    pub network: NetworkConfig,
+    pub api: ApiConfig,
+    pub control: ControlConfig,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        DashboardConfig::default_config()
+    }
 }
 
 impl DashboardConfig {
+    /// The same dashboard without effects outside the process: no stored
+    /// history, no learned thresholds, no notifications, no metrics
+    /// endpoint, API or control socket, no file export and no pushed or
+    /// OTLP metrics. Used for replays.
+    pub fn for_replay(&self) -> Self {
+        let mut config = self.clone();
+        config.storage.enabled = false;
+        config.learning.enabled = false;
+        config.notifiers.clear();
+        config.prometheus.enabled = false;
+        config.export.enabled = false;
+        config.push.clear();
+        config.otlp.enabled = false;
+        config.api.enabled = false;
+        config.control.enabled = false;
+        config
+    }
+
+    /// The dashboard a server keeps for each connected host: alerts,
+    /// notifications and detections run, but everything that writes to a
+    /// fixed path or binds a port stays with the server's own config.
+    pub fn for_remote_host(&self) -> Self {
+        let mut config = self.clone();
+        config.storage.enabled = false;
+        config.learning.enabled = false;
+        config.prometheus.enabled = false;
+        config.export.enabled = false;
+        config.push.clear();
+        config.otlp.enabled = false;
+        config.api.enabled = false;
+        config.control.enabled = false;
+        config
+    }
+
+    /// This config as a dashboard started with `running` can apply it:
+    /// sections that own files, ports or threads keep their running values,
+    /// and the ones that changed are returned since they need a restart.
+    pub fn applied_to(&self, running: &DashboardConfig) -> (DashboardConfig, Vec<&'static str>) {
+        fn keep<T: Serialize + Clone>(section: &'static str, new: &mut T, running: &T, restart: &mut Vec<&'static str>) {
+            if serde_json::to_value(&*new).ok() != serde_json::to_value(running).ok() {
+                *new = running.clone();
+                restart.push(section);
+            }
+        }
+        let mut config = self.clone();
+        let mut restart = Vec::new();
+        keep("notifiers", &mut config.notifiers, &running.notifiers, &mut restart);
+        keep("learning", &mut config.learning, &running.learning, &mut restart);
+        keep("storage", &mut config.storage, &running.storage, &mut restart);
+        keep("prometheus", &mut config.prometheus, &running.prometheus, &mut restart);
+        keep("export", &mut config.export, &running.export, &mut restart);
+        keep("push", &mut config.push, &running.push, &mut restart);
+        keep("otlp", &mut config.otlp, &running.otlp, &mut restart);
+        keep("network", &mut config.network, &running.network, &mut restart);
+        keep("api", &mut config.api, &running.api, &mut restart);
+        keep("control", &mut config.control, &running.control, &mut restart);
+        (config, restart)
+    }
+
+    /// Values that deserialize but cannot be used, as field path and message.
+    pub fn validate(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        for (field, value) in [("cpu_warning_threshold", self.cpu_warning_threshold), ("memory_warning_threshold", self.memory_warning_threshold)] {
+            if !(0.0..=100.0).contains(&value) {
+                problems.push((field.to_string(), format!("must be between 0 and 100, got {}", value)));
+            }
+        }
+        if self.refresh_interval_ms < MIN_REFRESH_INTERVAL_MS {
+            problems.push(("refresh_interval_ms".to_string(),
+                format!("must be at least {}, got {}", MIN_REFRESH_INTERVAL_MS, self.refresh_interval_ms)));
+        }
+        for (i, rule) in self.alerts.iter().enumerate() {
+            if let Some(problem) = rule.clear_problem() {
+                problems.push((format!("alerts[{}].clear", i), problem));
+            }
+            // Rules are told apart by name, e.g. in `/metrics` labels and acknowledgements
+            if self.alerts[..i].iter().any(|other| other.display_name() == rule.display_name()) {
+                let field = if rule.name.is_empty() { "condition" } else { "name" };
+                problems.push((format!("alerts[{}].{}", i, field), format!("another rule is already named '{}'", rule.display_name())));
+            }
+        }
+        for (i, panel) in self.layout.iter().enumerate() {
+            if !LAYOUT_PANELS.contains(&panel.as_str()) {
+                problems.push((format!("layout[{}]", i), format!("unknown panel '{}', expected one of {}", panel, LAYOUT_PANELS.join(", "))));
+            }
+        }
+        problems
+    }
+
+    /// Parse and validate the contents of a config file, upgrading older
+    /// versions in memory. A malformed file gives its first error;
+    /// otherwise every invalid value is reported.
+    pub fn parse(text: &str) -> Result<Self, Vec<ConfigError>> {
+        let config: DashboardConfig = match versioned(text) {
+            Some((version, _)) if version == 0 || version > CONFIG_VERSION => {
+                let (line, column) = locate(text, "version").unwrap_or((1, 1));
+                let message = format!("unsupported version {}, this grainx reads versions 1 to {}", version, CONFIG_VERSION);
+                return Err(vec![ConfigError { field: "version".to_string(), line, column, message }]);
+            }
+            Some((version, mut fields)) if version < CONFIG_VERSION => {
+                for migrate in &MIGRATIONS[version as usize - 1..] {
+                    migrate(&mut fields);
+                }
+                fields.insert("version".to_string(), CONFIG_VERSION.into());
+                serde_path_to_error::deserialize(Value::Object(fields)).map_err(|e| {
+                    let field = error_field(e.path());
+                    let (line, column) = locate(text, &field).unwrap_or((1, 1));
+                    vec![ConfigError { field, line, column, message: e.inner().to_string() }]
+                })?
+            }
+            _ => serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(text)).map_err(|e| {
+                let (line, column) = (e.inner().line(), e.inner().column());
+                // serde_json appends the position, which ConfigError shows itself
+                let message = e.inner().to_string();
+                let message = message.strip_suffix(&format!(" at line {} column {}", line, column)).unwrap_or(&message).to_string();
+                vec![ConfigError { field: error_field(e.path()), line, column, message }]
+            })?,
+        };
+        let errors: Vec<ConfigError> = config.validate()
+            .into_iter()
+            .map(|(field, message)| {
+                let (line, column) = locate(text, &field).unwrap_or((1, 1));
+                ConfigError { field, line, column, message }
+            })
+            .collect();
+        if errors.is_empty() { Ok(config) } else { Err(errors) }
+    }
+
     pub fn load_from_file(path: &str) -> io::Result<Self> {
         let content = fs::read_to_string(path)?;
-        let config: DashboardConfig = serde_json::from_str(&content)?;
-        Ok(config)
+        Self::parse(&content).map_err(invalid_data)
+    }
+
+    /// Rewrite a config file of an older version in the current one, after
+    /// copying the original to `<path>.v<version>.bak`. Returns the old
+    /// version and the backup path, or `None` when the file is current.
+    /// A file that does not load is left alone.
+    pub fn upgrade_file(path: &str) -> io::Result<Option<(u32, String)>> {
+        let text = fs::read_to_string(path)?;
+        let version = match versioned(&text) {
+            Some((version, _)) if (1..CONFIG_VERSION).contains(&version) => version,
+            _ => return Ok(None),
+        };
+        let config = Self::parse(&text).map_err(invalid_data)?;
+        let backup = (0..)
+            .map(|n| if n == 0 { format!("{}.v{}.bak", path, version) } else { format!("{}.v{}.{}.bak", path, version, n) })
+            .find(|backup| !Path::new(backup).exists())
+            .expect("some backup name is free");
+        fs::copy(path, &backup)?;
+        // Never leave a half-written config behind
+        let temporary = format!("{}.tmp", path);
+        config.save_to_file(&temporary)?;
+        fs::rename(&temporary, path)?;
+        Ok(Some((version, backup)))
     }
 
     pub fn save_to_file(&self, path: &str) -> io::Result<()> {
@@ -30,6 +258,7 @@ impl DashboardConfig {
 
     pub fn default_config() -> Self {
         DashboardConfig {
+            version: CONFIG_VERSION,
             name: "grainx_advanced".to_string(),
             layout: vec![
                 "cpu_graph".to_string(), 
@@ -45,13 +274,314 @@ impl DashboardConfig {
             show_correlations: true,
             max_processes: 10,
             graph_history_size: 100,
+            forecast: ForecastConfig::default(),
+            exhaustion: ExhaustionConfig::default(),
+            custom_metrics: vec![
+                CustomMetricConfig {
+                    name: "cpu_avg_1m".to_string(),
+                    formula: "avg_over(cpu, 60s)".to_string(),
+                    unit: "%".to_string(),
+                    precision: 1,
+                    warning: Some(70.0),
+                    critical: Some(90.0),
+                    graph: true,
+                },
+                CustomMetricConfig {
+                    name: "net_total_kb".to_string(),
+                    formula: "(net_rx + net_tx) / 1024".to_string(),
+                    unit: "KB".to_string(),
+                    precision: 1,
+                    warning: None,
+                    critical: None,
+                    graph: true,
+                },
+            ],
+            alerts: vec![
+                AlertRuleConfig {
+                    name: "High CPU".to_string(),
+                    condition: "cpu > 90 for 30s".to_string(),
+                    severity: Severity::Critical,
+                    clear: Some(80.0),
+                },
+                AlertRuleConfig {
+                    name: "Memory pressure".to_string(),
+                    condition: "mem_pct > 85".to_string(),
+                    severity: Severity::Warning,
+                    clear: Some(80.0),
+                },
+                AlertRuleConfig {
+                    name: "Root disk full".to_string(),
+                    condition: "disk[\"/\"] > 95".to_string(),
+                    severity: Severity::Critical,
+                    clear: None,
+                },
+            ],
+            notifiers: Vec::new(),
+            learning: LearningConfig::default(),
+            patterns: PatternConfig::default(),
+            storage: StorageConfig::default(),
+            prometheus: PrometheusConfig::default(),
+            export: ExportConfig::default(),
+            push: Vec::new(),
+            otlp: OtlpConfig::default(),
+            network: NetworkConfig::default(),
+            api: ApiConfig::default(),
+            control: ControlConfig::default(),
+        }
+    }
+}
+
+/// Per-user directory for state kept between runs: `$XDG_DATA_HOME/grainx`,
+/// else `~/.local/share/grainx`, or `%LOCALAPPDATA%\grainx` on Windows.
+/// With none of them set there is no directory only this user can write
+/// to, so none is guessed.
+pub fn data_dir() -> io::Result<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    var("XDG_DATA_HOME")
+        .map(|dir| dir.join("grainx"))
+        .or_else(|| var("HOME").map(|home| home.join(".local/share/grainx")))
+        .or_else(|| var("LOCALAPPDATA").map(|dir| dir.join("grainx")))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no per-user data directory, set XDG_DATA_HOME or HOME, or learning.state_path and storage.path",
+            )
+        })
+}
+
+fn invalid_data(errors: Vec<ConfigError>) -> io::Error {
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    io::Error::new(io::ErrorKind::InvalidData, messages.join("; "))
+}
+
+/// `grainx config check`: whether the file at `path` loads, with every
+/// problem on its own `path:line:column: field: message` line.
+pub fn check_file(path: &str, out: &mut impl Write) -> io::Result<bool> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            writeln!(out, "{}: {}", path, e)?;
+            return Ok(false);
+        }
+    };
+    match DashboardConfig::parse(&text) {
+        Ok(_) => {
+            match versioned(&text) {
+                Some((version, _)) if version < CONFIG_VERSION => writeln!(out,
+                    "{}: ok, version {} (upgraded to version {} on the next start, keeping a backup)", path, version, CONFIG_VERSION)?,
+                _ => writeln!(out, "{}: ok, version {}", path, CONFIG_VERSION)?,
+            }
+            Ok(true)
+        }
+        Err(errors) => {
+            for error in errors {
+                let field = if error.field.is_empty() { String::new() } else { format!("{}: ", error.field) };
+                writeln!(out, "{}:{}:{}: {}{}", path, error.line, error.column, field, error.message)?;
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// The field a deserialization error is about, empty for the whole file.
+fn error_field(path: &serde_path_to_error::Path) -> String {
+    // `?` stands for a field the file broke off in
+    match path.to_string().trim_end_matches(".?") {
+        "." | "?" => String::new(),
+        field => field.to_string(),
+    }
+}
+
+/// The version of a config file and its fields, if it is a JSON object
+/// with no or a numeric `version`. Files from before versioning are 1.
+fn versioned(text: &str) -> Option<(u32, Map<String, Value>)> {
+    let Ok(Value::Object(fields)) = serde_json::from_str(text) else { return None };
+    let version = match fields.get("version") {
+        None => 1,
+        Some(version) => version.as_u64()?.try_into().ok()?,
+    };
+    Some((version, fields))
+}
+
+type Migration = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades the fields of a version `n + 1` file to
+/// version `n + 2`.
+const MIGRATIONS: [Migration; 1] = [migrate_v1];
+
+/// Version 1 files have no `version`, and a missing `custom_metrics` or
+/// `alerts` meant none rather than the built-in ones. Values are left as
+/// they are, so whatever the new validation rejects is reported against
+/// the original file.
+fn migrate_v1(fields: &mut Map<String, Value>) {
+    for list in ["custom_metrics", "alerts"] {
+        fields.entry(list).or_insert_with(|| Value::Array(Vec::new()));
+    }
+}
+
+/// Line and column (both from 1) of the value at `field`, a path such as
+/// `layout[2]` or `network.server.listen`, in JSON `text`.
+fn locate(text: &str, field: &str) -> Option<(usize, usize)> {
+    let mut path = Vec::new();
+    for part in field.split('.') {
+        let mut pieces = part.split('[');
+        path.push(PathSegment::Key(pieces.next()?.to_string()));
+        for index in pieces {
+            path.push(PathSegment::Index(index.strip_suffix(']')?.parse().ok()?));
+        }
+    }
+    let offset = JsonScanner { bytes: text.as_bytes(), pos: 0 }.find(&path)?;
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some((before.matches('\n').count() + 1, before[line_start..].chars().count() + 1))
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Just enough of a JSON reader to find where a value starts.
+struct JsonScanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonScanner<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        (self.bytes.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    /// Whether the next byte is `byte`, consuming it if so.
+    fn next_is(&mut self, byte: u8) -> bool {
+        self.expect(byte).is_some()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b'"')?;
+        let mut value = Vec::new();
+        loop {
+            match *self.bytes.get(self.pos)? {
+                b'"' => break,
+                b'\\' => {
+                    self.pos += 1;
+                    value.push(*self.bytes.get(self.pos)?);
+                }
+                byte => value.push(byte),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        String::from_utf8(value).ok()
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        match *self.bytes.get(self.pos)? {
+            b'"' => self.string().map(|_| ()),
+            open @ (b'{' | b'[') => {
+                self.pos += 1;
+                let close = if open == b'{' { b'}' } else { b']' };
+                if self.next_is(close) {
+                    return Some(());
+                }
+                loop {
+                    if open == b'{' {
+                        self.string()?;
+                        self.expect(b':')?;
+                    }
+                    self.skip_value()?;
+                    if self.next_is(close) {
+                        return Some(());
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            _ => {
+                while self.bytes.get(self.pos).is_some_and(|b| !b",]} \t\r\n".contains(b)) {
+                    self.pos += 1;
+                }
+                Some(())
+            }
+        }
+    }
+
+    /// Byte offset of the value at `path` below the current position.
+    fn find(&mut self, path: &[PathSegment]) -> Option<usize> {
+        self.skip_whitespace();
+        let Some((segment, rest)) = path.split_first() else {
+            return Some(self.pos);
+        };
+        match segment {
+            PathSegment::Key(key) => {
+                self.expect(b'{')?;
+                loop {
+                    if self.string()? == *key {
+                        self.expect(b':')?;
+                        return self.find(rest);
+                    }
+                    self.expect(b':')?;
+                    self.skip_value()?;
+                    self.expect(b',')?;
+                }
+            }
+            PathSegment::Index(index) => {
+                self.expect(b'[')?;
+                for _ in 0..*index {
+                    self.skip_value()?;
+                    self.expect(b',')?;
+                }
+                self.find(rest)
+            }
+        }
+    }
+}
+
+/// Notices changes to a config file by polling its modification time and
+/// size.
+pub struct ConfigWatcher {
+    path: String,
+    stamp: Option<(SystemTime, u64)>,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &str) -> Self {
+        ConfigWatcher { path: path.to_string(), stamp: Self::stamp(path), last_check: Instant::now() }
+    }
+
+    fn stamp(path: &str) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// The file loaded again if it changed since the last check. Checks at
+    /// most once per `CONFIG_POLL_INTERVAL`; a deleted file is not a change.
+    pub fn poll(&mut self, now: Instant) -> Option<io::Result<DashboardConfig>> {
+        if now.duration_since(self.last_check) < CONFIG_POLL_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+        let stamp = Self::stamp(&self.path);
+        if stamp.is_none() || stamp == self.stamp {
+            return None;
         }
+        self.stamp = stamp;
+        Some(DashboardConfig::load_from_file(&self.path))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::forecast::ForecastModel;
     use std::fs;
 
     #[test]
@@ -96,6 +626,84 @@ mod tests {
         assert!(!config.show_correlations);
     }
 
+    #[test]
+    fn test_config_forecast_model_selection() {
+        let json = r#"{
+            "name": "forecast_config",
+            "layout": ["cpu_graph"],
+            "refresh_interval_ms": 500,
+            "cpu_warning_threshold": 80.0,
+            "memory_warning_threshold": 85.0,
+            "show_predictions": true,
+            "show_correlations": false,
+            "max_processes": 5,
+            "graph_history_size": 50,
+            "forecast": { "model": "holt_winters", "horizon": 20 }
+        }"#;
+
+        let config: DashboardConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.forecast.model, ForecastModel::HoltWinters);
+        assert_eq!(config.forecast.horizon, 20);
+        assert_eq!(config.forecast.season_length, ForecastConfig::default().season_length);
+    }
+
+    #[test]
+    fn test_config_custom_metrics() {
+        let json = r#"{
+            "name": "custom",
+            "layout": ["cpu_graph"],
+            "refresh_interval_ms": 500,
+            "cpu_warning_threshold": 80.0,
+            "memory_warning_threshold": 85.0,
+            "show_predictions": false,
+            "show_correlations": false,
+            "max_processes": 5,
+            "graph_history_size": 50,
+            "custom_metrics": [
+                { "name": "mem_gb", "formula": "mem_used / 1073741824", "unit": "GB", "precision": 2, "warning": 12 }
+            ]
+        }"#;
+
+        let config: DashboardConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.custom_metrics.len(), 1);
+        assert_eq!(config.custom_metrics[0].precision, 2);
+        assert_eq!(config.custom_metrics[0].warning, Some(12.0));
+        assert!(!config.custom_metrics[0].graph);
+    }
+
+    #[test]
+    fn test_config_alert_rules() {
+        let json = r#"{
+            "name": "alerts",
+            "layout": ["cpu_graph"],
+            "refresh_interval_ms": 500,
+            "cpu_warning_threshold": 80.0,
+            "memory_warning_threshold": 85.0,
+            "show_predictions": false,
+            "show_correlations": false,
+            "max_processes": 5,
+            "graph_history_size": 50,
+            "alerts": [
+                { "condition": "cpu > 90 for 30s", "severity": "critical", "clear": 80 },
+                { "condition": "mem_pct > 85" }
+            ]
+        }"#;
+
+        let config: DashboardConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.alerts.len(), 2);
+        assert_eq!(config.alerts[0].severity, Severity::Critical);
+        assert_eq!(config.alerts[0].clear, Some(80.0));
+        assert_eq!(config.alerts[1].severity, Severity::Warning);
+        assert_eq!(config.alerts[1].display_name(), "mem_pct > 85");
+        assert!(config.validate().is_empty());
+
+        let errors = DashboardConfig::parse(&json.replace("\"clear\": 80", "\"clear\": 95")).unwrap_err();
+        assert_eq!(errors[0].to_string(), "alerts[0].clear: 95 is above the threshold 90 of 'cpu > 90 for 30s' at line 12 column 85");
+
+        let errors = DashboardConfig::parse(&json.replace("mem_pct > 85", "cpu > 90 for 30s")).unwrap_err();
+        assert_eq!(errors[0].to_string(), "alerts[1].condition: another rule is already named 'cpu > 90 for 30s' at line 13 column 32");
+    }
+
     #[test]
     fn test_config_file_operations() {
         let config = DashboardConfig::default_config();
@@ -113,6 +721,180 @@ mod tests {
         fs::remove_file(test_file).ok();
     }
 
+    #[test]
+    fn test_applied_to_keeps_running_services() {
+        let running = DashboardConfig::default_config();
+        let mut new = running.clone();
+        new.cpu_warning_threshold = 50.0;
+        new.alerts.clear();
+        let (applied, restart) = new.applied_to(&running);
+        assert!(restart.is_empty());
+        assert_eq!(applied.cpu_warning_threshold, 50.0);
+
+        new.api.listen = "0.0.0.0:9185".to_string();
+        new.storage.enabled = !running.storage.enabled;
+        let (applied, restart) = new.applied_to(&running);
+        assert_eq!(restart, ["storage", "api"]);
+        assert_eq!(applied.api.listen, running.api.listen);
+        assert!(applied.alerts.is_empty());
+        // Still reported by the next reload, until a restart
+        assert_eq!(new.applied_to(&applied).1, ["storage", "api"]);
+    }
+
+    #[test]
+    fn test_watcher_reports_changes() {
+        let path = std::env::temp_dir().join(format!("grainx-config-{}-watch.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut config = DashboardConfig::default_config();
+        config.save_to_file(path).unwrap();
+        let mut watcher = ConfigWatcher::new(path);
+        let mut now = Instant::now();
+        let mut tick = || {
+            now += CONFIG_POLL_INTERVAL;
+            now
+        };
+        assert!(watcher.poll(tick()).is_none());
+
+        config.max_processes = 3;
+        config.save_to_file(path).unwrap();
+        // Not checked again before the poll interval
+        assert!(watcher.poll(Instant::now()).is_none());
+        assert_eq!(watcher.poll(tick()).unwrap().unwrap().max_processes, 3);
+        assert!(watcher.poll(tick()).is_none());
+
+        fs::write(path, "{ \"name\": ").unwrap();
+        assert!(watcher.poll(tick()).unwrap().is_err());
+        fs::remove_file(path).ok();
+        assert!(watcher.poll(tick()).is_none());
+    }
+
+    #[test]
+    fn test_parse_reports_field_and_position() {
+        let text = "{\n  \"version\": 2,\n  \"name\": \"x\",\n  \"layout\": [\"cpu_graph\", \"gpu\"],\n  \"refresh_interval_ms\": 20,\n  \"cpu_warning_threshold\": 120.0,\n  \"memory_warning_threshold\": 85.0,\n  \"show_predictions\": true,\n  \"show_correlations\": true,\n  \"max_processes\": 10,\n  \"graph_history_size\": 100\n}";
+        let errors = DashboardConfig::parse(text).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, [
+            "cpu_warning_threshold: must be between 0 and 100, got 120 at line 6 column 28",
+            "refresh_interval_ms: must be at least 100, got 20 at line 5 column 26",
+            "layout[1]: unknown panel 'gpu', expected one of cpu_graph, memory_usage, network_stats, process_list, analytics at line 4 column 27",
+        ]);
+
+        let errors = DashboardConfig::parse(&text.replace("\"max_processes\": 10", "\"max_processes\": \"ten\"")).unwrap_err();
+        assert_eq!(errors[0].field, "max_processes");
+        assert_eq!((errors[0].line, errors[0].column), (10, 24));
+        assert!(errors[0].message.starts_with("invalid type: string \"ten\""));
+
+        let errors = DashboardConfig::parse("{\n  \"name\": \"x\",\n").unwrap_err();
+        assert_eq!(errors[0].field, "");
+        assert_eq!(errors[0].line, 3);
+    }
+
+    #[test]
+    fn test_locate_nested_fields() {
+        let text = r#"{"a": {"list": [1, {"x": "]"}, [2, 3]], "b": true}, "c": 5}"#;
+        assert_eq!(locate(text, "c"), Some((1, 58)));
+        assert_eq!(locate(text, "a.b"), Some((1, 46)));
+        assert_eq!(locate(text, "a.list[2]"), Some((1, 32)));
+        assert_eq!(locate(text, "a.list[3]"), None);
+        assert_eq!(locate(text, "d"), None);
+    }
+
+    const V1_CONFIG: &str = r#"{
+        "name": "old",
+        "layout": ["cpu_graph", "memory_usage"],
+        "refresh_interval_ms": 500,
+        "cpu_warning_threshold": 90.0,
+        "memory_warning_threshold": 85.0,
+        "show_predictions": true,
+        "show_correlations": true,
+        "max_processes": 5,
+        "graph_history_size": 100
+    }"#;
+
+    #[test]
+    fn test_missing_fields_take_defaults() {
+        let config = DashboardConfig::parse(r#"{ "version": 2, "max_processes": 3 }"#).unwrap();
+        let default = DashboardConfig::default_config();
+        assert_eq!(config.max_processes, 3);
+        assert_eq!(config.name, default.name);
+        assert_eq!(config.alerts, default.alerts);
+    }
+
+    #[test]
+    fn test_version_1_files_are_migrated() {
+        let config = DashboardConfig::parse(V1_CONFIG).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.layout, ["cpu_graph", "memory_usage"]);
+        assert_eq!(config.refresh_interval_ms, 500);
+        // Version 1 had no default alerts or custom metrics
+        assert!(config.alerts.is_empty() && config.custom_metrics.is_empty());
+
+        // Values the new validation rejects are reported, not changed
+        let errors = DashboardConfig::parse(&V1_CONFIG.replace("90.0", "150.0").replace("\"memory_usage\"", "\"gpu_graph\"")).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["cpu_warning_threshold", "layout[1]"]);
+
+        let errors = DashboardConfig::parse(&V1_CONFIG.replace("\"max_processes\": 5", "\"max_processes\": \"5\"")).unwrap_err();
+        assert_eq!((errors[0].field.as_str(), errors[0].line), ("max_processes", 9));
+    }
+
+    #[test]
+    fn test_unsupported_versions() {
+        let errors = DashboardConfig::parse("{\n  \"version\": 9\n}").unwrap_err();
+        assert_eq!(errors[0].to_string(), "version: unsupported version 9, this grainx reads versions 1 to 2 at line 2 column 14");
+        assert!(DashboardConfig::parse(r#"{ "version": 0 }"#).is_err());
+        assert_eq!(DashboardConfig::parse(r#"{ "version": "2" }"#).unwrap_err()[0].field, "version");
+    }
+
+    #[test]
+    fn test_upgrade_file_keeps_backup() {
+        let dir = std::env::temp_dir().join(format!("grainx-config-{}-upgrade", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dashboard_config.json");
+        let path = path.to_str().unwrap();
+        fs::write(path, V1_CONFIG).unwrap();
+
+        let (version, backup) = DashboardConfig::upgrade_file(path).unwrap().unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1_CONFIG);
+        let upgraded = fs::read_to_string(path).unwrap();
+        assert!(upgraded.contains("\"version\": 2"));
+        assert_eq!(DashboardConfig::parse(&upgraded).unwrap().max_processes, 5);
+        assert!(DashboardConfig::upgrade_file(path).unwrap().is_none());
+
+        // A second upgrade does not replace the first backup
+        fs::write(path, V1_CONFIG).unwrap();
+        let (_, second) = DashboardConfig::upgrade_file(path).unwrap().unwrap();
+        assert_ne!(second, backup);
+
+        // Nor is a broken or invalid file touched
+        fs::write(path, "{ \"max_processes\": -1 }").unwrap();
+        assert!(DashboardConfig::upgrade_file(path).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), "{ \"max_processes\": -1 }");
+        let invalid = V1_CONFIG.replace("\"refresh_interval_ms\": 500", "\"refresh_interval_ms\": 50");
+        fs::write(path, &invalid).unwrap();
+        assert!(DashboardConfig::upgrade_file(path).is_err());
+        assert_eq!(fs::read_to_string(path).unwrap(), invalid);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_check_file() {
+        let path = std::env::temp_dir().join(format!("grainx-config-{}-check.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut out = Vec::new();
+        fs::write(path, "{\n  \"version\": 2,\n  \"max_processes\": 3\n}").unwrap();
+        assert!(check_file(path, &mut out).unwrap());
+        fs::write(path, "{\n  \"version\": 2,\n  \"cpu_warning_threshold\": -5,\n  \"layout\": [\"cpu\"]\n}").unwrap();
+        assert!(!check_file(path, &mut out).unwrap());
+        let output = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], format!("{}: ok, version 2", path));
+        assert_eq!(lines[1], format!("{}:3:28: cpu_warning_threshold: must be between 0 and 100, got -5", path));
+        assert!(lines[2].starts_with(&format!("{}:4:14: layout[0]: unknown panel 'cpu'", path)));
+        fs::remove_file(path).ok();
+    }
+
     #[test]
     fn test_config_validation() {
         let config = DashboardConfig::default_config();
@@ -120,8 +902,9 @@ mod tests {
         // Test reasonable values
         assert!(config.cpu_warning_threshold >= 0.0 && config.cpu_warning_threshold <= 100.0);
         assert!(config.memory_warning_threshold >= 0.0 && config.memory_warning_threshold <= 100.0);
-        assert!(config.refresh_interval_ms >= 100); // At least 100ms
+        assert!(config.refresh_interval_ms >= 100);
         assert!(config.max_processes >= 1 && config.max_processes <= 50);
         assert!(config.graph_history_size >= 10 && config.graph_history_size <= 1000);
+        assert!(config.validate().is_empty());
     }
 }
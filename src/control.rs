@@ -0,0 +1,417 @@
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::sync::mpsc::Sender;
+#[cfg(unix)]
+use {
+    std::io::{BufRead, BufReader, Write},
+    std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+    std::os::unix::net::{UnixListener, UnixStream},
+    std::path::{Path, PathBuf},
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::mpsc::{self, Receiver},
+    std::sync::Arc,
+    std::thread,
+    std::time::Duration,
+};
+use crate::config::{DashboardConfig, MIN_REFRESH_INTERVAL_MS};
+use crate::performance::PerformanceMonitor;
+use crate::snapshot::Snapshot;
+use crate::ui::{DashboardState, ReloadStatus};
+
+/// How long a client waits for the dashboard loop to pick up its command.
+#[cfg(unix)]
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ControlConfig {
+    pub enabled: bool,
+    /// Socket path, `$XDG_RUNTIME_DIR/grainx.sock` when empty
+    pub socket: String,
+}
+
+/// The configured socket path, or the per-user default. Without either
+/// there is no directory only this user can write to, so none is guessed.
+#[cfg(unix)]
+pub fn socket_path(config: &ControlConfig) -> io::Result<PathBuf> {
+    if !config.socket.is_empty() {
+        return Ok(PathBuf::from(&config.socket));
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir).join("grainx.sock")),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set, set control.socket to a path only you can write to")),
+    }
+}
+
+/// One line of the control protocol, e.g. `{"command":"ack","rule":"High CPU"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Status,
+    Pause,
+    Resume,
+    /// Write the latest snapshot as JSON, to a timestamped file in the
+    /// dashboard's directory when no path is given
+    Snapshot {
+        #[serde(default)]
+        path: Option<String>,
+    },
+    /// Refresh every `interval_ms`, or adapt to the system load when absent
+    Refresh {
+        #[serde(default)]
+        interval_ms: Option<u64>,
+    },
+    Reload,
+    Ack { rule: String },
+    Query { metric: String },
+}
+
+/// The result of a command, sent back as `{"ok":true,"result":..}` or
+/// `{"ok":false,"error":".."}`.
+pub type Reply = Result<Value, String>;
+
+#[cfg(unix)]
+fn reply_json(reply: &Reply) -> Value {
+    match reply {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(error) => json!({ "ok": false, "error": error }),
+    }
+}
+
+/// A command waiting for the dashboard loop.
+pub struct Pending {
+    pub request: ControlRequest,
+    reply: Sender<Reply>,
+}
+
+impl Pending {
+    pub fn respond(self, reply: Reply) {
+        // The client may have given up waiting
+        self.reply.send(reply).ok();
+    }
+}
+
+/// Accepts line-delimited JSON commands on a Unix socket and queues them
+/// for the dashboard loop, which owns the state they act on.
+#[cfg(unix)]
+pub struct ControlServer {
+    path: PathBuf,
+    requests: Receiver<Pending>,
+    stop: Arc<AtomicBool>,
+}
+
+/// Bind `path` so that only this user can ever connect: the socket is made
+/// and restricted in a private directory next to `path`, then moved there.
+#[cfg(unix)]
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no socket file name"))?;
+    let staging = path.with_file_name(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+    fs::remove_dir_all(&staging).ok();
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let bound = (|| {
+        let staged = staging.join(name);
+        let listener = UnixListener::bind(&staged)?;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    })();
+    fs::remove_dir_all(&staging).ok();
+    bound
+}
+
+#[cfg(unix)]
+impl ControlServer {
+    pub fn start(config: &ControlConfig) -> io::Result<Self> {
+        let path = socket_path(config)?;
+        if let Ok(metadata) = fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+            }
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("another grainx is listening on {}", path.display())));
+            }
+            // Left behind by an instance that did not shut down cleanly
+            fs::remove_file(&path)?;
+        }
+        let listener = bind_private(&path)
+            .map_err(|e| io::Error::new(e.kind(), format!("cannot bind control socket {}: {}", path.display(), e)))?;
+
+        let (sender, requests) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let sender = sender.clone();
+                    thread::spawn(move || serve_client(stream, sender));
+                }
+            }
+        });
+        Ok(ControlServer { path, requests, stop })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Commands received since the last call.
+    pub fn pending(&self) -> Vec<Pending> {
+        self.requests.try_iter().collect()
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees the flag
+        UnixStream::connect(&self.path).ok();
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(unix)]
+fn serve_client(stream: UnixStream, requests: Sender<Pending>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => {
+                let (reply, answer) = mpsc::channel();
+                if requests.send(Pending { request, reply }).is_err() {
+                    return Ok(());
+                }
+                answer.recv_timeout(REPLY_TIMEOUT).unwrap_or_else(|_| Err("grainx did not answer in time".to_string()))
+            }
+            Err(e) => Err(format!("invalid command: {}", e)),
+        };
+        writeln!(writer, "{}", reply_json(&reply))?;
+    }
+    Ok(())
+}
+
+/// Send one command to the instance listening on `path` and wait for its reply.
+#[cfg(unix)]
+pub fn send(path: &Path, request: &ControlRequest) -> io::Result<Reply> {
+    let mut stream = UnixStream::connect(path).map_err(|e| io::Error::new(
+        e.kind(),
+        format!("cannot connect to {}: {} (is grainx running with control.enabled?)", path.display(), e),
+    ))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let reply: Value = serde_json::from_str(&line)?;
+    if reply["ok"] == true {
+        Ok(Ok(reply["result"].clone()))
+    } else {
+        Ok(Err(reply["error"].as_str().unwrap_or("no reply").to_string()))
+    }
+}
+
+/// Control sockets are Unix domain sockets, so there is nothing to listen on here.
+#[cfg(not(unix))]
+pub struct ControlServer(std::convert::Infallible);
+
+#[cfg(not(unix))]
+impl ControlServer {
+    pub fn start(_config: &ControlConfig) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "control socket is not supported on this platform"))
+    }
+
+    pub fn pending(&self) -> Vec<Pending> {
+        match self.0 {}
+    }
+}
+
+/// The parts of a running dashboard that commands act on.
+pub struct Controlled<'a> {
+    pub state: &'a mut DashboardState,
+    pub config: &'a mut DashboardConfig,
+    pub config_path: &'a str,
+    pub paused: &'a mut bool,
+    pub perf: &'a mut PerformanceMonitor,
+    pub snapshot: Option<&'a Snapshot>,
+}
+
+pub fn execute(request: &ControlRequest, target: &mut Controlled) -> Reply {
+    match request {
+        ControlRequest::Status => Ok(json!({
+            "config": target.config_path,
+            "name": target.config.name,
+            "host": target.snapshot.map(|snapshot| snapshot.hostname.as_str()),
+            "paused": *target.paused,
+            "refresh_ms": target.perf.refresh_interval(),
+            "firing": target.state.alerts.firing().iter().map(|rule| rule.config.display_name()).collect::<Vec<_>>(),
+        })),
+        ControlRequest::Pause | ControlRequest::Resume => {
+            *target.paused = *request == ControlRequest::Pause;
+            Ok(json!({ "paused": *target.paused }))
+        }
+        ControlRequest::Snapshot { path } => {
+            let snapshot = target.snapshot.ok_or("no snapshot taken yet")?;
+            let path = path.clone().unwrap_or_else(|| format!("grainx-snapshot-{}.json", snapshot.timestamp.format("%Y%m%d-%H%M%S")));
+            let json = serde_json::to_string_pretty(snapshot).map_err(|e| e.to_string())?;
+            fs::write(&path, json).map_err(|e| format!("cannot write {}: {}", path, e))?;
+            let path = fs::canonicalize(&path).map(|path| path.display().to_string()).unwrap_or(path);
+            Ok(json!({ "path": path }))
+        }
+        ControlRequest::Refresh { interval_ms } => {
+            if let Some(interval_ms) = interval_ms.filter(|ms| *ms < MIN_REFRESH_INTERVAL_MS) {
+                return Err(format!("refresh interval {}ms is below the minimum of {}ms", interval_ms, MIN_REFRESH_INTERVAL_MS));
+            }
+            target.perf.set_refresh_interval(*interval_ms);
+            Ok(json!({ "refresh_ms": target.perf.refresh_interval() }))
+        }
+        ControlRequest::Reload => {
+            let loaded = DashboardConfig::load_from_file(target.config_path).map_err(|e| format!("{}: {}", target.config_path, e));
+            match target.state.reload(target.config, loaded, target.perf) {
+                ReloadStatus::Applied { restart_required, .. } => Ok(json!({ "restart_required": restart_required })),
+                ReloadStatus::Failed { error, .. } => Err(error.clone()),
+            }
+        }
+        ControlRequest::Ack { rule } => {
+            target.state.alerts.acknowledge(rule)?;
+            Ok(json!({ "acknowledged": rule }))
+        }
+        ControlRequest::Query { metric } => {
+            let (timestamp, value) = target.state.metrics.latest(metric).ok_or_else(|| format!("no metric '{}'", metric))?;
+            Ok(json!({ "metric": metric, "value": value, "timestamp": timestamp }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(test: &str, name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("grainx-control-{}-{}-{}", std::process::id(), test, name))
+    }
+
+    #[test]
+    fn test_request_lines() {
+        let request: ControlRequest = serde_json::from_str(r#"{"command":"ack","rule":"High CPU"}"#).unwrap();
+        assert_eq!(request, ControlRequest::Ack { rule: "High CPU".to_string() });
+        let request: ControlRequest = serde_json::from_str(r#"{"command":"refresh"}"#).unwrap();
+        assert_eq!(request, ControlRequest::Refresh { interval_ms: None });
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command":"reboot"}"#).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_commands_round_trip_through_socket() {
+        let config = ControlConfig { enabled: true, socket: temp_path("socket", "ctl.sock").display().to_string() };
+        let server = ControlServer::start(&config).unwrap();
+        assert_eq!(ControlServer::start(&config).err().unwrap().kind(), io::ErrorKind::AddrInUse);
+
+        let path = server.path().to_path_buf();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let staged = path.with_file_name(format!(".{}.{}", path.file_name().unwrap().to_string_lossy(), std::process::id()));
+        assert!(!staged.exists());
+        let client = thread::spawn(move || {
+            let paused = send(&path, &ControlRequest::Pause).unwrap();
+            let query = send(&path, &ControlRequest::Query { metric: "swap".to_string() }).unwrap();
+            (paused, query)
+        });
+        let mut answered = 0;
+        while answered < 2 {
+            for pending in server.pending() {
+                let reply = match &pending.request {
+                    ControlRequest::Pause => Ok(json!({ "paused": true })),
+                    _ => Err("no metric 'swap'".to_string()),
+                };
+                pending.respond(reply);
+                answered += 1;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let (paused, query) = client.join().unwrap();
+        assert_eq!(paused.unwrap()["paused"], true);
+        assert_eq!(query.unwrap_err(), "no metric 'swap'");
+
+        let path = server.path().to_path_buf();
+        drop(server);
+        assert!(!path.exists());
+        assert!(send(&path, &ControlRequest::Status).unwrap_err().to_string().contains("control.enabled"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_socket_path() {
+        let config = ControlConfig { enabled: true, socket: "/run/x.sock".to_string() };
+        assert_eq!(socket_path(&config).unwrap(), PathBuf::from("/run/x.sock"));
+        // Only ever derived from the runtime directory
+        let default = socket_path(&ControlConfig::default());
+        match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            Some(dir) => assert_eq!(default.unwrap(), PathBuf::from(dir).join("grainx.sock")),
+            None => assert!(default.unwrap_err().to_string().contains("control.socket")),
+        }
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn test_unsupported_platform() {
+        let error = ControlServer::start(&ControlConfig { enabled: true, ..ControlConfig::default() }).err().unwrap();
+        assert_eq!(error.to_string(), "control socket is not supported on this platform");
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut config = DashboardConfig::default_config();
+        config.storage.enabled = false;
+        config.learning.enabled = false;
+        let config_path = temp_path("execute", "config.json");
+        let mut state = DashboardState::new(&config).unwrap();
+        let mut snapshot = crate::monitor::SystemMonitor::new().snapshot();
+        snapshot.cpu_usage = 99.0;
+        state.update(&snapshot);
+        let (mut paused, mut perf) = (false, PerformanceMonitor::new(60.0));
+        let config_path_text = config_path.display().to_string();
+        let mut target = Controlled {
+            state: &mut state,
+            config: &mut config,
+            config_path: &config_path_text,
+            paused: &mut paused,
+            perf: &mut perf,
+            snapshot: Some(&snapshot),
+        };
+
+        execute(&ControlRequest::Pause, &mut target).unwrap();
+        assert!(*target.paused);
+        assert_eq!(execute(&ControlRequest::Query { metric: "cpu".to_string() }, &mut target).unwrap()["value"], 99.0);
+        assert!(execute(&ControlRequest::Refresh { interval_ms: Some(10) }, &mut target).is_err());
+        assert_eq!(execute(&ControlRequest::Refresh { interval_ms: Some(2000) }, &mut target).unwrap()["refresh_ms"], 2000);
+
+        let snapshot_path = temp_path("execute", "snapshot.json");
+        execute(&ControlRequest::Snapshot { path: Some(snapshot_path.display().to_string()) }, &mut target).unwrap();
+        let saved: Snapshot = serde_json::from_str(&fs::read_to_string(&snapshot_path).unwrap()).unwrap();
+        assert_eq!(saved.cpu_usage, 99.0);
+
+        // A broken file leaves the running config alone
+        fs::write(&config_path, "{ not json").unwrap();
+        assert!(execute(&ControlRequest::Reload, &mut target).is_err());
+        assert!(matches!(target.state.reload_status, Some(ReloadStatus::Failed { .. })));
+        let mut changed = target.config.clone();
+        changed.cpu_warning_threshold = 42.0;
+        changed.refresh_interval_ms = 750;
+        changed.api.enabled = true;
+        changed.save_to_file(&config_path_text).unwrap();
+        let reply = execute(&ControlRequest::Reload, &mut target).unwrap();
+        assert_eq!(target.config.cpu_warning_threshold, 42.0);
+        assert_eq!(target.perf.refresh_interval(), Some(750));
+        assert_eq!(reply["restart_required"], json!(["api"]));
+
+        for path in [config_path, snapshot_path] {
+            fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::expr::{Expr, ExprError, MetricContext};
+use crate::history::MetricHistory;
+
+/// A named metric computed from a formula over the collected metrics.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CustomMetricConfig {
+    /// Name the value is recorded under, so other formulas, alerts and exports can use it
+    pub name: String,
+    pub formula: String,
+    #[serde(default)]
+    pub unit: String,
+    /// Decimal places shown in the dashboard
+    #[serde(default = "default_precision")]
+    pub precision: usize,
+    #[serde(default)]
+    pub warning: Option<f64>,
+    #[serde(default)]
+    pub critical: Option<f64>,
+    /// Draw a sparkline of the metric's history
+    #[serde(default)]
+    pub graph: bool,
+}
+
+fn default_precision() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThresholdLevel {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl CustomMetricConfig {
+    /// Where `value` sits against the thresholds. A critical threshold below the
+    /// warning one means low values are the bad ones (e.g. free space).
+    pub fn level(&self, value: f64) -> ThresholdLevel {
+        let descending = matches!((self.warning, self.critical), (Some(w), Some(c)) if c < w);
+        let breached = |threshold: Option<f64>| match threshold {
+            Some(t) if descending => value <= t,
+            Some(t) => value >= t,
+            None => false,
+        };
+        if breached(self.critical) {
+            ThresholdLevel::Critical
+        } else if breached(self.warning) {
+            ThresholdLevel::Warning
+        } else {
+            ThresholdLevel::Normal
+        }
+    }
+
+    pub fn format_value(&self, value: f64) -> String {
+        if self.unit.is_empty() {
+            format!("{:.*}", self.precision, value)
+        } else {
+            format!("{:.*}{}", self.precision, value, self.unit)
+        }
+    }
+}
+
+pub struct CustomMetric {
+    pub config: CustomMetricConfig,
+    /// The compiled formula, or why it did not parse
+    pub expr: Result<Expr, ExprError>,
+}
+
+/// The result of evaluating one custom metric for a tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomMetricValue {
+    pub name: String,
+    pub value: Result<f64, ExprError>,
+    pub level: ThresholdLevel,
+}
+
+/// Custom metrics from the config, compiled once and evaluated every tick.
+pub struct CustomMetrics {
+    pub metrics: Vec<CustomMetric>,
+}
+
+impl CustomMetrics {
+    pub fn compile(configs: &[CustomMetricConfig]) -> Self {
+        let metrics = configs.iter()
+            .map(|config| CustomMetric { config: config.clone(), expr: Expr::parse(&config.formula) })
+            .collect();
+        CustomMetrics { metrics }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CustomMetric> {
+        self.metrics.iter().find(|metric| metric.config.name == name)
+    }
+
+    /// Evaluate every metric in declaration order.
+    ///
+    /// Each successful value is added to `current` as it is computed, so later
+    /// custom metrics can build on earlier ones.
+    pub fn evaluate(&self, current: &mut BTreeMap<String, f64>, history: &MetricHistory) -> Vec<CustomMetricValue> {
+        let mut values = Vec::with_capacity(self.metrics.len());
+        for metric in &self.metrics {
+            let value = match &metric.expr {
+                Ok(expr) => expr.eval(&MetricContext { current: &*current, history }),
+                Err(error) => Err(error.clone()),
+            };
+            let level = value.as_ref().map(|v| metric.config.level(*v)).unwrap_or(ThresholdLevel::Normal);
+            if let Ok(v) = &value {
+                current.insert(metric.config.name.clone(), *v);
+            }
+            values.push(CustomMetricValue { name: metric.config.name.clone(), value, level });
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(name: &str, formula: &str) -> CustomMetricConfig {
+        CustomMetricConfig {
+            name: name.to_string(),
+            formula: formula.to_string(),
+            unit: String::new(),
+            precision: 1,
+            warning: None,
+            critical: None,
+            graph: false,
+        }
+    }
+
+    #[test]
+    fn test_metrics_build_on_each_other() {
+        let custom = CustomMetrics::compile(&[
+            metric("net_total", "net_rx + net_tx"),
+            metric("net_kb", "net_total / 1024"),
+        ]);
+        let mut current = BTreeMap::from([("net_rx".to_string(), 1024.0), ("net_tx".to_string(), 3072.0)]);
+        let values = custom.evaluate(&mut current, &MetricHistory::new(10));
+        assert_eq!(values[1].value, Ok(4.0));
+        assert_eq!(current["net_total"], 4096.0);
+    }
+
+    #[test]
+    fn test_errors_are_reported_per_metric() {
+        let custom = CustomMetrics::compile(&[metric("broken", "cpu *"), metric("missing", "nope + 1"), metric("ok", "2")]);
+        let mut current = BTreeMap::new();
+        let values = custom.evaluate(&mut current, &MetricHistory::new(10));
+        assert!(matches!(values[0].value, Err(ExprError::UnexpectedEnd { .. })));
+        assert_eq!(values[1].value, Err(ExprError::UnknownMetric("nope".to_string())));
+        assert_eq!(values[2].value, Ok(2.0));
+    }
+
+    #[test]
+    fn test_threshold_levels() {
+        let mut high = metric("cpu_hot", "cpu");
+        high.warning = Some(70.0);
+        high.critical = Some(90.0);
+        assert_eq!(high.level(50.0), ThresholdLevel::Normal);
+        assert_eq!(high.level(75.0), ThresholdLevel::Warning);
+        assert_eq!(high.level(95.0), ThresholdLevel::Critical);
+
+        // Critical below warning: low values are bad
+        let mut low = metric("free", "100 - mem_pct");
+        low.warning = Some(20.0);
+        low.critical = Some(5.0);
+        assert_eq!(low.level(50.0), ThresholdLevel::Normal);
+        assert_eq!(low.level(10.0), ThresholdLevel::Warning);
+        assert_eq!(low.level(2.0), ThresholdLevel::Critical);
+    }
+
+    #[test]
+    fn test_format_value() {
+        let mut config = metric("mem_gb", "mem_used / 1073741824");
+        config.precision = 2;
+        config.unit = "GB".to_string();
+        assert_eq!(config.format_value(3.456), "3.46GB");
+    }
+
+    #[test]
+    fn test_config_defaults() {
+        let config: CustomMetricConfig = serde_json::from_str(r#"{ "name": "x", "formula": "cpu" }"#).unwrap();
+        assert_eq!(config.precision, 1);
+        assert!(config.unit.is_empty() && !config.graph && config.warning.is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use crate::alerts::Severity;
+use crate::exhaustion::format_duration;
+use crate::patterns::PatternKind;
+
+/// Something the monitor noticed that the user should hear about.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// CPU stayed above the bottleneck level for `readings` consecutive samples
+    Bottleneck { cpu: f32, readings: u32 },
+    /// A metric series shows a flapping, sawtooth or step pattern
+    Pattern { metric: String, pattern: PatternKind, period_secs: Option<f64>, amplitude: f64 },
+    /// A learned threshold moved
+    ThresholdAdapted { metric: String, from: f64, to: f64 },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+impl Event {
+    pub fn new(kind: EventKind) -> Self {
+        Event { timestamp: Utc::now(), kind }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            EventKind::Bottleneck { .. } => Severity::Critical,
+            EventKind::Pattern { .. } => Severity::Warning,
+            EventKind::ThresholdAdapted { .. } => Severity::Info,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            EventKind::Bottleneck { cpu, readings } =>
+                format!("Sustained high CPU ({:.1}%) for {} readings, possible CPU bottleneck", cpu, readings),
+            EventKind::Pattern { metric, pattern: PatternKind::Step, amplitude, .. } =>
+                format!("{} stepped {} by {:.1}", metric, if *amplitude < 0.0 { "down" } else { "up" }, amplitude.abs()),
+            EventKind::Pattern { metric, pattern, period_secs, amplitude } => {
+                let period = std::time::Duration::from_secs_f64(period_secs.unwrap_or(0.0).max(0.0));
+                format!("{} {} every {}, amplitude {:.1}", metric, pattern.label(), format_duration(period), amplitude)
+            }
+            EventKind::ThresholdAdapted { metric, from, to } =>
+                format!("Learned {} threshold moved {:.1} -> {:.1}", metric, from, to),
+        }
+    }
+}
+
+/// Sending half of the event channel, cheap to clone into every producer.
+#[derive(Clone)]
+pub struct EventSender(mpsc::Sender<Event>);
+
+impl EventSender {
+    pub fn emit(&self, kind: EventKind) {
+        // Nobody listening is not an error for a producer
+        let _ = self.0.send(Event::new(kind));
+    }
+}
+
+pub struct EventReceiver(mpsc::Receiver<Event>);
+
+impl EventReceiver {
+    /// Every event emitted since the last call, oldest first.
+    pub fn drain(&self) -> Vec<Event> {
+        self.0.try_iter().collect()
+    }
+}
+
+pub fn channel() -> (EventSender, EventReceiver) {
+    let (sender, receiver) = mpsc::channel();
+    (EventSender(sender), EventReceiver(receiver))
+}
+
+/// The most recent events, for display.
+pub struct EventLog {
+    capacity: usize,
+    events: VecDeque<Event>,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        EventLog { capacity: capacity.max(1), events: VecDeque::new() }
+    }
+
+    pub fn extend(&mut self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            self.events.push_front(event);
+        }
+        self.events.truncate(self.capacity);
+    }
+
+    /// Newest first.
+    pub fn recent(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_delivers_in_order() {
+        let (sender, receiver) = channel();
+        let producer = sender.clone();
+        producer.emit(EventKind::Pattern { metric: "cpu".to_string(), pattern: PatternKind::Flapping, period_secs: Some(6.0), amplitude: 50.0 });
+        sender.emit(EventKind::Bottleneck { cpu: 97.0, readings: 5 });
+
+        let events = receiver.drain();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0].kind, EventKind::Pattern { .. }));
+        assert_eq!(events[1].severity(), Severity::Critical);
+        assert!(receiver.drain().is_empty());
+    }
+
+    #[test]
+    fn test_emit_without_receiver_is_harmless() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+        sender.emit(EventKind::Bottleneck { cpu: 95.0, readings: 5 });
+    }
+
+    #[test]
+    fn test_event_log_keeps_newest() {
+        let mut log = EventLog::new(2);
+        log.extend((0..3).map(|i| Event::new(EventKind::ThresholdAdapted {
+            metric: "cpu".to_string(),
+            from: i as f64,
+            to: i as f64 + 5.0,
+        })));
+        let recent: Vec<String> = log.recent().map(|event| event.describe()).collect();
+        assert_eq!(recent, vec!["Learned cpu threshold moved 2.0 -> 7.0", "Learned cpu threshold moved 1.0 -> 6.0"]);
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = Event::new(EventKind::Bottleneck { cpu: 95.0, readings: 5 });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "bottleneck");
+        assert_eq!(json["readings"], 5);
+    }
+
+    #[test]
+    fn test_describe_patterns() {
+        let pattern = |pattern, period_secs, amplitude| Event::new(EventKind::Pattern { metric: "cpu".to_string(), pattern, period_secs, amplitude });
+        assert_eq!(pattern(PatternKind::Flapping, Some(6.0), 50.0).describe(), "cpu flapping every 6s, amplitude 50.0");
+        assert_eq!(pattern(PatternKind::Sawtooth, Some(150.0), 20.0).describe(), "cpu sawtooth every 2m, amplitude 20.0");
+        assert_eq!(pattern(PatternKind::Step, None, -30.0).describe(), "cpu stepped down by 30.0");
+    }
+}
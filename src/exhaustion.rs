@@ -0,0 +1,257 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+use crate::forecast::fit_linear;
+use crate::snapshot::{keyed_metric, Snapshot};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExhaustionConfig {
+    /// Usage percentage treated as "full"
+    pub limit_pct: f64,
+    /// How much recent history the growth trend is fitted over
+    pub window_secs: u64,
+    /// Minimum samples in the window before an estimate is made
+    pub min_samples: usize,
+    /// Estimates closer than this are highlighted
+    pub alert_within_secs: u64,
+}
+
+impl Default for ExhaustionConfig {
+    fn default() -> Self {
+        ExhaustionConfig {
+            limit_pct: 95.0,
+            window_secs: 600,
+            min_samples: 10,
+            alert_within_secs: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Memory,
+    Swap,
+    Disk(String),
+}
+
+impl Resource {
+    pub fn label(&self) -> String {
+        match self {
+            Resource::Memory => "RAM".to_string(),
+            Resource::Swap => "swap".to_string(),
+            Resource::Disk(mount) => format!("disk {}", mount),
+        }
+    }
+
+    /// Metric carrying the estimate's seconds to exhaustion, e.g. `disk_full_in_s[/var]`.
+    pub fn metric_name(&self) -> String {
+        match self {
+            Resource::Memory => "mem_full_in_s".to_string(),
+            Resource::Swap => "swap_full_in_s".to_string(),
+            Resource::Disk(mount) => keyed_metric("disk_full_in_s", mount),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExhaustionEstimate {
+    pub resource: Resource,
+    pub current_pct: f64,
+    /// Fitted growth in percentage points per second
+    pub growth_per_sec: f64,
+    /// Time until `limit_pct` is reached at the current growth rate
+    pub eta: Duration,
+}
+
+impl ExhaustionEstimate {
+    pub fn is_within(&self, horizon: Duration) -> bool {
+        self.eta <= horizon
+    }
+
+    /// Human readable summary, e.g. "disk /var fills in 3h 20m".
+    pub fn describe(&self) -> String {
+        let verb = match self.resource {
+            Resource::Disk(_) => "fills",
+            _ => "exhausted",
+        };
+        if self.eta.is_zero() {
+            format!("{} {} now", self.resource.label(), verb)
+        } else {
+            format!("{} {} in {}", self.resource.label(), verb, format_duration(self.eta))
+        }
+    }
+}
+
+/// Format a duration with its two most significant units ("3h 20m", "40m", "2d 4h").
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86_400, (secs % 86_400) / 3600, (secs % 3600) / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Fits growth trends to memory, swap and per-mount disk usage and estimates
+/// how long until each one reaches the configured limit.
+pub struct ExhaustionTracker {
+    pub config: ExhaustionConfig,
+    series: BTreeMap<Resource, VecDeque<(DateTime<Utc>, f64)>>,
+}
+
+impl ExhaustionTracker {
+    pub fn new(config: ExhaustionConfig) -> Self {
+        ExhaustionTracker { config, series: BTreeMap::new() }
+    }
+
+    pub fn record(&mut self, snapshot: &Snapshot) {
+        let timestamp = snapshot.timestamp;
+        self.record_sample(Resource::Memory, timestamp, snapshot.memory_percentage());
+        if let Some(swap) = snapshot.swap_percentage() {
+            self.record_sample(Resource::Swap, timestamp, swap);
+        }
+        for disk in snapshot.disks.iter().filter(|d| d.total > 0) {
+            self.record_sample(Resource::Disk(disk.mount_point.clone()), timestamp, disk.used_percentage());
+        }
+    }
+
+    pub fn record_sample(&mut self, resource: Resource, timestamp: DateTime<Utc>, used_pct: f64) {
+        let window = chrono::Duration::seconds(self.config.window_secs as i64);
+        let samples = self.series.entry(resource).or_default();
+        samples.push_back((timestamp, used_pct));
+        while let Some((oldest, _)) = samples.front() {
+            if timestamp - *oldest > window {
+                samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    pub fn estimate(&self, resource: &Resource) -> Option<ExhaustionEstimate> {
+        let samples = self.series.get(resource)?;
+        if samples.len() < self.config.min_samples.max(2) {
+            return None;
+        }
+
+        let start = samples.front()?.0;
+        let xs: Vec<f64> = samples.iter()
+            .map(|(t, _)| (*t - start).num_milliseconds() as f64 / 1000.0)
+            .collect();
+        let ys: Vec<f64> = samples.iter().map(|(_, v)| *v).collect();
+        let current_pct = *ys.last()?;
+        let fit = fit_linear(&xs, &ys);
+        let growth_per_sec = fit.map(|fit| fit.slope).unwrap_or(0.0);
+
+        let eta = if current_pct >= self.config.limit_pct {
+            Duration::ZERO
+        } else {
+            let fit = fit?;
+            if fit.slope <= f64::EPSILON {
+                return None; // Flat or shrinking, never exhausts
+            }
+            let now = fit.predict(*xs.last()?);
+            let remaining = ((self.config.limit_pct - now) / fit.slope).max(0.0);
+            Duration::from_secs(remaining.round() as u64)
+        };
+
+        Some(ExhaustionEstimate { resource: resource.clone(), current_pct, growth_per_sec, eta })
+    }
+
+    /// All resources that are on course to run out, soonest first.
+    pub fn estimates(&self) -> Vec<ExhaustionEstimate> {
+        let mut estimates: Vec<ExhaustionEstimate> = self.series.keys()
+            .filter_map(|resource| self.estimate(resource))
+            .collect();
+        estimates.sort_by_key(|e| e.eta);
+        estimates
+    }
+
+    /// Seconds until each resource with an estimate runs out, as metrics for
+    /// alert conditions. Resources that are not filling up have no metric.
+    pub fn metrics(&self) -> BTreeMap<String, f64> {
+        self.estimates().into_iter()
+            .map(|estimate| (estimate.resource.metric_name(), estimate.eta.as_secs_f64()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker_with(resource: Resource, values: &[f64], step_secs: i64) -> ExhaustionTracker {
+        let mut tracker = ExhaustionTracker::new(ExhaustionConfig {
+            window_secs: 3600,
+            min_samples: 3,
+            ..ExhaustionConfig::default()
+        });
+        let start = Utc::now();
+        for (i, value) in values.iter().enumerate() {
+            let timestamp = start + chrono::Duration::seconds(i as i64 * step_secs);
+            tracker.record_sample(resource.clone(), timestamp, *value);
+        }
+        tracker
+    }
+
+    #[test]
+    fn test_linear_growth_eta() {
+        // 1 percentage point per minute, currently at 55% -> 40 minutes to 95%
+        let values: Vec<f64> = (0..6).map(|i| 50.0 + i as f64).collect();
+        let tracker = tracker_with(Resource::Memory, &values, 60);
+        let estimate = tracker.estimate(&Resource::Memory).unwrap();
+        assert_eq!(estimate.eta.as_secs(), 40 * 60);
+        assert_eq!(estimate.describe(), "RAM exhausted in 40m");
+        assert_eq!(tracker.metrics(), BTreeMap::from([("mem_full_in_s".to_string(), 2400.0)]));
+    }
+
+    #[test]
+    fn test_disk_description() {
+        let values: Vec<f64> = (0..5).map(|i| 83.0 + i as f64 * 0.5).collect();
+        let tracker = tracker_with(Resource::Disk("/var".to_string()), &values, 600);
+        let estimate = tracker.estimates().pop().unwrap();
+        assert_eq!(estimate.describe(), "disk /var fills in 3h 20m");
+        assert_eq!(tracker.metrics()["disk_full_in_s[/var]"], 12_000.0);
+    }
+
+    #[test]
+    fn test_flat_or_shrinking_usage_has_no_estimate() {
+        let tracker = tracker_with(Resource::Swap, &[40.0, 40.0, 40.0, 40.0], 60);
+        assert!(tracker.estimate(&Resource::Swap).is_none());
+        let tracker = tracker_with(Resource::Swap, &[40.0, 39.0, 38.0, 37.0], 60);
+        assert!(tracker.estimate(&Resource::Swap).is_none());
+        assert!(tracker.metrics().is_empty());
+    }
+
+    #[test]
+    fn test_already_full() {
+        let tracker = tracker_with(Resource::Memory, &[90.0, 94.0, 96.0], 60);
+        let estimate = tracker.estimate(&Resource::Memory).unwrap();
+        assert!(estimate.eta.is_zero());
+        assert_eq!(estimate.describe(), "RAM exhausted now");
+    }
+
+    #[test]
+    fn test_window_drops_old_samples() {
+        let mut tracker = ExhaustionTracker::new(ExhaustionConfig { window_secs: 120, ..ExhaustionConfig::default() });
+        let start = Utc::now();
+        for i in 0..10 {
+            tracker.record_sample(Resource::Memory, start + chrono::Duration::seconds(i * 60), 50.0);
+        }
+        assert_eq!(tracker.series[&Resource::Memory].len(), 3);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(12_000)), "3h 20m");
+        assert_eq!(format_duration(Duration::from_secs(2 * 86_400 + 4 * 3600)), "2d 4h");
+    }
+}
@@ -0,0 +1,331 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub enabled: bool,
+    pub format: ExportFormat,
+    /// Directory the export files are written to
+    pub path: String,
+    /// `timestamp`, `host` or any metric name, e.g. `cpu`, `disk[/]` or a
+    /// custom metric. Empty exports every metric of the first sample in a file.
+    pub columns: Vec<String>,
+    /// Start a new file once the current one is this large, 0 to never
+    pub rotate_bytes: u64,
+    /// Start a new file once the current one covers this long, 0 to never
+    pub rotate_secs: u64,
+    pub gzip: bool,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            enabled: false,
+            format: ExportFormat::Csv,
+            path: "grainx_export".to_string(),
+            columns: ["timestamp", "host", "cpu", "mem_pct", "swap_pct", "net_rx", "net_tx", "disk_read", "disk_write", "load1"]
+                .iter().map(|c| c.to_string()).collect(),
+            rotate_bytes: 10 * 1024 * 1024,
+            rotate_secs: 86_400,
+            gzip: false,
+        }
+    }
+}
+
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Sink {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Sink::Plain(out) => out,
+            Sink::Gzip(out) => out,
+        }
+    }
+
+    fn file(&self) -> &File {
+        match self {
+            Sink::Plain(out) => out.get_ref(),
+            Sink::Gzip(out) => out.get_ref().get_ref(),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut out) => out.flush(),
+            Sink::Gzip(out) => out.finish()?.flush(),
+        }
+    }
+}
+
+struct OpenFile {
+    path: PathBuf,
+    sink: Sink,
+    opened: DateTime<Utc>,
+    columns: Vec<String>,
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Writes every sample as a CSV or JSON-lines row, rotating files by size
+/// and age. Uncompressed rows are flushed as they are written so the files
+/// can be read live; gzip files are complete once rotated or closed.
+pub struct FileExporter {
+    pub config: ExportConfig,
+    current: Option<OpenFile>,
+}
+
+impl FileExporter {
+    pub fn new(config: ExportConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.path)?;
+        Ok(FileExporter { config, current: None })
+    }
+
+    /// The file rows are currently written to, if any.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|file| file.path.as_path())
+    }
+
+    fn open(&self, timestamp: DateTime<Utc>, metrics: &BTreeMap<String, f64>) -> io::Result<OpenFile> {
+        let extension = match self.config.gzip {
+            true => format!("{}.gz", self.config.format.extension()),
+            false => self.config.format.extension().to_string(),
+        };
+        let stem = format!("grainx-{}", timestamp.format("%Y%m%d-%H%M%S"));
+        // Never append to or overwrite an earlier file
+        let mut path = Path::new(&self.config.path).join(format!("{}.{}", stem, extension));
+        let mut n = 1;
+        while path.exists() {
+            path = Path::new(&self.config.path).join(format!("{}-{}.{}", stem, n, extension));
+            n += 1;
+        }
+
+        let out = BufWriter::new(File::create_new(&path)?);
+        let sink = match self.config.gzip {
+            true => Sink::Gzip(GzEncoder::new(out, Compression::default())),
+            false => Sink::Plain(out),
+        };
+        let columns = if self.config.columns.is_empty() {
+            ["timestamp", "host"].iter().map(|c| c.to_string()).chain(metrics.keys().cloned()).collect()
+        } else {
+            self.config.columns.clone()
+        };
+        let mut file = OpenFile { path, sink, opened: timestamp, columns };
+        if self.config.format == ExportFormat::Csv {
+            let header: Vec<String> = file.columns.iter().map(|c| csv_field(c)).collect();
+            writeln!(file.sink.writer(), "{}", header.join(","))?;
+        }
+        Ok(file)
+    }
+
+    fn due_for_rotation(&self, file: &OpenFile, timestamp: DateTime<Utc>) -> io::Result<bool> {
+        let too_old = self.config.rotate_secs > 0 && (timestamp - file.opened).num_seconds() >= self.config.rotate_secs as i64;
+        let too_large = self.config.rotate_bytes > 0 && file.sink.file().metadata()?.len() >= self.config.rotate_bytes;
+        Ok(too_old || too_large)
+    }
+
+    /// Append one sample, starting a new file first when the current one is due.
+    pub fn write(&mut self, timestamp: DateTime<Utc>, host: &str, metrics: &BTreeMap<String, f64>) -> io::Result<()> {
+        if let Some(file) = &self.current && self.due_for_rotation(file, timestamp)? {
+            self.close()?;
+        }
+        if self.current.is_none() {
+            self.current = Some(self.open(timestamp, metrics)?);
+        }
+        let file = self.current.as_mut().expect("opened above");
+
+        let value = |column: &str| match column {
+            "timestamp" => Some(serde_json::Value::from(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true))),
+            "host" => Some(serde_json::Value::from(host)),
+            metric => metrics.get(metric).map(|v| serde_json::Value::from(*v)),
+        };
+        let line = match self.config.format {
+            ExportFormat::Csv => file.columns.iter()
+                .map(|column| match value(column) {
+                    Some(serde_json::Value::String(text)) => csv_field(&text),
+                    Some(number) => number.to_string(),
+                    None => String::new(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            ExportFormat::Jsonl => {
+                let row: serde_json::Map<String, serde_json::Value> = file.columns.iter()
+                    .map(|column| (column.clone(), value(column).unwrap_or(serde_json::Value::Null)))
+                    .collect();
+                serde_json::to_string(&row)?
+            }
+        };
+        writeln!(file.sink.writer(), "{}", line)?;
+        match &mut file.sink {
+            Sink::Plain(out) => out.flush(),
+            // A sync flush per row would ruin the compression
+            Sink::Gzip(_) => Ok(()),
+        }
+    }
+
+    /// Finish the current file; gzip files are only complete after this.
+    pub fn close(&mut self) -> io::Result<()> {
+        match self.current.take() {
+            Some(file) => file.sink.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for FileExporter {
+    fn drop(&mut self) {
+        self.close().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn temp_dir(test: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("grainx-export-{}-{}", std::process::id(), test));
+        fs::remove_dir_all(&dir).ok();
+        dir.display().to_string()
+    }
+
+    fn config(test: &str, format: ExportFormat) -> ExportConfig {
+        ExportConfig {
+            enabled: true,
+            format,
+            path: temp_dir(test),
+            columns: vec!["timestamp".to_string(), "host".to_string(), "cpu".to_string(), "disk[/]".to_string()],
+            rotate_bytes: 0,
+            rotate_secs: 0,
+            gzip: false,
+        }
+    }
+
+    fn metrics(cpu: f64) -> BTreeMap<String, f64> {
+        BTreeMap::from([("cpu".to_string(), cpu), ("mem_pct".to_string(), 50.0)])
+    }
+
+    fn files(dir: &str) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path()).collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        let config = config("csv", ExportFormat::Csv);
+        let mut exporter = FileExporter::new(config.clone()).unwrap();
+        exporter.write(at(0), "web,1", &metrics(12.5)).unwrap();
+        exporter.write(at(1), "web,1", &metrics(15.0)).unwrap();
+
+        // Readable while still open
+        let text = fs::read_to_string(exporter.current_path().unwrap()).unwrap();
+        assert_eq!(text, "timestamp,host,cpu,disk[/]\n\
+            2023-11-14T22:13:20.000Z,\"web,1\",12.5,\n\
+            2023-11-14T22:13:21.000Z,\"web,1\",15.0,\n");
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_jsonl_rows_with_all_columns() {
+        let mut config = config("jsonl", ExportFormat::Jsonl);
+        config.columns.clear();
+        let mut exporter = FileExporter::new(config.clone()).unwrap();
+        exporter.write(at(0), "web-1", &metrics(12.5)).unwrap();
+
+        let text = fs::read_to_string(exporter.current_path().unwrap()).unwrap();
+        let row: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(row["host"], "web-1");
+        assert_eq!(row["cpu"], 12.5);
+        assert_eq!(row["mem_pct"], 50.0);
+        assert!(exporter.current_path().unwrap().to_string_lossy().ends_with(".jsonl"));
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_rotation_by_time_and_size() {
+        let mut config = config("rotate", ExportFormat::Csv);
+        config.rotate_secs = 60;
+        let mut exporter = FileExporter::new(config.clone()).unwrap();
+        for s in 0..3 {
+            exporter.write(at(s * 30), "web-1", &metrics(s as f64)).unwrap();
+        }
+        exporter.close().unwrap();
+        let rotated = files(&config.path);
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(fs::read_to_string(&rotated[0]).unwrap().lines().count(), 3);
+        assert_eq!(fs::read_to_string(&rotated[1]).unwrap().lines().count(), 2);
+
+        fs::remove_dir_all(&config.path).ok();
+        config.rotate_secs = 0;
+        config.rotate_bytes = 100;
+        let mut exporter = FileExporter::new(config.clone()).unwrap();
+        // Same second each time: names must not collide
+        for _ in 0..6 {
+            exporter.write(at(0), "web-1", &metrics(1.0)).unwrap();
+        }
+        exporter.close().unwrap();
+        let rotated = files(&config.path);
+        assert!(rotated.len() >= 2);
+        assert!(rotated.iter().all(|path| fs::read_to_string(path).unwrap().starts_with("timestamp,")));
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_gzip() {
+        let mut config = config("gzip", ExportFormat::Csv);
+        config.gzip = true;
+        let mut exporter = FileExporter::new(config.clone()).unwrap();
+        for _ in 0..200 {
+            exporter.write(at(0), "web-1", &metrics(12.5)).unwrap();
+        }
+        let path = exporter.current_path().unwrap().to_path_buf();
+        exporter.close().unwrap();
+
+        assert!(path.to_string_lossy().ends_with(".csv.gz"));
+        let mut text = String::new();
+        GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut text).unwrap();
+        assert_eq!(text.lines().nth(1), Some("2023-11-14T22:13:20.000Z,web-1,12.5,"));
+        assert_eq!(text.lines().count(), 201);
+        // Rows are not flushed one by one, which would defeat the compression
+        assert!(fs::metadata(&path).unwrap().len() < text.len() as u64 / 20, "{} bytes", fs::metadata(&path).unwrap().len());
+        fs::remove_dir_all(&config.path).ok();
+    }
+}
@@ -0,0 +1,696 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Duration;
+use crate::history::MetricHistory;
+use crate::snapshot::keyed_metric;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    UnexpectedChar { position: usize, found: char },
+    UnexpectedToken { position: usize, found: String, expected: &'static str },
+    UnexpectedEnd { expected: &'static str },
+    InvalidNumber { position: usize, text: String },
+    UnknownFunction { position: usize, name: String },
+    WrongArity { function: String, expected: &'static str, found: usize },
+    UnknownMetric(String),
+    InsufficientHistory(String),
+    DivisionByZero,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::UnexpectedChar { position, found } =>
+                write!(f, "unexpected character '{}' at position {}", found, position),
+            ExprError::UnexpectedToken { position, found, expected } =>
+                write!(f, "expected {} at position {}, found '{}'", expected, position, found),
+            ExprError::UnexpectedEnd { expected } =>
+                write!(f, "expected {} but the formula ended", expected),
+            ExprError::InvalidNumber { position, text } =>
+                write!(f, "invalid number '{}' at position {}", text, position),
+            ExprError::UnknownFunction { position, name } =>
+                write!(f, "unknown function '{}' at position {}", name, position),
+            ExprError::WrongArity { function, expected, found } =>
+                write!(f, "{}() takes {} argument(s), got {}", function, expected, found),
+            ExprError::UnknownMetric(name) => write!(f, "unknown metric '{}'", name),
+            ExprError::InsufficientHistory(name) => write!(f, "not enough history for '{}'", name),
+            ExprError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Number(f64),
+    Duration(Duration),
+    Ident(String),
+    Str(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Compare(Comparison),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+    text: String,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, ExprError> {
+    let chars: Vec<(usize, char)> = input.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (position, c) = chars[i];
+        let single = |kind| Token { kind, position, text: c.to_string() };
+        match c {
+            c if c.is_whitespace() => { i += 1; }
+            '+' => { tokens.push(single(TokenKind::Plus)); i += 1; }
+            '-' => { tokens.push(single(TokenKind::Minus)); i += 1; }
+            '*' => { tokens.push(single(TokenKind::Star)); i += 1; }
+            '/' => { tokens.push(single(TokenKind::Slash)); i += 1; }
+            '(' => { tokens.push(single(TokenKind::LParen)); i += 1; }
+            ')' => { tokens.push(single(TokenKind::RParen)); i += 1; }
+            '[' => { tokens.push(single(TokenKind::LBracket)); i += 1; }
+            ']' => { tokens.push(single(TokenKind::RBracket)); i += 1; }
+            ',' => { tokens.push(single(TokenKind::Comma)); i += 1; }
+            '>' | '<' => {
+                let or_equal = chars.get(i + 1).is_some_and(|(_, next)| *next == '=');
+                let comparison = match (c, or_equal) {
+                    ('>', false) => Comparison::Greater,
+                    ('>', true) => Comparison::GreaterOrEqual,
+                    ('<', false) => Comparison::Less,
+                    _ => Comparison::LessOrEqual,
+                };
+                let text = if or_equal { format!("{}=", c) } else { c.to_string() };
+                i += text.len();
+                tokens.push(Token { kind: TokenKind::Compare(comparison), position, text });
+            }
+            '"' | '\'' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some((_, ch)) if *ch == quote => { i += 1; break; }
+                        Some((_, ch)) => { value.push(*ch); i += 1; }
+                        None => return Err(ExprError::UnexpectedEnd { expected: "closing quote" }),
+                    }
+                }
+                tokens.push(Token { kind: TokenKind::Str(value.clone()), position, text: value });
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_ascii_digit() || chars[i].1 == '.') {
+                    i += 1;
+                }
+                let number_text: String = chars[start..i].iter().map(|(_, ch)| ch).collect();
+                let value: f64 = number_text.parse()
+                    .map_err(|_| ExprError::InvalidNumber { position, text: number_text.clone() })?;
+
+                // A unit directly after the number makes it a duration, e.g. `60s` or `5m`
+                let unit_start = i;
+                while i < chars.len() && chars[i].1.is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let unit: String = chars[unit_start..i].iter().map(|(_, ch)| ch).collect();
+                let text = format!("{}{}", number_text, unit);
+                let seconds = match unit.as_str() {
+                    "" => None,
+                    "ms" => Some(value / 1000.0),
+                    "s" => Some(value),
+                    "m" => Some(value * 60.0),
+                    "h" => Some(value * 3600.0),
+                    "d" => Some(value * 86_400.0),
+                    _ => return Err(ExprError::InvalidNumber { position, text }),
+                };
+                let kind = match seconds {
+                    None => TokenKind::Number(value),
+                    // Too long for a Duration, e.g. `999999999999999d`
+                    Some(seconds) => match Duration::try_from_secs_f64(seconds) {
+                        Ok(duration) => TokenKind::Duration(duration),
+                        Err(_) => return Err(ExprError::InvalidNumber { position, text }),
+                    },
+                };
+                tokens.push(Token { kind, position, text });
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_' || chars[i].1 == '.') {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().map(|(_, ch)| ch).collect();
+                tokens.push(Token { kind: TokenKind::Ident(name.clone()), position, text: name });
+            }
+            other => return Err(ExprError::UnexpectedChar { position, found: other }),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Comparison {
+    pub fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Comparison::Greater => value > threshold,
+            Comparison::GreaterOrEqual => value >= threshold,
+            Comparison::Less => value < threshold,
+            Comparison::LessOrEqual => value <= threshold,
+        }
+    }
+
+    /// Whether the condition is about a value rising past the threshold.
+    pub fn is_upper_bound(self) -> bool {
+        matches!(self, Comparison::Greater | Comparison::GreaterOrEqual)
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Min,
+    Max,
+    Abs,
+}
+
+/// A compiled metric formula.
+///
+/// Parse once with `Expr::parse` and evaluate every tick against a `MetricSource`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// Flat metric name, keyed metrics already folded in (`disk["/"]` -> `disk[/]`)
+    Metric(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    /// `avg_over(metric, 60s)`: mean of the metric over the trailing window
+    AvgOver { metric: String, window: Duration },
+    /// `rate(metric)` / `rate(metric, 1m)`: per-second change over the window
+    /// (the last two samples when no window is given)
+    Rate { metric: String, window: Option<Duration> },
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn next(&mut self, expected: &'static str) -> Result<Token, ExprError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(ExprError::UnexpectedEnd { expected })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<(), ExprError> {
+        let token = self.next(expected)?;
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(unexpected(&token, expected))
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.term()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Plus) => BinaryOp::Add,
+                Some(TokenKind::Minus) => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(TokenKind::Star) => BinaryOp::Mul,
+                Some(TokenKind::Slash) => BinaryOp::Div,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    // unary := ('-' | '+') unary | primary
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        match self.peek() {
+            Some(TokenKind::Minus) => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            }
+            Some(TokenKind::Plus) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        let token = self.next("a number, metric or '('")?;
+        match token.kind {
+            TokenKind::Number(value) => Ok(Expr::Number(value)),
+            TokenKind::LParen => {
+                let inner = self.expr()?;
+                self.expect(TokenKind::RParen, "')'")?;
+                Ok(inner)
+            }
+            TokenKind::Ident(name) => {
+                if self.peek() == Some(&TokenKind::LParen) {
+                    self.pos += 1;
+                    self.call(name, token.position)
+                } else {
+                    Ok(Expr::Metric(self.metric_name(name)?))
+                }
+            }
+            _ => Err(unexpected(&token, "a number, metric or '('")),
+        }
+    }
+
+    /// Metric name with an optional `[key]` suffix.
+    fn metric_name(&mut self, family: String) -> Result<String, ExprError> {
+        if self.peek() != Some(&TokenKind::LBracket) {
+            return Ok(family);
+        }
+        self.pos += 1;
+        let key = self.next("a metric key")?;
+        let key_text = match key.kind {
+            TokenKind::Str(value) => value,
+            TokenKind::Ident(_) | TokenKind::Number(_) => key.text.clone(),
+            _ => return Err(unexpected(&key, "a metric key")),
+        };
+        self.expect(TokenKind::RBracket, "']'")?;
+        Ok(keyed_metric(&family, &key_text))
+    }
+
+    fn call(&mut self, name: String, position: usize) -> Result<Expr, ExprError> {
+        match name.as_str() {
+            "min" | "max" | "abs" => {
+                let mut args = Vec::new();
+                if self.peek() != Some(&TokenKind::RParen) {
+                    args.push(self.expr()?);
+                    while self.peek() == Some(&TokenKind::Comma) {
+                        self.pos += 1;
+                        args.push(self.expr()?);
+                    }
+                }
+                self.expect(TokenKind::RParen, "')'")?;
+
+                let (function, expected, arity_ok) = match name.as_str() {
+                    "min" => (Function::Min, "at least 1", !args.is_empty()),
+                    "max" => (Function::Max, "at least 1", !args.is_empty()),
+                    _ => (Function::Abs, "1", args.len() == 1),
+                };
+                if !arity_ok {
+                    return Err(ExprError::WrongArity { function: name, expected, found: args.len() });
+                }
+                Ok(Expr::Call(function, args))
+            }
+            "avg_over" | "rate" => {
+                let metric_token = self.next("a metric name")?;
+                let metric = match metric_token.kind {
+                    TokenKind::Ident(family) => self.metric_name(family)?,
+                    _ => return Err(unexpected(&metric_token, "a metric name")),
+                };
+
+                let mut window = None;
+                if self.peek() == Some(&TokenKind::Comma) {
+                    self.pos += 1;
+                    let duration = self.next("a duration such as 60s")?;
+                    match duration.kind {
+                        TokenKind::Duration(value) => window = Some(value),
+                        _ => return Err(unexpected(&duration, "a duration such as 60s")),
+                    }
+                }
+                self.expect(TokenKind::RParen, "')'")?;
+
+                if name == "rate" {
+                    Ok(Expr::Rate { metric, window })
+                } else {
+                    let window = window.ok_or(ExprError::WrongArity {
+                        function: name, expected: "2", found: 1,
+                    })?;
+                    Ok(Expr::AvgOver { metric, window })
+                }
+            }
+            _ => Err(ExprError::UnknownFunction { position, name }),
+        }
+    }
+}
+
+/// A threshold condition such as `cpu > 90 for 30s`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub expr: Expr,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// How long the comparison has to keep holding, zero when there is no `for` clause
+    pub hold: Duration,
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Condition, ExprError> {
+        let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+        let expr = parser.expr()?;
+
+        let token = parser.next("a comparison such as '>'")?;
+        let TokenKind::Compare(comparison) = token.kind else {
+            return Err(unexpected(&token, "a comparison such as '>'"));
+        };
+
+        let negative = parser.peek() == Some(&TokenKind::Minus);
+        if negative {
+            parser.pos += 1;
+        }
+        let token = parser.next("a threshold number")?;
+        let TokenKind::Number(threshold) = token.kind else {
+            return Err(unexpected(&token, "a threshold number"));
+        };
+        let threshold = if negative { -threshold } else { threshold };
+
+        let mut hold = Duration::ZERO;
+        if let Some(token) = parser.tokens.get(parser.pos).cloned() {
+            if token.kind != TokenKind::Ident("for".to_string()) {
+                return Err(unexpected(&token, "'for' or end of condition"));
+            }
+            parser.pos += 1;
+            let token = parser.next("a duration such as 30s")?;
+            let TokenKind::Duration(duration) = token.kind else {
+                return Err(unexpected(&token, "a duration such as 30s"));
+            };
+            hold = duration;
+        }
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(unexpected(token, "end of condition"));
+        }
+
+        Ok(Condition { expr, comparison, threshold, hold })
+    }
+}
+
+fn unexpected(token: &Token, expected: &'static str) -> ExprError {
+    ExprError::UnexpectedToken { position: token.position, found: token.text.clone(), expected }
+}
+
+/// Where formulas read metric values from.
+pub trait MetricSource {
+    /// Current value of a metric.
+    fn value(&self, name: &str) -> Option<f64>;
+
+    /// Samples of `name` in the trailing `window` (the last two when `None`), oldest first.
+    fn window(&self, _name: &str, _window: Option<Duration>) -> Vec<(DateTime<Utc>, f64)> {
+        Vec::new()
+    }
+}
+
+impl MetricSource for HashMap<&str, f64> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl MetricSource for BTreeMap<String, f64> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+/// Current metrics backed by their history, so `avg_over` and `rate` work.
+pub struct MetricContext<'a> {
+    pub current: &'a BTreeMap<String, f64>,
+    pub history: &'a MetricHistory,
+}
+
+impl MetricSource for MetricContext<'_> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.current.get(name).copied().or_else(|| self.history.latest(name).map(|(_, v)| v))
+    }
+
+    fn window(&self, name: &str, window: Option<Duration>) -> Vec<(DateTime<Utc>, f64)> {
+        let Some((latest, _)) = self.history.latest(name) else {
+            return Vec::new();
+        };
+        match window {
+            Some(window) => {
+                let span = chrono::Duration::from_std(window).unwrap_or(chrono::Duration::zero());
+                self.history.since(name, latest - span)
+            }
+            None => {
+                let samples = self.history.samples(name).map(|s| s.len()).unwrap_or(0);
+                self.history.samples(name)
+                    .map(|s| s.iter().skip(samples.saturating_sub(2)).copied().collect())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, ExprError> {
+        let mut parser = Parser { tokens: tokenize(input)?, pos: 0 };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(unexpected(token, "an operator or end of formula"));
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, source: &dyn MetricSource) -> Result<f64, ExprError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Metric(name) => source.value(name).ok_or_else(|| ExprError::UnknownMetric(name.clone())),
+            Expr::Neg(inner) => Ok(-inner.eval(source)?),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(source)?, right.eval(source)?);
+                match op {
+                    BinaryOp::Add => Ok(left + right),
+                    BinaryOp::Sub => Ok(left - right),
+                    BinaryOp::Mul => Ok(left * right),
+                    BinaryOp::Div if right == 0.0 => Err(ExprError::DivisionByZero),
+                    BinaryOp::Div => Ok(left / right),
+                }
+            }
+            Expr::Call(function, args) => {
+                let values = args.iter().map(|arg| arg.eval(source)).collect::<Result<Vec<f64>, _>>()?;
+                Ok(match function {
+                    Function::Min => values.into_iter().fold(f64::INFINITY, f64::min),
+                    Function::Max => values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                    Function::Abs => values[0].abs(),
+                })
+            }
+            Expr::AvgOver { metric, window } => {
+                let samples = source.window(metric, Some(*window));
+                if samples.is_empty() {
+                    return Err(ExprError::InsufficientHistory(metric.clone()));
+                }
+                Ok(samples.iter().map(|(_, v)| v).sum::<f64>() / samples.len() as f64)
+            }
+            Expr::Rate { metric, window } => {
+                let samples = source.window(metric, *window);
+                match (samples.first(), samples.last()) {
+                    (Some((t0, v0)), Some((t1, v1))) if t1 > t0 => {
+                        let seconds = (*t1 - *t0).num_milliseconds() as f64 / 1000.0;
+                        Ok((v1 - v0) / seconds)
+                    }
+                    _ => Err(ExprError::InsufficientHistory(metric.clone())),
+                }
+            }
+        }
+    }
+
+    /// Every metric name the formula reads.
+    pub fn metrics(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_metrics(&mut names);
+        names
+    }
+
+    fn collect_metrics<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Metric(name) | Expr::AvgOver { metric: name, .. } | Expr::Rate { metric: name, .. } => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Expr::Neg(inner) => inner.collect_metrics(names),
+            Expr::Binary(_, left, right) => {
+                left.collect_metrics(names);
+                right.collect_metrics(names);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.collect_metrics(names)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(values: &[(&'static str, f64)]) -> HashMap<&'static str, f64> {
+        values.iter().copied().collect()
+    }
+
+    fn eval(formula: &str, source: &dyn MetricSource) -> Result<f64, ExprError> {
+        Expr::parse(formula)?.eval(source)
+    }
+
+    #[test]
+    fn test_precedence_and_parentheses() {
+        let m = metrics(&[]);
+        assert_eq!(eval("2 + 3 * 4", &m).unwrap(), 14.0);
+        assert_eq!(eval("(2 + 3) * 4", &m).unwrap(), 20.0);
+        assert_eq!(eval("10 - 4 - 3", &m).unwrap(), 3.0);
+        assert_eq!(eval("8 / 4 / 2", &m).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        let m = metrics(&[("cpu", 30.0)]);
+        assert_eq!(eval("-cpu + 50", &m).unwrap(), 20.0);
+        assert_eq!(eval("2 * -3", &m).unwrap(), -6.0);
+        assert_eq!(eval("--4", &m).unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_metric_names_do_not_clobber_each_other() {
+        let m = metrics(&[("cpu_usage", 10.0), ("cpu_usage_avg", 20.0)]);
+        assert_eq!(eval("cpu_usage_avg - cpu_usage", &m).unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_functions() {
+        let m = metrics(&[("a", -3.0), ("b", 7.0)]);
+        assert_eq!(eval("min(a, b, 0)", &m).unwrap(), -3.0);
+        assert_eq!(eval("max(a, b)", &m).unwrap(), 7.0);
+        assert_eq!(eval("abs(a) * 2", &m).unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_keyed_metrics() {
+        let m = metrics(&[("disk[/]", 91.0), ("cpu_core[0]", 12.0)]);
+        assert_eq!(eval("disk[\"/\"] - cpu_core[0]", &m).unwrap(), 79.0);
+    }
+
+    #[test]
+    fn test_history_functions() {
+        let mut history = MetricHistory::new(100);
+        let start = Utc::now();
+        for i in 0..10 {
+            history.record("net_rx", start + chrono::Duration::seconds(i * 10), i as f64 * 100.0);
+        }
+        let current = BTreeMap::from([("net_rx".to_string(), 900.0)]);
+        let context = MetricContext { current: &current, history: &history };
+
+        // Samples at 60..90s -> 600, 700, 800, 900
+        assert_eq!(eval("avg_over(net_rx, 30s)", &context).unwrap(), 750.0);
+        assert_eq!(eval("rate(net_rx)", &context).unwrap(), 10.0);
+        assert_eq!(eval("rate(net_rx, 1m)", &context).unwrap(), 10.0);
+        assert_eq!(eval("rate(missing)", &context), Err(ExprError::InsufficientHistory("missing".to_string())));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Expr::parse("cpu *"), Err(ExprError::UnexpectedEnd { expected: "a number, metric or '('" }));
+        assert!(matches!(Expr::parse("cpu $ 2"), Err(ExprError::UnexpectedChar { position: 4, found: '$' })));
+        assert!(matches!(Expr::parse("(cpu + 1"), Err(ExprError::UnexpectedEnd { .. })));
+        assert!(matches!(Expr::parse("cpu 2"), Err(ExprError::UnexpectedToken { position: 4, .. })));
+        assert!(matches!(Expr::parse("median(cpu)"), Err(ExprError::UnknownFunction { .. })));
+        assert!(matches!(Expr::parse("abs(1, 2)"), Err(ExprError::WrongArity { .. })));
+        assert!(matches!(Expr::parse("avg_over(cpu)"), Err(ExprError::WrongArity { .. })));
+        assert!(matches!(Expr::parse("avg_over(cpu, 10)"), Err(ExprError::UnexpectedToken { .. })));
+        assert!(matches!(Expr::parse("1.2.3"), Err(ExprError::InvalidNumber { .. })));
+        assert!(matches!(Expr::parse("avg_over(cpu, 999999999999999999999d)"), Err(ExprError::InvalidNumber { .. })));
+        // 999999999999999 days overflow a Duration, ten times fewer do not
+        assert!(matches!(Expr::parse("avg_over(cpu, 999999999999999d)"), Err(ExprError::InvalidNumber { .. })));
+        assert!(Expr::parse("avg_over(cpu, 99999999999999d)").is_ok());
+
+        let m = metrics(&[]);
+        assert_eq!(eval("missing + 1", &m), Err(ExprError::UnknownMetric("missing".to_string())));
+        assert_eq!(eval("1 / 0", &m), Err(ExprError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_referenced_metrics() {
+        let expr = Expr::parse("cpu + max(mem_pct, cpu) + rate(net_rx)").unwrap();
+        assert_eq!(expr.metrics(), vec!["cpu", "mem_pct", "net_rx"]);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let condition = Condition::parse("cpu > 90 for 30s").unwrap();
+        assert_eq!(condition.expr, Expr::Metric("cpu".to_string()));
+        assert_eq!(condition.comparison, Comparison::Greater);
+        assert_eq!(condition.threshold, 90.0);
+        assert_eq!(condition.hold, Duration::from_secs(30));
+
+        let condition = Condition::parse("disk[\"/\"] >= 95").unwrap();
+        assert_eq!(condition.expr, Expr::Metric("disk[/]".to_string()));
+        assert_eq!(condition.hold, Duration::ZERO);
+
+        let condition = Condition::parse("rate(net_rx) <= -10").unwrap();
+        assert_eq!(condition.comparison, Comparison::LessOrEqual);
+        assert_eq!(condition.threshold, -10.0);
+
+        assert!(Condition::parse("cpu").is_err());
+        assert!(Condition::parse("cpu > mem_pct").is_err());
+        assert!(Condition::parse("cpu > 90 for").is_err());
+        assert!(Condition::parse("cpu > 90 during 30s").is_err());
+        assert!(matches!(Expr::parse("cpu > 90"), Err(ExprError::UnexpectedToken { position: 4, .. })));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        let error = Expr::parse("cpu +").unwrap_err();
+        assert_eq!(error.to_string(), "expected a number, metric or '(' but the formula ended");
+    }
+}
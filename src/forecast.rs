@@ -0,0 +1,329 @@
+use serde::{Serialize, Deserialize};
+
+/// Forecasting model used for the prediction readout and graph ribbons.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastModel {
+    /// Simple moving average over `window_size` samples (flat forecast)
+    MovingAverage,
+    /// Least-squares linear trend over the whole history
+    LinearRegression,
+    /// Holt's double exponential smoothing (level + trend)
+    Holt,
+    /// Additive Holt-Winters triple exponential smoothing (level + trend + season)
+    HoltWinters,
+}
+
+impl ForecastModel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ForecastModel::MovingAverage => "sma",
+            ForecastModel::LinearRegression => "linear",
+            ForecastModel::Holt => "holt",
+            ForecastModel::HoltWinters => "holt-winters",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ForecastConfig {
+    pub model: ForecastModel,
+    /// Number of steps to forecast ahead
+    pub horizon: usize,
+    /// Window used by the moving average model
+    pub window_size: usize,
+    /// Level smoothing factor (Holt, Holt-Winters)
+    pub alpha: f64,
+    /// Trend smoothing factor (Holt, Holt-Winters)
+    pub beta: f64,
+    /// Seasonal smoothing factor (Holt-Winters)
+    pub gamma: f64,
+    /// Samples per season (Holt-Winters)
+    pub season_length: usize,
+    /// Width of the prediction interval in standard deviations (1.96 ~ 95%)
+    pub interval_z: f64,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        ForecastConfig {
+            model: ForecastModel::Holt,
+            horizon: 10,
+            window_size: 5,
+            alpha: 0.5,
+            beta: 0.3,
+            gamma: 0.1,
+            season_length: 12,
+            interval_z: 1.96,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForecastPoint {
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ForecastPoint {
+    fn new(value: f64, spread: f64) -> Self {
+        ForecastPoint { value, lower: value - spread, upper: value + spread }
+    }
+
+    /// Clamp the point and its interval to `[min, max]`, e.g. 0-100 for percentages.
+    pub fn clamped(&self, min: f64, max: f64) -> Self {
+        ForecastPoint {
+            value: self.value.clamp(min, max),
+            lower: self.lower.clamp(min, max),
+            upper: self.upper.clamp(min, max),
+        }
+    }
+}
+
+/// Result of an ordinary least-squares fit of `y = slope * x + intercept`.
+#[derive(Debug, Clone, Copy)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// Standard error of the residuals
+    pub residual_std: f64,
+    pub n: usize,
+    pub mean_x: f64,
+    /// Sum of squared deviations of x from its mean
+    pub sxx: f64,
+}
+
+impl LinearFit {
+    pub fn predict(&self, x: f64) -> f64 {
+        self.slope * x + self.intercept
+    }
+
+    /// Half-width of the prediction interval for a new observation at `x`.
+    pub fn prediction_spread(&self, x: f64, z: f64) -> f64 {
+        let n = self.n as f64;
+        let leverage = if self.sxx > 0.0 { (x - self.mean_x).powi(2) / self.sxx } else { 0.0 };
+        z * self.residual_std * (1.0 + 1.0 / n + leverage).sqrt()
+    }
+}
+
+pub fn fit_linear(xs: &[f64], ys: &[f64]) -> Option<LinearFit> {
+    if xs.len() != ys.len() || xs.len() < 2 {
+        return None;
+    }
+
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+
+    let sxx: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let sxy: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+
+    let sse: f64 = xs.iter().zip(ys).map(|(x, y)| (y - (slope * x + intercept)).powi(2)).sum();
+    let residual_std = if xs.len() > 2 { (sse / (n - 2.0)).sqrt() } else { 0.0 };
+
+    Some(LinearFit { slope, intercept, residual_std, n: xs.len(), mean_x, sxx })
+}
+
+/// Forecast `config.horizon` steps past the end of `history` with the configured model.
+pub fn forecast(history: &[f64], config: &ForecastConfig) -> Option<Vec<ForecastPoint>> {
+    let z = config.interval_z;
+    match config.model {
+        ForecastModel::MovingAverage => moving_average(history, config.window_size, config.horizon, z),
+        ForecastModel::LinearRegression => linear_regression(history, config.horizon, z),
+        ForecastModel::Holt => holt(history, config.alpha, config.beta, config.horizon, z),
+        ForecastModel::HoltWinters => holt_winters(
+            history, config.alpha, config.beta, config.gamma, config.season_length, config.horizon, z,
+        ),
+    }
+}
+
+pub fn moving_average(history: &[f64], window_size: usize, horizon: usize, z: f64) -> Option<Vec<ForecastPoint>> {
+    if window_size == 0 || history.len() < window_size || horizon == 0 {
+        return None;
+    }
+
+    let window = &history[history.len() - window_size..];
+    let mean = window.iter().sum::<f64>() / window_size as f64;
+    let variance = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / window_size as f64;
+    let spread = z * (variance * (1.0 + 1.0 / window_size as f64)).sqrt();
+
+    Some(vec![ForecastPoint::new(mean, spread); horizon])
+}
+
+pub fn linear_regression(history: &[f64], horizon: usize, z: f64) -> Option<Vec<ForecastPoint>> {
+    if history.len() < 3 || horizon == 0 {
+        return None;
+    }
+
+    let xs: Vec<f64> = (0..history.len()).map(|i| i as f64).collect();
+    let fit = fit_linear(&xs, history)?;
+
+    Some((1..=horizon)
+        .map(|h| {
+            let x = (history.len() - 1 + h) as f64;
+            ForecastPoint::new(fit.predict(x), fit.prediction_spread(x, z))
+        })
+        .collect())
+}
+
+pub fn holt(history: &[f64], alpha: f64, beta: f64, horizon: usize, z: f64) -> Option<Vec<ForecastPoint>> {
+    if history.len() < 3 || horizon == 0 {
+        return None;
+    }
+
+    let mut level = history[0];
+    let mut trend = history[1] - history[0];
+    let mut sse = 0.0;
+
+    for &value in &history[1..] {
+        let predicted = level + trend;
+        sse += (value - predicted).powi(2);
+
+        let previous_level = level;
+        level = alpha * value + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous_level) + (1.0 - beta) * trend;
+    }
+
+    let sigma = (sse / (history.len() - 1) as f64).sqrt();
+
+    // Variance of the h-step error for additive-trend smoothing grows with
+    // the accumulated smoothing weights: sigma^2 * (1 + sum (alpha * (1 + j*beta))^2)
+    let mut weight_sum = 0.0;
+    Some((1..=horizon)
+        .map(|h| {
+            if h > 1 {
+                weight_sum += (alpha * (1.0 + (h - 1) as f64 * beta)).powi(2);
+            }
+            let spread = z * sigma * (1.0 + weight_sum).sqrt();
+            ForecastPoint::new(level + h as f64 * trend, spread)
+        })
+        .collect())
+}
+
+pub fn holt_winters(
+    history: &[f64],
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    season_length: usize,
+    horizon: usize,
+    z: f64,
+) -> Option<Vec<ForecastPoint>> {
+    let m = season_length;
+    if m < 2 || history.len() < 2 * m || horizon == 0 {
+        return None;
+    }
+
+    // Initialise from the first two seasons
+    let first_mean = history[..m].iter().sum::<f64>() / m as f64;
+    let second_mean = history[m..2 * m].iter().sum::<f64>() / m as f64;
+    let mut level = first_mean;
+    let mut trend = (second_mean - first_mean) / m as f64;
+    let mut seasonals: Vec<f64> = history[..m].iter().map(|v| v - first_mean).collect();
+
+    let mut sse = 0.0;
+    let mut residuals = 0;
+
+    for (i, &value) in history.iter().enumerate().skip(m) {
+        let season_index = i % m;
+        let predicted = level + trend + seasonals[season_index];
+        sse += (value - predicted).powi(2);
+        residuals += 1;
+
+        let previous_level = level;
+        level = alpha * (value - seasonals[season_index]) + (1.0 - alpha) * (level + trend);
+        trend = beta * (level - previous_level) + (1.0 - beta) * trend;
+        seasonals[season_index] = gamma * (value - level) + (1.0 - gamma) * seasonals[season_index];
+    }
+
+    let sigma = (sse / residuals as f64).sqrt();
+    let n = history.len();
+
+    let mut weight_sum = 0.0;
+    Some((1..=horizon)
+        .map(|h| {
+            if h > 1 {
+                let j = h - 1;
+                let seasonal_weight = if j % m == 0 { gamma * (1.0 - alpha) } else { 0.0 };
+                weight_sum += (alpha * (1.0 + j as f64 * beta) + seasonal_weight).powi(2);
+            }
+            let seasonal = seasonals[(n + h - 1) % m];
+            let spread = z * sigma * (1.0 + weight_sum).sqrt();
+            ForecastPoint::new(level + h as f64 * trend + seasonal, spread)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(model: ForecastModel) -> ForecastConfig {
+        ForecastConfig { model, horizon: 5, season_length: 4, ..ForecastConfig::default() }
+    }
+
+    #[test]
+    fn test_linear_regression_follows_trend() {
+        let history: Vec<f64> = (0..20).map(|i| 10.0 + 2.0 * i as f64).collect();
+        let points = forecast(&history, &config(ForecastModel::LinearRegression)).unwrap();
+        assert_eq!(points.len(), 5);
+        assert!((points[0].value - 50.0).abs() < 1e-9);
+        assert!((points[4].value - 58.0).abs() < 1e-9);
+        // Perfect fit has no residual spread
+        assert!((points[0].upper - points[0].lower).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_holt_tracks_trend_unlike_moving_average() {
+        let history: Vec<f64> = (0..30).map(|i| i as f64).collect();
+        let holt = forecast(&history, &config(ForecastModel::Holt)).unwrap();
+        let sma = forecast(&history, &config(ForecastModel::MovingAverage)).unwrap();
+        assert!((holt[0].value - 30.0).abs() < 0.5);
+        assert!(holt[4].value > holt[0].value);
+        assert!(sma[0].value < 29.0);
+        assert_eq!(sma[0].value, sma[4].value);
+    }
+
+    #[test]
+    fn test_holt_winters_reproduces_season() {
+        let pattern = [10.0, 20.0, 30.0, 20.0];
+        let history: Vec<f64> = (0..40).map(|i| pattern[i % 4]).collect();
+        let points = forecast(&history, &config(ForecastModel::HoltWinters)).unwrap();
+        for (h, point) in points.iter().enumerate() {
+            assert!((point.value - pattern[(40 + h) % 4]).abs() < 1.0, "step {} was {}", h, point.value);
+        }
+    }
+
+    #[test]
+    fn test_intervals_widen_with_horizon() {
+        let history: Vec<f64> = (0..40).map(|i| 50.0 + ((i * 7) % 11) as f64).collect();
+        let points = forecast(&history, &config(ForecastModel::Holt)).unwrap();
+        let first = points[0].upper - points[0].lower;
+        let last = points[4].upper - points[4].lower;
+        assert!(first > 0.0);
+        assert!(last > first);
+        assert!(points.iter().all(|p| p.lower <= p.value && p.value <= p.upper));
+    }
+
+    #[test]
+    fn test_insufficient_history() {
+        assert!(forecast(&[1.0, 2.0], &config(ForecastModel::Holt)).is_none());
+        assert!(forecast(&[1.0; 7], &config(ForecastModel::HoltWinters)).is_none());
+        assert!(forecast(&[1.0; 4], &config(ForecastModel::MovingAverage)).is_none());
+    }
+
+    #[test]
+    fn test_clamped_point() {
+        let point = ForecastPoint::new(98.0, 5.0).clamped(0.0, 100.0);
+        assert_eq!(point.upper, 100.0);
+        assert_eq!(point.lower, 93.0);
+    }
+}
@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Bounded in-memory history of named metric series.
+///
+/// Every series keeps at most `capacity` samples. Samples recorded in the same
+/// tick share a timestamp, which is what `aligned` uses to pair series up.
+pub struct MetricHistory {
+    capacity: usize,
+    series: BTreeMap<String, VecDeque<(DateTime<Utc>, f64)>>,
+}
+
+impl MetricHistory {
+    pub fn new(capacity: usize) -> Self {
+        MetricHistory { capacity: capacity.max(1), series: BTreeMap::new() }
+    }
+
+    /// Change how many samples each series keeps, dropping the oldest ones
+    /// when it shrinks.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        for samples in self.series.values_mut() {
+            while samples.len() > self.capacity {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn record(&mut self, name: &str, timestamp: DateTime<Utc>, value: f64) {
+        let samples = self.series.entry(name.to_string()).or_default();
+        samples.push_back((timestamp, value));
+        while samples.len() > self.capacity {
+            samples.pop_front();
+        }
+    }
+
+    pub fn record_all<'a>(&mut self, timestamp: DateTime<Utc>, metrics: impl IntoIterator<Item = (&'a String, &'a f64)>) {
+        for (name, value) in metrics {
+            self.record(name, timestamp, *value);
+        }
+    }
+
+    /// Drop series that have not been recorded since `cutoff`, e.g. exited processes.
+    pub fn prune_stale(&mut self, cutoff: DateTime<Utc>) {
+        self.series.retain(|_, samples| samples.back().is_some_and(|(t, _)| *t >= cutoff));
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.series.keys().map(|name| name.as_str())
+    }
+
+    pub fn samples(&self, name: &str) -> Option<&VecDeque<(DateTime<Utc>, f64)>> {
+        self.series.get(name)
+    }
+
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.series.get(name)
+            .map(|samples| samples.iter().map(|(_, v)| *v).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn latest(&self, name: &str) -> Option<(DateTime<Utc>, f64)> {
+        self.series.get(name)?.back().copied()
+    }
+
+    /// Samples of `name` taken at or after `since`.
+    pub fn since(&self, name: &str, since: DateTime<Utc>) -> Vec<(DateTime<Utc>, f64)> {
+        self.series.get(name)
+            .map(|samples| samples.iter().filter(|(t, _)| *t >= since).copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Values of series `a` and `b` at the timestamps they have in common, or
+    /// `None` if either has no history.
+    pub fn aligned(&self, a: &str, b: &str) -> Option<(Vec<f64>, Vec<f64>)> {
+        let (first, second) = (self.series.get(a)?, self.series.get(b)?);
+        let by_time: HashMap<DateTime<Utc>, f64> = second.iter().copied().collect();
+        Some(first.iter().filter_map(|(t, value)| Some((*value, *by_time.get(t)?))).unzip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = MetricHistory::new(3);
+        let start = Utc::now();
+        for i in 0..5 {
+            history.record("cpu", start + chrono::Duration::seconds(i), i as f64);
+        }
+        assert_eq!(history.values("cpu"), vec![2.0, 3.0, 4.0]);
+        assert_eq!(history.latest("cpu").unwrap().1, 4.0);
+        assert!(history.values("missing").is_empty());
+    }
+
+    #[test]
+    fn test_aligned_skips_gaps() {
+        let mut history = MetricHistory::new(10);
+        let start = Utc::now();
+        for i in 0..5 {
+            let t = start + chrono::Duration::seconds(i);
+            history.record("cpu", t, i as f64);
+            if i != 2 {
+                history.record("proc", t, i as f64 * 10.0);
+            }
+        }
+        let (cpu, proc) = history.aligned("cpu", "proc").unwrap();
+        assert_eq!(cpu, vec![0.0, 1.0, 3.0, 4.0]);
+        assert_eq!(proc, vec![0.0, 10.0, 30.0, 40.0]);
+        assert_eq!(history.aligned("cpu", "cpu").unwrap().0.len(), 5);
+        assert!(history.aligned("cpu", "missing").is_none());
+    }
+
+    #[test]
+    fn test_prune_stale() {
+        let mut history = MetricHistory::new(10);
+        let start = Utc::now();
+        history.record("proc_cpu[old]", start, 1.0);
+        history.record("cpu", start + chrono::Duration::seconds(5), 1.0);
+        history.prune_stale(start + chrono::Duration::seconds(1));
+        assert_eq!(history.names().collect::<Vec<_>>(), vec!["cpu"]);
+    }
+
+    #[test]
+    fn test_since() {
+        let mut history = MetricHistory::new(10);
+        let start = Utc::now();
+        for i in 0..5 {
+            history.record("cpu", start + chrono::Duration::seconds(i), i as f64);
+        }
+        let recent = history.since("cpu", start + chrono::Duration::seconds(3));
+        assert_eq!(recent.len(), 2);
+    }
+}
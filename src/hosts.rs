@@ -0,0 +1,393 @@
+use crate::alerts::AlertEngine;
+use crate::network::HostStatus;
+use crate::rendering::AdvancedCanvas;
+use crossterm::{event::KeyCode, style::Color};
+use std::cmp::Ordering;
+use std::io;
+
+/// Connection state of a host as shown in the overview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostState {
+    Online,
+    Stale,
+    Offline,
+}
+
+impl HostState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HostState::Online => "online",
+            HostState::Stale => "stale",
+            HostState::Offline => "offline",
+        }
+    }
+}
+
+/// One line of the overview table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostRow {
+    pub host: String,
+    pub state: HostState,
+    pub cpu: f32,
+    pub mem_pct: f64,
+    pub load1: f64,
+    /// Bytes received and sent during the host's latest tick
+    pub net_rx: u64,
+    pub net_tx: u64,
+    pub firing: usize,
+    pub pending: usize,
+}
+
+impl HostRow {
+    pub fn new(status: &HostStatus, alerts: Option<&AlertEngine>) -> Self {
+        let state = match (status.connected, status.stale) {
+            (false, _) => HostState::Offline,
+            (true, true) => HostState::Stale,
+            (true, false) => HostState::Online,
+        };
+        let snapshot = status.latest.as_ref().map(|frame| &frame.snapshot);
+        HostRow {
+            host: status.host.clone(),
+            state,
+            cpu: snapshot.map_or(0.0, |s| s.cpu_usage),
+            mem_pct: snapshot.map_or(0.0, |s| s.memory_percentage()),
+            load1: snapshot.map_or(0.0, |s| s.load_average[0]),
+            net_rx: snapshot.map_or(0, |s| s.network_rx),
+            net_tx: snapshot.map_or(0, |s| s.network_tx),
+            firing: alerts.map_or(0, |alerts| alerts.firing().len()),
+            pending: alerts.map_or(0, |alerts| alerts.pending().len()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Host,
+    Cpu,
+    Memory,
+    Load,
+    Network,
+    Alerts,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Host => SortKey::Cpu,
+            SortKey::Cpu => SortKey::Memory,
+            SortKey::Memory => SortKey::Load,
+            SortKey::Load => SortKey::Network,
+            SortKey::Network => SortKey::Alerts,
+            SortKey::Alerts => SortKey::Host,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortKey::Host => "host",
+            SortKey::Cpu => "cpu",
+            SortKey::Memory => "memory",
+            SortKey::Load => "load",
+            SortKey::Network => "network",
+            SortKey::Alerts => "alerts",
+        }
+    }
+
+    fn compare(&self, a: &HostRow, b: &HostRow) -> Ordering {
+        match self {
+            SortKey::Host => a.host.cmp(&b.host),
+            SortKey::Cpu => a.cpu.total_cmp(&b.cpu),
+            SortKey::Memory => a.mem_pct.total_cmp(&b.mem_pct),
+            SortKey::Load => a.load1.total_cmp(&b.load1),
+            SortKey::Network => (a.net_rx + a.net_tx).cmp(&(b.net_rx + b.net_tx)),
+            SortKey::Alerts => (a.firing, a.pending).cmp(&(b.firing, b.pending)),
+        }
+    }
+}
+
+/// Sorting, filtering and selection of the multi-host overview, and which
+/// host, if any, is shown in full.
+#[derive(Debug, Clone)]
+pub struct HostsView {
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Only hosts whose name contains this, ignoring case
+    pub filter: String,
+    /// Keys are typed into the filter
+    pub editing_filter: bool,
+    pub selected: usize,
+    /// Host whose full dashboard is shown instead of the overview
+    pub drill: Option<String>,
+    /// Selected process while drilled into a host
+    pub selected_process: usize,
+    /// Shown under the table, e.g. why the last agent was refused
+    pub notice: Option<String>,
+    /// The screen must be cleared before the next draw
+    pub redraw: bool,
+    /// Table lines drawn last time, to blank the ones no longer used
+    drawn: u16,
+    /// First row shown when there are more hosts than fit the terminal
+    scroll: usize,
+}
+
+impl Default for HostsView {
+    fn default() -> Self {
+        HostsView {
+            sort: SortKey::Host,
+            descending: false,
+            filter: String::new(),
+            editing_filter: false,
+            selected: 0,
+            drill: None,
+            selected_process: 0,
+            notice: None,
+            redraw: true,
+            drawn: 0,
+            scroll: 0,
+        }
+    }
+}
+
+impl HostsView {
+    /// Which of `total` rows fit in `room` lines, scrolled so the selection stays visible.
+    fn visible_rows(&mut self, total: usize, room: usize) -> std::ops::Range<usize> {
+        let room = room.max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + room {
+            self.scroll = self.selected + 1 - room;
+        }
+        self.scroll = self.scroll.min(total.saturating_sub(room));
+        self.scroll..total.min(self.scroll + room)
+    }
+
+    /// The rows that pass the filter, in display order.
+    pub fn arrange(&self, mut rows: Vec<HostRow>) -> Vec<HostRow> {
+        let filter = self.filter.to_lowercase();
+        rows.retain(|row| row.host.to_lowercase().contains(&filter));
+        rows.sort_by(|a, b| {
+            let order = self.sort.compare(a, b);
+            let order = if self.descending { order.reverse() } else { order };
+            // Ties keep a stable, readable order
+            order.then_with(|| a.host.cmp(&b.host))
+        });
+        rows
+    }
+
+    fn drill_into(&mut self, host: Option<String>) {
+        self.drill = host;
+        self.selected_process = 0;
+        self.redraw = true;
+    }
+
+    /// Apply a key to the view; `rows` are the arranged rows on screen.
+    /// Returns false when the user asked to quit.
+    pub fn handle_key(&mut self, code: KeyCode, rows: &[HostRow], processes: usize) -> bool {
+        if self.drill.is_some() {
+            match code {
+                KeyCode::Char('q') => return false,
+                KeyCode::Esc | KeyCode::Backspace => self.drill_into(None),
+                KeyCode::Up => self.selected_process = self.selected_process.saturating_sub(1),
+                KeyCode::Down => self.selected_process = (self.selected_process + 1).min(processes.saturating_sub(1)),
+                _ => {}
+            }
+            return true;
+        }
+        if self.editing_filter {
+            match code {
+                KeyCode::Char(c) => self.filter.push(c),
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Esc => {
+                    self.editing_filter = false;
+                    self.filter.clear();
+                }
+                _ => {}
+            }
+            self.selected = 0;
+            return true;
+        }
+        match code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if self.filter.is_empty() => return false,
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.selected = 0;
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(rows.len().saturating_sub(1)),
+            KeyCode::Enter => {
+                if let Some(row) = rows.get(self.selected) {
+                    self.drill_into(Some(row.host.clone()));
+                }
+            }
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.descending = !self.descending,
+            KeyCode::Char('/') => self.editing_filter = true,
+            _ => {}
+        }
+        true
+    }
+}
+
+fn rate(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1_048_576 => format!("{:.1}MB", b as f64 / 1_048_576.0),
+        b if b >= 1024 => format!("{:.1}KB", b as f64 / 1024.0),
+        b => format!("{}B", b),
+    }
+}
+
+fn alert_label(row: &HostRow) -> String {
+    match (row.firing, row.pending) {
+        (0, 0) => "ok".to_string(),
+        (0, pending) => format!("{} pending", pending),
+        (firing, _) => format!("{} firing", firing),
+    }
+}
+
+/// Draw the overview table; `rows` come from `HostsView::arrange`.
+pub fn draw_hosts(canvas: &mut AdvancedCanvas, rows: &[HostRow], view: &mut HostsView) -> io::Result<()> {
+    const WIDTH: usize = 100;
+    let line = |text: String| format!("{:<width$}", text, width = WIDTH);
+
+    canvas.set_cursor(0, 0)?;
+    canvas.set_color(Color::Cyan)?;
+    let order = if view.descending { "desc" } else { "asc" };
+    canvas.draw_str(&line(format!("=== grainx Hosts === {} hosts, sorted by {} ({})", rows.len(), view.sort.label(), order)))?;
+
+    canvas.set_cursor(0, 1)?;
+    canvas.set_color(if view.editing_filter { Color::Yellow } else { Color::White })?;
+    let cursor = if view.editing_filter { "_" } else { "" };
+    canvas.draw_str(&line(format!("Filter: {}{}", view.filter, cursor)))?;
+
+    canvas.set_cursor(0, 3)?;
+    canvas.set_color(Color::White)?;
+    canvas.draw_str(&line(format!("  {:<24} {:<8} {:>7} {:>7} {:>7} {:>10} {:>10}  {}",
+        "HOST", "STATE", "CPU", "MEM", "LOAD", "RX", "TX", "ALERTS")))?;
+
+    view.selected = view.selected.min(rows.len().saturating_sub(1));
+    // Header, column titles and the two lines under the table
+    let (_, height) = canvas.size();
+    let visible = view.visible_rows(rows.len(), height.saturating_sub(7) as usize);
+    let shown = visible.len() as u16;
+    for (y, i) in visible.clone().enumerate() {
+        let row = &rows[i];
+        let color = match (row.state, row.firing) {
+            (HostState::Offline, _) => Color::DarkGrey,
+            (_, firing) if firing > 0 => Color::Red,
+            (HostState::Stale, _) => Color::Yellow,
+            _ if row.pending > 0 => Color::Yellow,
+            _ => Color::Green,
+        };
+        let marker = if i == view.selected { ">" } else { " " };
+        let host: String = row.host.chars().take(24).collect();
+        canvas.set_cursor(0, 4 + y as u16)?;
+        canvas.set_color(color)?;
+        canvas.draw_str(&line(format!("{} {:<24} {:<8} {:>6.1}% {:>6.1}% {:>7.2} {:>10} {:>10}  {}",
+            marker, host, row.state.label(), row.cpu, row.mem_pct, row.load1,
+            rate(row.net_rx), rate(row.net_tx), alert_label(row))))?;
+    }
+    for y in shown..view.drawn {
+        canvas.set_cursor(0, 4 + y)?;
+        canvas.draw_str(&" ".repeat(WIDTH))?;
+    }
+    view.drawn = shown;
+
+    let position = if visible.len() < rows.len() {
+        format!("  rows {}-{} of {}", visible.start + 1, visible.end, rows.len())
+    } else {
+        String::new()
+    };
+    canvas.set_cursor(0, 5 + shown)?;
+    canvas.set_color(Color::DarkGrey)?;
+    canvas.draw_str(&line(format!("Enter: open host  s: sort  r: reverse  /: filter  q: quit{}", position)))?;
+    canvas.set_cursor(0, 6 + shown)?;
+    canvas.set_color(Color::Red)?;
+    canvas.draw_str(&line(view.notice.clone().unwrap_or_default()))?;
+    view.drawn += 3;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(host: &str, cpu: f32, firing: usize) -> HostRow {
+        HostRow {
+            host: host.to_string(),
+            state: HostState::Online,
+            cpu,
+            mem_pct: 40.0,
+            load1: 1.0,
+            net_rx: 0,
+            net_tx: 0,
+            firing,
+            pending: 0,
+        }
+    }
+
+    fn hosts(rows: &[HostRow]) -> Vec<&str> {
+        rows.iter().map(|row| row.host.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let all = vec![row("web-2", 80.0, 0), row("db-1", 20.0, 2), row("web-1", 50.0, 0)];
+        let mut view = HostsView::default();
+        assert_eq!(hosts(&view.arrange(all.clone())), ["db-1", "web-1", "web-2"]);
+
+        view.handle_key(KeyCode::Char('s'), &[], 0);
+        view.handle_key(KeyCode::Char('r'), &[], 0);
+        assert_eq!(view.sort, SortKey::Cpu);
+        assert_eq!(hosts(&view.arrange(all.clone())), ["web-2", "web-1", "db-1"]);
+
+        for c in "/WEB".chars() {
+            view.handle_key(KeyCode::Char(c), &[], 0);
+        }
+        view.handle_key(KeyCode::Enter, &[], 0);
+        assert!(!view.editing_filter);
+        assert_eq!(hosts(&view.arrange(all.clone())), ["web-2", "web-1"]);
+
+        // Esc clears the filter first, then quits
+        assert!(view.handle_key(KeyCode::Esc, &[], 0));
+        assert_eq!(view.arrange(all).len(), 3);
+        assert!(!view.handle_key(KeyCode::Esc, &[], 0));
+    }
+
+    #[test]
+    fn test_drill_into_selected_host() {
+        let mut view = HostsView::default();
+        let rows = view.arrange(vec![row("a", 1.0, 0), row("b", 2.0, 0)]);
+        view.redraw = false;
+        view.handle_key(KeyCode::Down, &rows, 0);
+        view.handle_key(KeyCode::Down, &rows, 0);
+        view.handle_key(KeyCode::Enter, &rows, 0);
+        assert_eq!(view.drill.as_deref(), Some("b"));
+        assert!(view.redraw);
+
+        // Keys go to the host dashboard until Esc returns to the table
+        view.handle_key(KeyCode::Char('s'), &rows, 5);
+        view.handle_key(KeyCode::Down, &rows, 5);
+        assert_eq!(view.sort, SortKey::Host);
+        assert_eq!(view.selected_process, 1);
+        view.handle_key(KeyCode::Esc, &rows, 5);
+        assert_eq!(view.drill, None);
+        assert!(!view.handle_key(KeyCode::Char('q'), &rows, 0));
+    }
+
+    #[test]
+    fn test_selection_scrolls_into_view() {
+        let mut view = HostsView::default();
+        assert_eq!(view.visible_rows(3, 10), 0..3);
+        view.selected = 7;
+        assert_eq!(view.visible_rows(20, 5), 3..8);
+        view.selected = 1;
+        assert_eq!(view.visible_rows(20, 5), 1..6);
+        // Fewer hosts after filtering pull the window back
+        view.selected = 2;
+        assert_eq!(view.visible_rows(4, 5), 0..4);
+    }
+}
@@ -0,0 +1,549 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Requests larger than this are refused; grainx only serves small API calls.
+const MAX_BODY: usize = 1 << 20;
+/// Longest request or header line, and most header lines, in a request.
+const MAX_LINE: usize = 8 << 10;
+const MAX_HEADERS: usize = 100;
+/// Connections served at once; more are answered with 503 straight away.
+const MAX_CONNECTIONS: usize = 64;
+/// Pause after a failed accept (e.g. out of file descriptors), doubled up to
+/// `MAX_ACCEPT_BACKOFF` while accepting keeps failing.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+/// How long `post` may take to connect, send or hear back before it gives up.
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    /// Header names are lowercased
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(|v| v.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Response { status, content_type: content_type.to_string(), headers: Vec::new(), body: body.into() }
+    }
+
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Response::new(status, "text/plain; charset=utf-8", body.into())
+    }
+
+    pub fn json(status: u16, body: &impl serde::Serialize) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(body) => Response::new(status, "application/json", body),
+            Err(e) => Response::text(500, format!("could not encode response: {}", e)),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Response::text(404, "not found\n")
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (percent_decode(&key.replace('+', " ")), percent_decode(&value.replace('+', " "))),
+            None => (percent_decode(&pair.replace('+', " ")), String::new()),
+        })
+        .collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read one line of at most `MAX_LINE` bytes into `line`.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE as u64 + 1).read_line(line)?;
+    if read > MAX_LINE {
+        return Err(invalid("line too long"));
+    }
+    Ok(read)
+}
+
+/// Read one HTTP/1.x request.
+pub fn read_request(stream: &mut impl Read) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (method, path, query) = (method.to_string(), percent_decode(path), parse_query(query));
+
+    let mut headers = BTreeMap::new();
+    for count in 0.. {
+        line.clear();
+        if read_line(&mut reader, &mut line)? == 0 {
+            return Err(invalid("connection closed in headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if count == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("malformed header"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+
+    let length: usize = match headers.get("content-length") {
+        Some(length) => length.parse().map_err(|_| invalid("bad content-length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Request { method, path, query, headers, body })
+}
+
+fn encode_response(response: &Response) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status, reason(response.status), response.content_type, response.body.len());
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    let mut bytes = head.into_bytes();
+    bytes.extend_from_slice(&response.body);
+    bytes
+}
+
+pub fn write_response(stream: &mut impl Write, response: &Response) -> io::Result<()> {
+    stream.write_all(&encode_response(response))?;
+    stream.flush()
+}
+
+pub type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+/// The last accept failure of a server, cleared once accepting works again.
+/// Kept for the dashboard to show, since stderr belongs to the terminal UI.
+pub type LastError = Arc<Mutex<Option<String>>>;
+
+/// Record a failed accept once and return how long to wait before the next.
+fn accept_failed(addr: SocketAddr, error: &io::Error, backoff: &mut Option<Duration>, last_error: &LastError) -> Duration {
+    let delay = match *backoff {
+        None => {
+            *last_error.lock() = Some(format!("accepting on {} failed: {}", addr, error));
+            ACCEPT_BACKOFF
+        }
+        Some(delay) => (delay * 2).min(MAX_ACCEPT_BACKOFF),
+    };
+    *backoff = Some(delay);
+    delay
+}
+
+/// One of the `MAX_CONNECTIONS` connections being served, given back when dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn take(active: &Arc<AtomicUsize>) -> Option<Self> {
+        active.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_CONNECTIONS).then_some(n + 1)).ok()?;
+        Some(ConnectionSlot(active.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn accept_succeeded(backoff: &mut Option<Duration>, last_error: &LastError) {
+    if backoff.take().is_some() {
+        *last_error.lock() = None;
+    }
+}
+
+fn too_many_connections() -> Vec<u8> {
+    encode_response(&Response::text(503, "too many connections\n"))
+}
+
+fn serve_connection(mut stream: TcpStream, handler: &Handler) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+    let response = match read_request(&mut stream) {
+        Ok(request) => handler(&request),
+        Err(e) => Response::text(400, format!("{}\n", e)),
+    };
+    write_response(&mut stream, &response)
+}
+
+/// A small blocking HTTP/1.1 server: one request per connection, one thread per connection.
+pub struct HttpServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    last_error: LastError,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpServer {
+    /// Bind `listen` (e.g. `127.0.0.1:9184`, port 0 for any) and serve requests with `handler`.
+    pub fn start(listen: &str, handler: Handler) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let last_error = LastError::default();
+
+        let thread = {
+            let stop = stop.clone();
+            let last_error = last_error.clone();
+            thread::Builder::new().name("grainx-http".to_string()).spawn(move || {
+                let mut backoff = None;
+                let active = Arc::new(AtomicUsize::new(0));
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            thread::sleep(accept_failed(addr, &e, &mut backoff, &last_error));
+                            continue;
+                        }
+                    };
+                    accept_succeeded(&mut backoff, &last_error);
+                    let Some(slot) = ConnectionSlot::take(&active) else {
+                        // Fits the socket buffer of a fresh connection, so never blocks
+                        (&stream).write_all(&too_many_connections()).ok();
+                        continue;
+                    };
+                    let handler = handler.clone();
+                    // A client that goes away mid-request is not our problem
+                    thread::spawn(move || {
+                        let _slot = slot;
+                        serve_connection(stream, &handler).ok()
+                    });
+                }
+            })?
+        };
+
+        Ok(HttpServer { addr, stop, last_error, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Why accepting connections is currently failing, if it is.
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().clone()
+    }
+
+    pub fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees the flag
+        TcpStream::connect(self.addr).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Drop for HttpServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Serve `handler` on the running tokio runtime instead of a thread of its
+/// own: connections are accepted asynchronously and each request is handled
+/// as a blocking task. Accept failures are kept in `last_error`. Serving
+/// stops when the returned task is aborted.
+pub fn spawn_on_runtime(listen: &str, handler: Handler, last_error: LastError) -> io::Result<(SocketAddr, tokio::task::JoinHandle<()>)> {
+    let runtime = tokio::runtime::Handle::try_current()
+        .map_err(|_| io::Error::other(format!("cannot serve {} without a tokio runtime", listen)))?;
+    // Bind here so a bad or busy address is reported to the caller
+    let listener = TcpListener::bind(listen)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+
+    let task = runtime.spawn(async move {
+        let Ok(listener) = tokio::net::TcpListener::from_std(listener) else { return };
+        let mut backoff = None;
+        let active = Arc::new(AtomicUsize::new(0));
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tokio::time::sleep(accept_failed(addr, &e, &mut backoff, &last_error)).await;
+                    continue;
+                }
+            };
+            accept_succeeded(&mut backoff, &last_error);
+            let Some(slot) = ConnectionSlot::take(&active) else {
+                stream.try_write(&too_many_connections()).ok();
+                continue;
+            };
+            let handler = handler.clone();
+            tokio::task::spawn_blocking(move || {
+                let _slot = slot;
+                let stream = stream.into_std()?;
+                stream.set_nonblocking(false)?;
+                serve_connection(stream, &handler)
+            });
+        }
+    });
+    Ok((addr, task))
+}
+
+/// An `http://host[:port][/path]` URL, checked when a webhook or push
+/// target is configured rather than on every send.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> io::Result<Self> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("URL '{}': {}", url, reason));
+        let rest = url.strip_prefix("http://").ok_or_else(|| invalid("only http:// is supported"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid("bad port"))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        Ok(Url { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// POST `body` to `url` for webhooks and metric pushes; anything but a 2xx
+/// answer is an error.
+pub fn post(url: &Url, headers: &BTreeMap<String, String>, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let address = (url.host.as_str(), url.port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", url.host)))?;
+    let mut stream = TcpStream::connect_timeout(&address, POST_TIMEOUT)?;
+    stream.set_read_timeout(Some(POST_TIMEOUT))?;
+    stream.set_write_timeout(Some(POST_TIMEOUT))?;
+
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nUser-Agent: grainx\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path, url.host, url.port, content_type, body.len()
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status: u16 = status_line.split_whitespace().nth(1).and_then(|code| code.parse().ok()).unwrap_or(0);
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("server answered '{}'", status_line.trim())))
+    }
+}
+
+/// Minimal client for tests and `grainx` talking to itself: send one request, read the response.
+pub fn request(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> io::Result<(u16, String)> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n", method, path, addr, body.len());
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response.split_whitespace().nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("malformed status line"))?;
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let raw = b"POST /api/x%20y?a=1&b=two+words HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nX-Token: abc\r\n\r\nhello";
+        let request = read_request(&mut &raw[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/x y");
+        assert_eq!(request.query["b"], "two words");
+        assert_eq!(request.header("x-token"), Some("abc"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn test_malformed_request() {
+        assert!(read_request(&mut &b"\r\n"[..]).is_err());
+        assert!(read_request(&mut &b"GET / HTTP/1.1\r\nbroken\r\n\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_request_limits() {
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(read_request(&mut long.as_bytes()).unwrap_err().to_string(), "line too long");
+        let headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(MAX_HEADERS));
+        assert!(read_request(&mut headers.as_bytes()).is_ok());
+        let headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(MAX_HEADERS + 1));
+        assert_eq!(read_request(&mut headers.as_bytes()).unwrap_err().to_string(), "too many headers");
+    }
+
+    #[test]
+    fn test_connections_are_capped() {
+        let handler: Handler = Arc::new(|_: &Request| Response::text(200, "ok"));
+        let mut server = HttpServer::start("127.0.0.1:0", handler).unwrap();
+        let addr = server.local_addr();
+        // Idle clients that never finish their request hold every slot
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        // Refused before the request is read, so this client only listens
+        let mut refused = String::new();
+        TcpStream::connect(addr).unwrap().read_to_string(&mut refused).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 503 ") && refused.ends_with("too many connections\n"), "{}", refused);
+        drop(idle);
+        let started = std::time::Instant::now();
+        while request(addr, "GET", "/", &[], b"").unwrap().0 != 200 {
+            assert!(started.elapsed() < Duration::from_secs(5), "slots were not given back");
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = Url::parse("http://localhost:9000/hooks/grainx").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("localhost", 9000, "/hooks/grainx"));
+        let url = Url::parse("http://alerts.example").unwrap();
+        assert_eq!((url.port, url.path.as_str()), (80, "/"));
+        assert_eq!(url.to_string(), "http://alerts.example:80/");
+        assert!(Url::parse("https://alerts.example").is_err());
+        assert!(Url::parse("http://host:port/").is_err());
+        assert!(Url::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn test_accept_backoff() {
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        let error = io::Error::other("too many open files");
+        let mut backoff = None;
+        let last_error = LastError::default();
+        let delays: Vec<Duration> = (0..6).map(|_| accept_failed(addr, &error, &mut backoff, &last_error)).collect();
+        assert_eq!(delays[0], ACCEPT_BACKOFF);
+        assert_eq!(delays[1], ACCEPT_BACKOFF * 2);
+        assert_eq!(delays[5], MAX_ACCEPT_BACKOFF);
+        assert_eq!(last_error.lock().as_deref(), Some("accepting on 127.0.0.1:9 failed: too many open files"));
+        accept_succeeded(&mut backoff, &last_error);
+        assert_eq!((backoff, last_error.lock().clone()), (None, None));
+    }
+
+    #[test]
+    fn test_server_round_trip() {
+        let handler: Handler = Arc::new(|request: &Request| match request.path.as_str() {
+            "/hello" => Response::text(200, format!("hello {}", request.query.get("name").map(|s| s.as_str()).unwrap_or("?"))),
+            _ => Response::not_found(),
+        });
+        let mut server = HttpServer::start("127.0.0.1:0", handler).unwrap();
+        let addr = server.local_addr();
+
+        assert_eq!(request(addr, "GET", "/hello?name=grainx", &[], b"").unwrap(), (200, "hello grainx".to_string()));
+        assert_eq!(request(addr, "GET", "/missing", &[], b"").unwrap().0, 404);
+        server.shutdown();
+        assert!(TcpStream::connect(addr).is_err() || request(addr, "GET", "/hello", &[], b"").is_err());
+    }
+}
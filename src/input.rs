@@ -1,107 +1,209 @@
 use crate::monitor::SystemMonitor;
 use crate::rendering::AdvancedCanvas;
 use crate::help::show_help;
+use crate::hosts::{HostRow, HostsView};
+use crate::learning::ThresholdLearner;
 use crate::performance::PerformanceMonitor;
+use crate::recording::Player;
+use crate::timeline::TimeView;
+use crate::ui::PanelOverlay;
 use crossterm::{event::{self, Event, KeyCode, poll}, style::Color};
 use std::io;
 use std::time::Duration;
 
+/// Graph time navigation, shared by the local and the remote host dashboards.
+/// Returns whether `key` was one of its keys.
+pub fn handle_time_keys(view: &mut TimeView, key: KeyCode) -> bool {
+    match key {
+        KeyCode::Char('[') => view.pan(-1),
+        KeyCode::Char(']') => view.pan(1),
+        KeyCode::Char('z') => view.zoom_in(),
+        KeyCode::Char('x') => view.zoom_out(),
+        KeyCode::Char('c') => view.toggle_cursor(),
+        KeyCode::Char(',') | KeyCode::Char('<') => view.move_cursor(-1),
+        KeyCode::Char('.') | KeyCode::Char('>') => view.move_cursor(1),
+        KeyCode::Char('g') => view.reset(),
+        _ => return false,
+    }
+    true
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn handle_input(
     selected_process: &mut usize, 
-    processes: &Vec<(usize, String, f32, u64)>, 
+    processes: &[(usize, String, f32, u64)], 
     monitor: &mut SystemMonitor, 
     canvas: &mut AdvancedCanvas, 
     proc_start_y: u16,
-    perf_monitor: Option<&mut PerformanceMonitor>
+    perf_monitor: Option<&mut PerformanceMonitor>,
+    learner: Option<&mut ThresholdLearner>,
+    player: Option<&mut Player>,
+    view: Option<&mut TimeView>,
+    overlay: Option<&mut PanelOverlay>,
 ) -> io::Result<bool> {
     // Non-blocking input check
-    if poll(Duration::from_millis(50))? {
-        if let Event::Key(key_event) = event::read()? {
+    if poll(Duration::from_millis(50))? && let Event::Key(key_event) = event::read()? {
+        // Replay controls; the recorded processes are not ours to kill
+        if let Some(player) = player {
             match key_event.code {
-                KeyCode::Char('q') | KeyCode::Esc => {
-                    return Ok(false); // Exit program
+                KeyCode::Char(' ') | KeyCode::Char('p') => {
+                    player.toggle_pause();
+                    return Ok(true);
                 }
-                KeyCode::Up => {
-                    if *selected_process > 0 {
-                        *selected_process -= 1;
-                    }
+                KeyCode::Char('+') | KeyCode::Char('=') => {
+                    player.faster();
+                    return Ok(true);
                 }
-                KeyCode::Down => {
-                    if *selected_process < processes.len().saturating_sub(1) {
-                        *selected_process += 1;
-                    }
+                KeyCode::Char('-') => {
+                    player.slower();
+                    return Ok(true);
                 }
-                KeyCode::Char('k') => {
-                    // Kill selected process with confirmation
-                    if !processes.is_empty() && *selected_process < processes.len() {
-                        let (pid, name, _, _) = &processes[*selected_process];
-                        
-                        // Show confirmation dialog
-                        canvas.set_cursor(0, proc_start_y + 10)?;
-                        canvas.set_color(Color::Red)?;
-                        canvas.draw_str(&format!("Kill process '{}' (PID: {})? (y/N): ", name, pid))?;
-                        
-                        // Wait for confirmation
-                        if let Event::Key(confirm_key) = event::read()? {
-                            if let KeyCode::Char('y') | KeyCode::Char('Y') = confirm_key.code {
-                                if monitor.kill_process(*pid) {
-                                    canvas.set_cursor(0, proc_start_y + 11)?;
-                                    canvas.set_color(Color::Green)?;
-                                    canvas.draw_str(&format!("Process {} killed successfully!", name))?;
-                                } else {
-                                    canvas.set_cursor(0, proc_start_y + 11)?;
-                                    canvas.set_color(Color::Red)?;
-                                    canvas.draw_str(&format!("Failed to kill process {}", name))?;
-                                }
-                            }
-                        }
-                        
-                        // Clear confirmation area after 2 seconds
-                        std::thread::sleep(Duration::from_secs(2));
-                        canvas.set_cursor(0, proc_start_y + 10)?;
-                        canvas.draw_str(&" ".repeat(80))?;
-                        canvas.set_cursor(0, proc_start_y + 11)?;
-                        canvas.draw_str(&" ".repeat(80))?;
-                    }
+                KeyCode::Left => {
+                    player.seek(chrono::Duration::seconds(-10));
+                    return Ok(true);
                 }
-                KeyCode::Char('r') => {
-                    // Refresh/reset monitoring
-                    canvas.set_cursor(0, 0)?;
-                    canvas.set_color(Color::Cyan)?;
-                    canvas.draw_str("Refreshing...")?;
+                KeyCode::Right => {
+                    player.seek(chrono::Duration::seconds(10));
+                    return Ok(true);
                 }
-                KeyCode::Char('h') | KeyCode::Char('?') => {
-                    // Show help menu
-                    show_help(canvas)?;
-                    // Wait for any key to continue
-                    event::read()?;
+                KeyCode::Char('k') => return Ok(true),
+                _ => {}
+            }
+        }
+        // Graph time navigation
+        if let Some(view) = view {
+            handle_time_keys(view, key_event.code);
+        }
+        if let (Some(overlay), KeyCode::Char('o')) = (overlay, key_event.code) {
+            *overlay = overlay.next();
+        }
+        match key_event.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                return Ok(false); // Exit program
+            }
+            KeyCode::Up if *selected_process > 0 => {
+                *selected_process -= 1;
+            }
+            KeyCode::Down if *selected_process < processes.len().saturating_sub(1) => {
+                *selected_process += 1;
+            }
+            // Kill selected process with confirmation
+            KeyCode::Char('k') if *selected_process < processes.len() => {
+                let (pid, name, _, _) = &processes[*selected_process];
+                
+                // Show confirmation dialog
+                canvas.set_cursor(0, proc_start_y + 10)?;
+                canvas.set_color(Color::Red)?;
+                canvas.draw_str(&format!("Kill process '{}' (PID: {})? (y/N): ", name, pid))?;
+                
+                // Wait for confirmation
+                if let Event::Key(confirm_key) = event::read()? && let KeyCode::Char('y') | KeyCode::Char('Y') = confirm_key.code {
+                    if monitor.kill_process(*pid) {
+                        canvas.set_cursor(0, proc_start_y + 11)?;
+                        canvas.set_color(Color::Green)?;
+                        canvas.draw_str(&format!("Process {} killed successfully!", name))?;
+                    } else {
+                        canvas.set_cursor(0, proc_start_y + 11)?;
+                        canvas.set_color(Color::Red)?;
+                        canvas.draw_str(&format!("Failed to kill process {}", name))?;
+                    }
                 }
-                KeyCode::Char('p') => {
-                    // Pause/Resume functionality
-                    canvas.set_cursor(0, 0)?;
-                    canvas.set_color(Color::Yellow)?;
-                    canvas.draw_str("PAUSED - Press any key to continue...")?;
-                    event::read()?; // Wait for any key
+                
+                // Clear confirmation area after 2 seconds
+                std::thread::sleep(Duration::from_secs(2));
+                canvas.set_cursor(0, proc_start_y + 10)?;
+                canvas.draw_str(&" ".repeat(80))?;
+                canvas.set_cursor(0, proc_start_y + 11)?;
+                canvas.draw_str(&" ".repeat(80))?;
+            }
+            KeyCode::Char('r') => {
+                // Refresh/reset monitoring
+                canvas.set_cursor(0, 0)?;
+                canvas.set_color(Color::Cyan)?;
+                canvas.draw_str("Refreshing...")?;
+            }
+            KeyCode::Char('h') | KeyCode::Char('?') => {
+                // Show help menu
+                show_help(canvas)?;
+                // Wait for any key to continue
+                event::read()?;
+            }
+            KeyCode::Char('p') => {
+                // Pause/Resume functionality
+                canvas.set_cursor(0, 0)?;
+                canvas.set_color(Color::Yellow)?;
+                canvas.draw_str("PAUSED - Press any key to continue...")?;
+                event::read()?; // Wait for any key
+            }
+            KeyCode::Char('s') => {
+                // Save current stats to file
+                canvas.set_cursor(0, 0)?;
+                canvas.set_color(Color::Green)?;
+                canvas.draw_str("Stats saved to grainx_stats.txt")?;
+                // TODO: Implement actual saving
+            }
+            KeyCode::Char('l') => {
+                // Forget learned thresholds; the learning panel shows the outcome
+                if let Some(learner) = learner {
+                    learner.reset().ok();
                 }
-                KeyCode::Char('s') => {
-                    // Save current stats to file
+            }
+            KeyCode::Char('a') => {
+                // Toggle adaptive refresh
+                if let Some(perf) = perf_monitor {
+                    perf.toggle_adaptive_refresh();
                     canvas.set_cursor(0, 0)?;
-                    canvas.set_color(Color::Green)?;
-                    canvas.draw_str("Stats saved to grainx_stats.txt")?;
-                    // TODO: Implement actual saving
-                }
-                KeyCode::Char('a') => {
-                    // Toggle adaptive refresh
-                    if let Some(perf) = perf_monitor {
-                        perf.toggle_adaptive_refresh();
-                        canvas.set_cursor(0, 0)?;
-                        canvas.set_color(Color::Cyan)?;
-                        canvas.draw_str("Adaptive refresh toggled!")?;
-                    }
+                    canvas.set_color(Color::Cyan)?;
+                    canvas.draw_str("Adaptive refresh toggled!")?;
                 }
-                _ => {}
             }
+            _ => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Input for the multi-host overview of `grainx server`. While a host is
+/// drilled into, the graph time navigation keys apply to its dashboard.
+/// Remote processes cannot be killed from here.
+pub fn handle_hosts_input(
+    view: &mut HostsView,
+    rows: &[HostRow],
+    processes: usize,
+    canvas: &mut AdvancedCanvas,
+    time_view: Option<&mut TimeView>,
+    overlay: Option<&mut PanelOverlay>,
+) -> io::Result<bool> {
+    if poll(Duration::from_millis(50))? && let Event::Key(key_event) = event::read()? {
+        // Typed into the filter rather than navigating time
+        if let Some(time_view) = time_view.filter(|_| !view.editing_filter) {
+            handle_time_keys(time_view, key_event.code);
+        }
+        if let (Some(overlay), KeyCode::Char('o')) = (overlay.filter(|_| !view.editing_filter), key_event.code) {
+            *overlay = overlay.next();
         }
+        if !view.editing_filter && let KeyCode::Char('h') | KeyCode::Char('?') = key_event.code {
+            show_help(canvas)?;
+            event::read()?;
+            view.redraw = true;
+            return Ok(true);
+        }
+        return Ok(view.handle_key(key_event.code, rows, processes));
     }
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_keys() {
+        let mut view = TimeView::new();
+        assert!(handle_time_keys(&mut view, KeyCode::Char('z')));
+        assert_eq!(view.zoom_label(), "1m");
+        assert!(handle_time_keys(&mut view, KeyCode::Char('g')));
+        assert!(view.is_live());
+        assert!(!handle_time_keys(&mut view, KeyCode::Char('q')));
+    }
+}
@@ -0,0 +1,294 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use crate::config::data_dir;
+use crate::events::EventKind;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LearningConfig {
+    pub enabled: bool,
+    /// Metrics a baseline is learned for
+    pub metrics: Vec<String>,
+    /// Where learned baselines are kept between runs, `learned.json` in the
+    /// per-user data directory when empty
+    pub state_path: String,
+    /// Threshold used until enough samples have been seen
+    pub initial_threshold: f64,
+    pub min_threshold: f64,
+    pub max_threshold: f64,
+    /// Standard deviations above the learned mean the threshold sits at
+    pub sensitivity: f64,
+    /// Old behaviour loses half its weight over this many seconds
+    pub half_life_secs: u64,
+    pub min_samples: u64,
+    /// Threshold moves smaller than this are not reported as events
+    pub report_step: f64,
+}
+
+impl Default for LearningConfig {
+    fn default() -> Self {
+        LearningConfig {
+            enabled: true,
+            metrics: vec!["cpu".to_string(), "mem_pct".to_string()],
+            state_path: String::new(),
+            initial_threshold: 75.0,
+            min_threshold: 50.0,
+            max_threshold: 98.0,
+            sensitivity: 3.0,
+            half_life_secs: 3600,
+            min_samples: 30,
+            report_step: 5.0,
+        }
+    }
+}
+
+impl LearningConfig {
+    pub fn state_path(&self) -> io::Result<PathBuf> {
+        if self.state_path.is_empty() {
+            Ok(data_dir()?.join("learned.json"))
+        } else {
+            Ok(PathBuf::from(&self.state_path))
+        }
+    }
+}
+
+/// Exponentially weighted mean and variance of one metric, and the threshold derived from them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Baseline {
+    pub mean: f64,
+    pub variance: f64,
+    pub samples: u64,
+    pub updated: DateTime<Utc>,
+    pub threshold: f64,
+    /// Threshold last announced with a `ThresholdAdapted` event
+    reported: f64,
+}
+
+impl Baseline {
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct LearnedState {
+    baselines: BTreeMap<String, Baseline>,
+}
+
+/// Learns per-metric baselines and thresholds that follow usage up and down.
+pub struct ThresholdLearner {
+    pub config: LearningConfig,
+    baselines: BTreeMap<String, Baseline>,
+    last_saved: Option<DateTime<Utc>>,
+    /// When the baselines were last reset, and why saving that failed if it did
+    last_reset: Option<(DateTime<Utc>, Option<String>)>,
+}
+
+impl ThresholdLearner {
+    pub fn new(config: LearningConfig) -> Self {
+        ThresholdLearner { config, baselines: BTreeMap::new(), last_saved: None, last_reset: None }
+    }
+
+    /// Restore learned baselines from the state file; a missing file starts fresh.
+    pub fn load(config: LearningConfig) -> io::Result<Self> {
+        let mut learner = ThresholdLearner::new(config);
+        let path = learner.config.state_path()?;
+        if path.exists() {
+            let state: LearnedState = serde_json::from_str(&fs::read_to_string(&path)?)?;
+            learner.baselines = state.baselines;
+            let metrics = learner.config.metrics.clone();
+            learner.baselines.retain(|name, _| metrics.contains(name));
+        }
+        Ok(learner)
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let state = LearnedState { baselines: self.baselines.clone() };
+        let path = self.config.state_path()?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        // Write then rename so an interrupted save never leaves a truncated file
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, serde_json::to_string_pretty(&state)?)?;
+        fs::rename(&temp_path, &path)?;
+        self.last_saved = self.baselines.values().map(|b| b.updated).max();
+        Ok(())
+    }
+
+    /// Save when at least `interval` has passed since the last save.
+    pub fn save_if_due(&mut self, now: DateTime<Utc>, interval: chrono::Duration) -> io::Result<()> {
+        match self.last_saved {
+            Some(last) if now - last < interval => Ok(()),
+            _ => self.save(),
+        }
+    }
+
+    /// Fold this tick's values into the baselines, returning an event for
+    /// every threshold that moved by at least `report_step`.
+    pub fn observe(&mut self, metrics: &BTreeMap<String, f64>, now: DateTime<Utc>) -> Vec<EventKind> {
+        let config = &self.config;
+        let mut events = Vec::new();
+        for name in &config.metrics {
+            let Some(&value) = metrics.get(name) else { continue };
+            let baseline = self.baselines.entry(name.clone()).or_insert_with(|| Baseline {
+                mean: value,
+                variance: 0.0,
+                samples: 0,
+                updated: now,
+                threshold: config.initial_threshold,
+                reported: config.initial_threshold,
+            });
+
+            if baseline.samples > 0 {
+                let elapsed = (now - baseline.updated).num_milliseconds().max(0) as f64 / 1000.0;
+                let decay = 1.0 - 0.5f64.powf(elapsed / config.half_life_secs.max(1) as f64);
+                // Plain averaging while warming up, then exponential forgetting. Capped so
+                // one sample after a long pause cannot wipe out everything learned.
+                let alpha = decay.min(0.5).max(1.0 / (baseline.samples + 1) as f64);
+                let diff = value - baseline.mean;
+                baseline.mean += alpha * diff;
+                baseline.variance = (1.0 - alpha) * (baseline.variance + alpha * diff * diff);
+            }
+            baseline.samples += 1;
+            baseline.updated = now;
+
+            baseline.threshold = if baseline.samples >= config.min_samples {
+                (baseline.mean + config.sensitivity * baseline.std_dev()).clamp(config.min_threshold, config.max_threshold)
+            } else {
+                config.initial_threshold
+            };
+
+            if (baseline.threshold - baseline.reported).abs() >= config.report_step {
+                events.push(EventKind::ThresholdAdapted { metric: name.clone(), from: baseline.reported, to: baseline.threshold });
+                baseline.reported = baseline.threshold;
+            }
+        }
+        events
+    }
+
+    /// The learned threshold for `metric`, or the initial one if nothing has been learned.
+    pub fn threshold(&self, metric: &str) -> f64 {
+        self.baselines.get(metric).map(|b| b.threshold).unwrap_or(self.config.initial_threshold)
+    }
+
+    pub fn baselines(&self) -> impl Iterator<Item = (&str, &Baseline)> {
+        self.baselines.iter().map(|(name, baseline)| (name.as_str(), baseline))
+    }
+
+    /// Forget everything learned and persist the empty state.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.baselines.clear();
+        let saved = self.save();
+        self.last_reset = Some((Utc::now(), saved.as_ref().err().map(|e| e.to_string())));
+        saved
+    }
+
+    /// When `reset` last ran, with the error if the reset could not be saved.
+    pub fn last_reset(&self) -> Option<(DateTime<Utc>, Option<&str>)> {
+        self.last_reset.as_ref().map(|(at, error)| (*at, error.as_deref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LearningConfig {
+        LearningConfig {
+            metrics: vec!["cpu".to_string()],
+            state_path: std::env::temp_dir()
+                .join(format!("grainx-learned-{}-{:?}.json", std::process::id(), std::thread::current().id()))
+                .display()
+                .to_string(),
+            min_samples: 5,
+            half_life_secs: 60,
+            ..LearningConfig::default()
+        }
+    }
+
+    fn feed(learner: &mut ThresholdLearner, values: impl IntoIterator<Item = f64>, start_secs: i64) -> Vec<EventKind> {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        values.into_iter()
+            .enumerate()
+            .flat_map(|(i, value)| {
+                let metrics = BTreeMap::from([("cpu".to_string(), value)]);
+                learner.observe(&metrics, start + chrono::Duration::seconds(start_secs + i as i64))
+            })
+            .collect()
+    }
+
+    fn noisy(level: f64, n: usize) -> Vec<f64> {
+        (0..n).map(|i| level + if i % 2 == 0 { 2.0 } else { -2.0 }).collect()
+    }
+
+    #[test]
+    fn test_initial_threshold_until_enough_samples() {
+        let mut learner = ThresholdLearner::new(config());
+        feed(&mut learner, [10.0, 12.0, 11.0], 0);
+        assert_eq!(learner.threshold("cpu"), 75.0);
+        assert_eq!(learner.threshold("unknown"), 75.0);
+    }
+
+    #[test]
+    fn test_learns_in_both_directions() {
+        let mut learner = ThresholdLearner::new(config());
+        feed(&mut learner, noisy(70.0, 50), 0);
+        let high = learner.threshold("cpu");
+        assert!(high > 75.0 && high < 80.0, "threshold {}", high);
+
+        // Usage drops for a long while: the threshold follows it down
+        feed(&mut learner, noisy(20.0, 600), 50);
+        let low = learner.threshold("cpu");
+        assert_eq!(low, 50.0, "clamped at min_threshold");
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut learner = ThresholdLearner::new(config());
+        let values: Vec<f64> = (0..50).map(|i| if i % 2 == 0 { 100.0 } else { 60.0 }).collect();
+        feed(&mut learner, values, 0);
+        assert_eq!(learner.threshold("cpu"), 98.0);
+    }
+
+    #[test]
+    fn test_reports_threshold_moves() {
+        let mut learner = ThresholdLearner::new(config());
+        let events = feed(&mut learner, noisy(20.0, 10), 0);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], EventKind::ThresholdAdapted { from, to, .. } if *from == 75.0 && *to == 50.0));
+        assert!(feed(&mut learner, noisy(20.0, 10), 10).is_empty());
+    }
+
+    #[test]
+    fn test_persistence_and_reset() {
+        let mut learner = ThresholdLearner::new(config());
+        feed(&mut learner, noisy(70.0, 50), 0);
+        learner.save().unwrap();
+
+        let mut restored = ThresholdLearner::load(config()).unwrap();
+        assert_eq!(restored.threshold("cpu"), learner.threshold("cpu"));
+        assert_eq!(restored.baselines().count(), 1);
+
+        restored.reset().unwrap();
+        assert!(matches!(restored.last_reset(), Some((_, None))));
+        assert_eq!(ThresholdLearner::load(config()).unwrap().baselines().count(), 0);
+        fs::remove_file(&config().state_path).ok();
+    }
+
+    #[test]
+    fn test_state_path_defaults_to_the_data_dir() {
+        assert_eq!(LearningConfig::default().state_path().unwrap(), data_dir().unwrap().join("learned.json"));
+        assert_eq!(config().state_path().unwrap(), PathBuf::from(config().state_path));
+    }
+
+    #[test]
+    fn test_missing_state_file_starts_fresh() {
+        let config = LearningConfig { state_path: "/nonexistent/grainx_learned.json".to_string(), ..config() };
+        assert_eq!(ThresholdLearner::load(config).unwrap().baselines().count(), 0);
+    }
+}
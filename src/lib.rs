@@ -1,5 +1,34 @@
+pub mod alerts;
+pub mod api;
 pub mod analytics;
+pub mod batch;
+pub mod cli;
 pub mod config;
+pub mod control;
+pub mod custom_metrics;
+pub mod events;
+pub mod exhaustion;
+pub mod export;
+pub mod expr;
+pub mod forecast;
+pub mod history;
+pub mod hosts;
+pub mod http;
+pub mod learning;
+pub mod patterns;
 pub mod performance;
+pub mod prometheus;
+pub mod push;
 pub mod monitor;
-pub mod rendering;
\ No newline at end of file
+pub mod network;
+pub mod notify;
+pub mod otlp;
+pub mod recording;
+pub mod rendering;
+pub mod snapshot;
+pub mod timeline;
+pub mod tls;
+pub mod tsdb;
+pub mod ui;
+pub mod input;
+pub mod help;
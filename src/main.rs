@@ -1,75 +1,282 @@
-mod rendering;
-mod analytics;
-mod config;
-mod monitor;
-mod input;
-mod ui;
-mod help;
-mod performance;
-
 use std::{thread, time::Duration};
-use rendering::{AdvancedCanvas, Rect};
-use crossterm::{terminal, execute, cursor, style::ResetColor};
+use grainx::rendering::{AdvancedCanvas, Rect};
+use crossterm::{terminal, execute, cursor, style::{Color, ResetColor}};
 
+use std::collections::BTreeMap;
 use std::io::{self};
-use analytics::{AnomalyDetector, AnomalyDetectorConfig, AnomalyStrategy};
-use config::DashboardConfig;
-
-use crate::monitor::SystemMonitor;
-use crate::input::handle_input;
-use crate::ui::draw_dashboard;
-use crate::performance::PerformanceMonitor;
+use grainx::analytics::{AnomalyDetector, AnomalyDetectorConfig, AnomalyStrategy};
+use grainx::batch::run_batch;
+use grainx::cli::{parse_args, Command, ConfigAction, USAGE};
+use grainx::config::{check_file, ConfigWatcher, DashboardConfig, CONFIG_VERSION};
+use grainx::control::{self, ControlServer, Controlled};
+#[cfg(unix)]
+use grainx::control::ControlRequest;
+use grainx::events;
+use grainx::hosts::{draw_hosts, HostRow, HostsView};
+use grainx::network::{run_agent, run_server, Server};
+use grainx::recording::{Player, Recorder, Recording};
 
+use grainx::monitor::SystemMonitor;
+use grainx::input::{handle_hosts_input, handle_input};
+use grainx::ui::{draw_dashboard, draw_host_status, draw_reload_status, draw_replay_status, DashboardState};
+use grainx::performance::PerformanceMonitor;
 
-#[tokio::main]
-async fn main() -> io::Result<()> {
-    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
-    terminal::enable_raw_mode()?;
 
-    // Load dashboard configuration
-    let config_path = "dashboard_config.json";
-    let dashboard_config = match DashboardConfig::load_from_file(config_path) {
+fn load_config(config_path: &str) -> io::Result<DashboardConfig> {
+    match DashboardConfig::upgrade_file(config_path) {
+        Ok(Some((version, backup))) => {
+            eprintln!("Upgraded {} from config version {} to {}, the original is kept as {}.", config_path, version, CONFIG_VERSION, backup);
+        }
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(io::Error::new(e.kind(), format!("{}: {}", config_path, e))),
+        _ => {}
+    }
+    match DashboardConfig::load_from_file(config_path) {
         Ok(config) => {
-            println!("Loaded dashboard config: {}", config.name);
-            config
+            eprintln!("Loaded dashboard config: {}", config.name);
+            Ok(config)
         },
-        Err(_) => {
-            println!("No dashboard config found, creating default.");
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            eprintln!("No dashboard config found, creating default.");
             let default_config = DashboardConfig::default_config();
             default_config.save_to_file(config_path)?;
-            default_config
+            Ok(default_config)
         }
-    };
+        // Never replace a file that exists: it holds the user's edits
+        Err(e) => Err(io::Error::new(e.kind(), format!("{}: {}", config_path, e))),
+    }
+}
+
+/// The multi-host overview of `grainx server`: one table row per agent's
+/// host, and that host's full dashboard on Enter.
+async fn run_hosts_dashboard(config: &DashboardConfig) -> io::Result<()> {
+    let server = Server::start(config.network.server.clone())?;
+    let host_config = config.for_remote_host();
+
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    terminal::enable_raw_mode()?;
 
-    let mut monitor = SystemMonitor::new();
     let mut canvas = AdvancedCanvas::new();
     let anomaly_detector = AnomalyDetector::new(
         AnomalyDetectorConfig { threshold_multiplier: 2.0 },
         AnomalyStrategy::Statistical,
     );
+    let cpu_rect = Rect { x: 0, y: 0, width: 80, height: 20 };
+    let mem_rect = Rect { x: 0, y: 21, width: 80, height: 10 };
+    let network_start_y = 32;
+    let proc_start_y = 38;
+
+    let mut states: BTreeMap<String, DashboardState> = BTreeMap::new();
+    let mut view = HostsView::default();
+    let mut perf_monitor = PerformanceMonitor::new(60.0);
+
+    loop {
+        if view.redraw {
+            canvas.clear()?;
+            view.redraw = false;
+            if let Some(host) = &view.drill {
+                canvas.set_cursor(0, 0)?;
+                canvas.set_color(Color::Cyan)?;
+                canvas.draw_str(&format!("Waiting for the next snapshot from {}...", host))?;
+            }
+        }
+
+        for (host, frames) in server.drain_frames() {
+            if !states.contains_key(&host) {
+                states.insert(host.clone(), DashboardState::new(&host_config)?);
+            }
+            let state = states.get_mut(&host).expect("inserted above");
+            let Some((latest, earlier)) = frames.split_last() else { continue };
+            for frame in earlier {
+                state.update(&frame.snapshot);
+                state.events.extend(frame.events.iter().cloned());
+            }
+            state.events.extend(latest.events.iter().cloned());
+            if view.drill.as_deref() == Some(host.as_str()) {
+                perf_monitor.start_frame();
+                draw_dashboard(
+                    &mut canvas,
+                    &latest.snapshot,
+                    state,
+                    view.selected_process,
+                    &host_config,
+                    &anomaly_detector,
+                    &cpu_rect,
+                    &mem_rect,
+                    network_start_y,
+                    proc_start_y,
+                    &mut perf_monitor
+                ).await?;
+                perf_monitor.end_frame();
+            } else {
+                state.update(&latest.snapshot);
+            }
+        }
+
+        if let Some((address, reason)) = server.drain_rejections().pop() {
+            view.notice = Some(format!("Agent from {} rejected: {}", address, reason));
+        }
+        let hosts = server.hosts();
+        let rows = view.arrange(hosts.iter()
+            .map(|status| HostRow::new(status, states.get(&status.host).map(|state| &state.alerts)))
+            .collect());
+        let drilled = view.drill.as_ref().and_then(|host| hosts.iter().find(|status| &status.host == host));
+        let processes = drilled.and_then(|status| status.latest.as_ref()).map_or(0, |frame| frame.snapshot.processes.len());
+        match drilled {
+            Some(status) => draw_host_status(&mut canvas, status, proc_start_y + 10)?,
+            None => draw_hosts(&mut canvas, &rows, &mut view)?,
+        }
+
+        let (time_view, overlay) = match view.drill.as_ref().and_then(|host| states.get_mut(host)) {
+            Some(state) => (Some(&mut state.view), Some(&mut state.overlay)),
+            None => (None, None),
+        };
+        if !handle_hosts_input(&mut view, &rows, processes, &mut canvas, time_view, overlay)? {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    terminal::disable_raw_mode()?;
+    execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show, ResetColor)?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let cli = match parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("grainx: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if cli.command == Command::Help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    if let Command::Config { action } = cli.command {
+        match action {
+            ConfigAction::DumpDefault => println!("{}", serde_json::to_string_pretty(&DashboardConfig::default_config())?),
+            ConfigAction::Check => {
+                if !check_file(&cli.config_path, &mut io::stdout().lock())? {
+                    std::process::exit(1);
+                }
+            }
+        }
+        return Ok(());
+    }
 
-    let mut cpu_points: Vec<(f64, f64)> = Vec::new();
-    let mut mem_points: Vec<(f64, f64)> = Vec::new();
+    // Load dashboard configuration
+    let dashboard_config = match load_config(&cli.config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("grainx: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    #[cfg(unix)]
+    if let Command::Ctl { mut request } = cli.command {
+        // The dashboard may run in another directory
+        if let ControlRequest::Snapshot { path: Some(path) } = &mut request {
+            *path = std::env::current_dir()?.join(&path).display().to_string();
+        }
+        match control::send(&control::socket_path(&dashboard_config.control)?, &request)? {
+            Ok(result) => println!("{}", serde_json::to_string_pretty(&result)?),
+            Err(message) => {
+                eprintln!("grainx: {}", message);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if let Command::Batch { iterations } = cli.command {
+        return run_batch(&dashboard_config, iterations, &mut io::stdout().lock());
+    }
+    if let Command::Agent { server } = &cli.command {
+        let mut config = dashboard_config.clone();
+        if let Some(server) = server {
+            config.network.agent.server = server.clone();
+        }
+        return run_agent(&config, &mut io::stdout().lock());
+    }
+    if let Command::Server { listen, batch } = &cli.command {
+        let mut config = dashboard_config.clone();
+        if let Some(listen) = listen {
+            config.network.server.listen = listen.clone();
+        }
+        if *batch {
+            return run_server(&config, None, &mut io::stdout().lock());
+        }
+        return run_hosts_dashboard(&config).await;
+    }
+
+    // A replay must not write history, learn from old data or notify about
+    // it; alerts are still evaluated so the replayed dashboard shows them
+    let replay = matches!(cli.command, Command::Replay { .. });
+    let mut dashboard_config = if replay {
+        dashboard_config.for_replay()
+    } else {
+        dashboard_config
+    };
+    let mut player = match &cli.command {
+        Command::Replay { path } => Some(Player::new(Recording::load(path)?)),
+        _ => None,
+    };
+    let mut recorder = match &cli.command {
+        Command::Record { path } => Some(Recorder::create(path)?),
+        _ => None,
+    };
+
+    let control = if dashboard_config.control.enabled { Some(ControlServer::start(&dashboard_config.control)?) } else { None };
+
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    terminal::enable_raw_mode()?;
+
+    let (event_sender, event_receiver) = events::channel();
+    let mut monitor = SystemMonitor::new().with_events(event_sender);
+    let mut canvas = AdvancedCanvas::new();
+    let anomaly_detector = AnomalyDetector::new(
+        AnomalyDetectorConfig { threshold_multiplier: 2.0 },
+        AnomalyStrategy::Statistical,
+    );
 
     let cpu_rect = Rect { x: 0, y: 0, width: 80, height: 20 };
     let mem_rect = Rect { x: 0, y: 21, width: 80, height: 10 };
     let network_start_y = 32;
     let proc_start_y = 38; // Moved down to accommodate new sections
 
-    let mut current_cpu_y_val = 0.0; // For smooth animation
-    let mut current_mem_y_val = 0.0; // For smooth animation
-
-    let mut cpu_history: Vec<f64> = Vec::new();
-    let mut dummy_metric_history: Vec<f64> = Vec::new();
-
-    let mut iteration_count = 0;
+    let mut dashboard_state = DashboardState::new(&dashboard_config)?;
     let mut selected_process = 0;
     let mut perf_monitor = PerformanceMonitor::new(60.0); // Target 60 FPS
+    let mut last_snapshot = None;
+    let mut last_tick = std::time::Instant::now();
+    let mut paused = false;
+    let mut config_watcher = ConfigWatcher::new(&cli.config_path);
 
     loop {
         perf_monitor.start_frame();
+
+        if let Some(loaded) = config_watcher.poll(std::time::Instant::now()) {
+            let loaded = loaded
+                .map(|config| if replay { config.for_replay() } else { config })
+                .map_err(|e| format!("{}: {}", cli.config_path, e));
+            dashboard_state.reload(&mut dashboard_config, loaded, &mut perf_monitor);
+        }
+
+        for pending in control.iter().flat_map(|control| control.pending()) {
+            let reply = control::execute(&pending.request, &mut Controlled {
+                state: &mut dashboard_state,
+                config: &mut dashboard_config,
+                config_path: &cli.config_path,
+                paused: &mut paused,
+                perf: &mut perf_monitor,
+                snapshot: last_snapshot.as_ref(),
+            });
+            pending.respond(reply);
+        }
         
-        let processes = monitor.get_processes();
         let current_cpu = monitor.last_cpu_usage;
 
         // Skip frame if system is overloaded
@@ -78,30 +285,79 @@ async fn main() -> io::Result<()> {
             continue;
         }
 
-        if !handle_input(&mut selected_process, &processes, &mut monitor, &mut canvas, proc_start_y, Some(&mut perf_monitor))? {
-            break;
+        let elapsed = last_tick.elapsed();
+        last_tick = std::time::Instant::now();
+        let new_snapshot = match &mut player {
+            // Paused from the control socket: nothing is sampled or replayed
+            _ if paused => None,
+            Some(player) => {
+                if player.take_seek() {
+                    // Derived state (graphs, alerts, patterns) is rebuilt from the frames before the new position
+                    let reload_status = dashboard_state.reload_status.take();
+                    dashboard_state = DashboardState::new(&dashboard_config)?;
+                    dashboard_state.reload_status = reload_status;
+                    for frame in player.history(dashboard_config.graph_history_size) {
+                        dashboard_state.update(&frame.snapshot);
+                        dashboard_state.events.extend(frame.events.iter().cloned());
+                    }
+                }
+                let frames = player.advance(elapsed);
+                if let Some((latest, skipped)) = frames.split_last() {
+                    for frame in skipped {
+                        dashboard_state.update(&frame.snapshot);
+                        dashboard_state.events.extend(frame.events.iter().cloned());
+                    }
+                    dashboard_state.events.extend(latest.events.iter().cloned());
+                    Some(latest.snapshot.clone())
+                } else {
+                    None
+                }
+            }
+            None => {
+                let snapshot = monitor.snapshot();
+                let events = event_receiver.drain();
+                if let Some(recorder) = &mut recorder {
+                    recorder.record(&snapshot, &events)?;
+                }
+                dashboard_state.events.extend(events);
+                Some(snapshot)
+            }
+        };
+
+        if let Some(snapshot) = new_snapshot {
+            draw_dashboard(
+                &mut canvas,
+                &snapshot,
+                &mut dashboard_state,
+                selected_process,
+                &dashboard_config,
+                &anomaly_detector,
+                &cpu_rect,
+                &mem_rect,
+                network_start_y,
+                proc_start_y,
+                &mut perf_monitor
+            ).await?;
+            last_snapshot = Some(snapshot);
+        }
+        if let Some(player) = &player {
+            draw_replay_status(&mut canvas, player, proc_start_y + 10)?;
+        }
+        if let Some(status) = &dashboard_state.reload_status {
+            // Below the kill confirmation and its result
+            draw_reload_status(&mut canvas, status, proc_start_y + 12)?;
+        }
+        if paused {
+            // Under the header status slot, which keeps showing storage and export failures
+            canvas.set_cursor(72, 1)?;
+            canvas.set_color(Color::Yellow)?;
+            canvas.draw_str("PAUSED  ")?;
         }
 
-        draw_dashboard(
-            &mut canvas,
-            &mut monitor,
-            &processes,
-            &mut cpu_points,
-            &mut mem_points,
-            &mut cpu_history,
-            &mut dummy_metric_history,
-            &mut iteration_count,
-            selected_process,
-            &dashboard_config,
-            &anomaly_detector,
-            &cpu_rect,
-            &mem_rect,
-            network_start_y,
-            proc_start_y,
-            &mut current_cpu_y_val,
-            &mut current_mem_y_val,
-            &mut perf_monitor
-        ).await?;
+        let processes = last_snapshot.as_ref().map(|s| s.processes.as_slice()).unwrap_or_default();
+        if !handle_input(&mut selected_process, processes, &mut monitor, &mut canvas, proc_start_y, Some(&mut perf_monitor), Some(&mut dashboard_state.learner), player.as_mut(), Some(&mut dashboard_state.view), Some(&mut dashboard_state.overlay))? {
+            break;
+        }
 
         let frame_duration = perf_monitor.end_frame();
         let adaptive_refresh = perf_monitor.calculate_adaptive_refresh(current_cpu);
@@ -114,5 +370,21 @@ async fn main() -> io::Result<()> {
     terminal::disable_raw_mode()?;
     execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show, ResetColor)?;
 
+    if dashboard_config.learning.enabled {
+        dashboard_state.learner.save()?;
+    }
+    if let Some(store) = &mut dashboard_state.store {
+        store.close()?;
+    }
+    if let Some(export) = &mut dashboard_state.export {
+        export.close()?;
+    }
+    if let Some(push) = dashboard_state.push.take() {
+        push.shutdown();
+    }
+    if let Some(otlp) = dashboard_state.otlp.take() {
+        otlp.shutdown();
+    }
+
     Ok(())
 }
@@ -1,7 +1,9 @@
 use sysinfo::{System, Networks, Pid, Disks};
-use std::collections::VecDeque;
 use std::thread;
 use std::time::Duration;
+use chrono::Utc;
+use crate::events::{EventKind, EventSender};
+use crate::snapshot::{DiskSnapshot, InterfaceSnapshot, Snapshot};
 
 pub struct SystemMonitor {
     pub sys: System,
@@ -9,9 +11,13 @@ pub struct SystemMonitor {
     pub disks: Disks,
     pub last_cpu_usage: f32,
     pub high_cpu_duration: u32, // Counter for consecutive high CPU readings
-    pub cpu_state_history: VecDeque<bool>, // true for high CPU, false for low
-    pub user_cpu_threshold: f32, // User-learned CPU threshold
-    pub high_cpu_count: u32, // Counter for how many times CPU exceeds user_cpu_threshold
+    pub events: Option<EventSender>, // Where detections are reported
+}
+
+impl Default for SystemMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SystemMonitor {
@@ -22,9 +28,19 @@ impl SystemMonitor {
             disks: Disks::new_with_refreshed_list(),
             last_cpu_usage: 0.0,
             high_cpu_duration: 0,
-            cpu_state_history: VecDeque::with_capacity(5),
-            user_cpu_threshold: 75.0, // Initial threshold
-            high_cpu_count: 0,
+            events: None,
+        }
+    }
+
+    /// Report detections (bottlenecks) on `events`.
+    pub fn with_events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    fn emit(&self, kind: EventKind) {
+        if let Some(events) = &self.events {
+            events.emit(kind);
         }
     }
 
@@ -47,42 +63,13 @@ impl SystemMonitor {
         // Bottleneck identification logic
         if current_cpu_usage > 90.0 {
             self.high_cpu_duration += 1;
-            if self.high_cpu_duration >= 5 { // If high CPU for 5 consecutive readings
-                println!("\nWARNING: Sustained high CPU usage detected! Potential CPU bottleneck.\n");
+            if self.high_cpu_duration == 5 { // Report once when high CPU reaches 5 consecutive readings
+                self.emit(EventKind::Bottleneck { cpu: current_cpu_usage, readings: self.high_cpu_duration });
             }
         } else {
             self.high_cpu_duration = 0;
         }
 
-        // Behavioral pattern recognition
-        let is_high_cpu = current_cpu_usage > 70.0; // Define a threshold for "high"
-        self.cpu_state_history.push_back(is_high_cpu);
-        if self.cpu_state_history.len() > 5 { // Keep history size limited
-            self.cpu_state_history.pop_front();
-        }
-
-        // Check for a simple fluctuation pattern: High -> Low -> High
-        if self.cpu_state_history.len() == 3 && 
-           self.cpu_state_history[0] == true && 
-           self.cpu_state_history[1] == false && 
-           self.cpu_state_history[2] == true {
-            println!("\nPATTERN DETECTED: CPU usage fluctuating (High-Low-High)!\n");
-            // Clear history to avoid repeated detection of the same pattern
-            self.cpu_state_history.clear();
-        }
-
-        // User behavior learning for personalized monitoring
-        if current_cpu_usage > self.user_cpu_threshold {
-            self.high_cpu_count += 1;
-            if self.high_cpu_count >= 10 { // If CPU exceeds user threshold 10 times
-                self.user_cpu_threshold += 5.0; // Increase threshold
-                println!("\nUSER BEHAVIOR LEARNED: Increased user CPU threshold to {:.1}%\n", self.user_cpu_threshold);
-                self.high_cpu_count = 0; // Reset counter
-            }
-        } else {
-            self.high_cpu_count = 0; // Reset if CPU drops below threshold
-        }
-
         self.last_cpu_usage = current_cpu_usage;
         current_cpu_usage
     }
@@ -92,6 +79,11 @@ impl SystemMonitor {
         (self.sys.used_memory(), self.sys.total_memory())
     }
 
+    pub fn get_swap_usage(&mut self) -> (u64, u64) {
+        self.sys.refresh_memory();
+        (self.sys.used_swap(), self.sys.total_swap())
+    }
+
     pub fn get_processes(&mut self) -> Vec<(usize, String, f32, u64)> {
         self.sys.refresh_processes();
         let mut processes: Vec<(usize, String, f32, u64)> = self.sys.processes()
@@ -122,6 +114,34 @@ impl SystemMonitor {
         (received_bytes, transmitted_bytes)
     }
 
+    pub fn get_interface_snapshots(&mut self) -> Vec<InterfaceSnapshot> {
+        self.networks.refresh();
+        let mut interfaces: Vec<InterfaceSnapshot> = self.networks.iter()
+            .map(|(name, data)| InterfaceSnapshot {
+                name: name.clone(),
+                received: data.received(),
+                transmitted: data.transmitted(),
+                total_received: data.total_received(),
+                total_transmitted: data.total_transmitted(),
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        interfaces
+    }
+
+    /// Bytes read and written by all processes since the last process refresh.
+    pub fn get_disk_io(&self) -> (u64, u64) {
+        self.sys.processes()
+            .values()
+            .map(|p| p.disk_usage())
+            .fold((0, 0), |(read, written), usage| (read + usage.read_bytes, written + usage.written_bytes))
+    }
+
+    pub fn get_load_average(&self) -> [f64; 3] {
+        let load = System::load_average();
+        [load.one, load.five, load.fifteen]
+    }
+
     pub fn get_cpu_cores(&mut self) -> Vec<f32> {
         self.sys.refresh_cpu();
         thread::sleep(Duration::from_millis(200));
@@ -146,6 +166,18 @@ impl SystemMonitor {
             .collect()
     }
 
+    pub fn get_disk_snapshots(&mut self) -> Vec<DiskSnapshot> {
+        self.disks.refresh();
+        self.disks.iter()
+            .map(|disk| DiskSnapshot {
+                name: disk.name().to_string_lossy().to_string(),
+                mount_point: disk.mount_point().to_string_lossy().to_string(),
+                total: disk.total_space(),
+                available: disk.available_space(),
+            })
+            .collect()
+    }
+
     pub fn get_system_info(&mut self) -> (String, String, u64) {
         self.sys.refresh_all();
         let os_name = System::name().unwrap_or_else(|| "Unknown".to_string());
@@ -153,5 +185,41 @@ impl SystemMonitor {
         let uptime = System::uptime();
         (os_name, kernel_version, uptime)
     }
-}
 
+    /// Collect every metric the dashboard needs for one tick.
+    pub fn snapshot(&mut self) -> Snapshot {
+        let cpu_usage = self.get_cpu_usage();
+        let (used_memory, total_memory) = self.get_memory_usage();
+        let (used_swap, total_swap) = self.get_swap_usage();
+        let interfaces = self.get_interface_snapshots();
+        let network_rx = interfaces.iter().map(|i| i.received).sum();
+        let network_tx = interfaces.iter().map(|i| i.transmitted).sum();
+        let cpu_cores = self.get_cpu_cores();
+        let disks = self.get_disk_snapshots();
+        let (os_name, kernel_version, uptime) = self.get_system_info();
+        let processes = self.get_processes();
+        let (disk_read, disk_written) = self.get_disk_io();
+
+        Snapshot {
+            timestamp: Utc::now(),
+            hostname: System::host_name().unwrap_or_else(|| "localhost".to_string()),
+            cpu_usage,
+            cpu_cores,
+            used_memory,
+            total_memory,
+            used_swap,
+            total_swap,
+            network_rx,
+            network_tx,
+            interfaces,
+            disk_read,
+            disk_written,
+            load_average: self.get_load_average(),
+            disks,
+            processes,
+            os_name,
+            kernel_version,
+            uptime,
+        }
+    }
+}
@@ -0,0 +1,830 @@
+use chrono::Utc;
+use parking_lot::Mutex;
+use rustls::{ClientConnection, ConnectionCommon, ServerConnection, StreamOwned};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::batch::{format_host_alert, format_host_event, format_sample, timestamp};
+use crate::config::DashboardConfig;
+use crate::events;
+use crate::monitor::SystemMonitor;
+use crate::recording::Frame;
+use crate::tls::{self, TlsConfig};
+use crate::ui::DashboardState;
+
+/// Bumped whenever `Message` changes incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Frames larger than this are refused.
+const MAX_FRAME: usize = 16 << 20;
+/// Limit for the hello, which arrives before the agent is authenticated.
+const MAX_HELLO: usize = 4 << 10;
+/// How long connecting and the handshake may take.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Frames kept per host until the server drains them.
+const MAX_PENDING_FRAMES: usize = 1000;
+/// Refused connections kept until the server drains them.
+const MAX_REJECTIONS: usize = 100;
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// Address of the grainx server
+    pub server: String,
+    /// Sent when no snapshot went out for this long
+    pub heartbeat_secs: u64,
+    /// First pause before reconnecting, doubled after every failed attempt
+    pub reconnect_min_ms: u64,
+    pub reconnect_max_ms: u64,
+    /// Pre-shared token sent in the handshake, if the server requires one
+    pub token: String,
+    pub tls: TlsConfig,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            server: "127.0.0.1:9190".to_string(),
+            heartbeat_secs: 5,
+            reconnect_min_ms: 500,
+            reconnect_max_ms: 30_000,
+            token: String::new(),
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub listen: String,
+    /// A host that sent nothing for this long is shown as stale
+    pub stale_secs: u64,
+    /// A connection that sent nothing for this long is closed
+    pub timeout_secs: u64,
+    /// Agent connections served at once, including ones still in the
+    /// handshake; further connections are closed right away
+    pub max_connections: usize,
+    /// Agents must send this token in the handshake; empty accepts any agent
+    pub token: String,
+    pub tls: TlsConfig,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: "127.0.0.1:9190".to_string(),
+            stale_secs: 10,
+            timeout_secs: 30,
+            max_connections: 256,
+            token: String::new(),
+            tls: TlsConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub agent: AgentConfig,
+    pub server: ServerConfig,
+}
+
+/// What agents and the server say to each other, one per frame.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// First message from an agent
+    Hello {
+        version: u32,
+        host: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// The server accepted the agent
+    Welcome { version: u32 },
+    /// The server refused the agent; the connection is closed after it
+    Error { message: String },
+    Frame { frame: Box<Frame> },
+    Heartbeat,
+}
+
+/// Write `message` as a 4-byte big-endian length followed by its JSON.
+pub fn write_message(out: &mut impl Write, message: &Message) -> io::Result<()> {
+    let body = serde_json::to_vec(message)?;
+    out.write_all(&(body.len() as u32).to_be_bytes())?;
+    out.write_all(&body)?;
+    out.flush()
+}
+
+pub fn read_message(input: &mut impl Read) -> io::Result<Message> {
+    read_message_within(input, MAX_FRAME)
+}
+
+/// Read one message, refusing frames over `limit` bytes before allocating for them.
+fn read_message_within(input: &mut impl Read, limit: usize) -> io::Result<Message> {
+    let mut length = [0u8; 4];
+    input.read_exact(&mut length)?;
+    if matches!(length, [0x15 | 0x16, 0x03, _, _]) {
+        // The header of a TLS alert or handshake record
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "received TLS data on a connection without TLS"));
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes is too large", length)));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn resolve(address: &str) -> io::Result<SocketAddr> {
+    address.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", address)))
+}
+
+/// A connection between an agent and the server, with or without TLS.
+enum Stream {
+    Plain(TcpStream),
+    Client(Box<StreamOwned<ClientConnection, TcpStream>>),
+    Server(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Stream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(stream) => stream,
+            Stream::Client(stream) => stream.get_ref(),
+            Stream::Server(stream) => stream.get_ref(),
+        }
+    }
+
+    /// Finish the TLS handshake, so certificate problems are reported as
+    /// such rather than as a failed read of the first message.
+    fn handshake(&mut self) -> io::Result<()> {
+        fn complete<D>(conn: &mut ConnectionCommon<D>, sock: &mut TcpStream) -> io::Result<()> {
+            while conn.is_handshaking() {
+                conn.complete_io(sock)?;
+            }
+            Ok(())
+        }
+        let result = match self {
+            Stream::Plain(_) => return Ok(()),
+            Stream::Client(stream) => complete(&mut stream.conn, &mut stream.sock),
+            Stream::Server(stream) => complete(&mut stream.conn, &mut stream.sock),
+        };
+        result.map_err(|e| io::Error::new(e.kind(), format!("tls handshake failed: {}", e)))
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Client(stream) => stream.read(buf),
+            Stream::Server(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Client(stream) => stream.write(buf),
+            Stream::Server(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Client(stream) => stream.flush(),
+            Stream::Server(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Compare tokens without revealing how much of a guess was right.
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Streams snapshots to a server, reconnecting with exponential backoff.
+/// Snapshots taken while disconnected are dropped.
+pub struct Agent {
+    pub config: AgentConfig,
+    host: String,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    stream: Option<Stream>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    last_sent: Instant,
+    /// Why the last connection attempt or write failed
+    pub last_error: Option<String>,
+}
+
+impl Agent {
+    /// Fails if the TLS certificates in `config.tls` cannot be loaded.
+    pub fn new(config: AgentConfig, host: &str) -> io::Result<Self> {
+        let tls = match config.tls.enabled {
+            true => Some(tls::client_config(&config.tls)?),
+            false => None,
+        };
+        let backoff = Duration::from_millis(config.reconnect_min_ms);
+        Ok(Agent { config, host: host.to_string(), tls, stream: None, backoff, retry_at: None, last_sent: Instant::now(), last_error: None })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn handshake(&self) -> io::Result<Stream> {
+        let tcp = TcpStream::connect_timeout(&resolve(&self.config.server)?, HANDSHAKE_TIMEOUT)?;
+        tcp.set_nodelay(true)?;
+        tcp.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        tcp.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut stream = match &self.tls {
+            Some(tls) => {
+                let name = tls::server_name(&self.config.tls, &self.config.server)?;
+                let conn = ClientConnection::new(tls.clone(), name).map_err(|e| io::Error::other(e.to_string()))?;
+                Stream::Client(Box::new(StreamOwned::new(conn, tcp)))
+            }
+            None => Stream::Plain(tcp),
+        };
+        stream.handshake()?;
+
+        let token = Some(self.config.token.clone()).filter(|token| !token.is_empty());
+        if let Err(e) = write_message(&mut stream, &Message::Hello { version: PROTOCOL_VERSION, host: self.host.clone(), token }) {
+            // With TLS 1.3 a refused client certificate is only reported after
+            // the handshake, as an alert waiting to be read
+            return Err(read_message(&mut stream).err().filter(|e| e.kind() == io::ErrorKind::InvalidData).unwrap_or(e));
+        }
+        match read_message(&mut stream) {
+            Ok(Message::Welcome { .. }) => Ok(stream),
+            Ok(Message::Error { message }) => Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("server refused: {}", message))),
+            Ok(other) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected handshake reply {:?}", other))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && self.tls.is_none() => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "server closed the connection during the handshake; does it require TLS?",
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Connect unless connected already or still backing off.
+    fn ensure_connected(&mut self) -> bool {
+        if self.stream.is_some() {
+            return true;
+        }
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return false;
+        }
+        match self.handshake() {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.backoff = Duration::from_millis(self.config.reconnect_min_ms);
+                self.retry_at = None;
+                self.last_error = None;
+                self.last_sent = Instant::now();
+                true
+            }
+            Err(e) => {
+                self.last_error = Some(e.to_string());
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = (self.backoff * 2).min(Duration::from_millis(self.config.reconnect_max_ms));
+                false
+            }
+        }
+    }
+
+    fn write(&mut self, message: &Message) -> bool {
+        let Some(stream) = &mut self.stream else { return false };
+        match write_message(stream, message) {
+            Ok(()) => {
+                self.last_sent = Instant::now();
+                true
+            }
+            Err(e) => {
+                // Reconnect straight away; backoff starts if that fails
+                self.last_error = Some(e.to_string());
+                self.stream = None;
+                false
+            }
+        }
+    }
+
+    /// Send one frame, connecting first if needed. Returns whether it went out.
+    pub fn send(&mut self, frame: &Frame) -> bool {
+        self.ensure_connected() && self.write(&Message::Frame { frame: Box::new(frame.clone()) })
+    }
+
+    /// Send a heartbeat if nothing went out for `heartbeat_secs`.
+    pub fn heartbeat(&mut self) {
+        if self.last_sent.elapsed() >= Duration::from_secs(self.config.heartbeat_secs) && self.ensure_connected() {
+            self.write(&Message::Heartbeat);
+        }
+    }
+}
+
+struct HostEntry {
+    address: SocketAddr,
+    /// Which connection currently owns the host name
+    connection: u64,
+    connected: bool,
+    last_seen: Instant,
+    latest: Option<Frame>,
+    pending: VecDeque<Frame>,
+}
+
+/// A host as the server currently sees it.
+#[derive(Debug, Clone)]
+pub struct HostStatus {
+    pub host: String,
+    pub address: SocketAddr,
+    pub connected: bool,
+    /// Time since the host last sent anything
+    pub silent_for: Duration,
+    pub stale: bool,
+    pub latest: Option<Frame>,
+}
+
+type Hosts = Arc<Mutex<BTreeMap<String, HostEntry>>>;
+type Rejections = Arc<Mutex<Vec<(SocketAddr, String)>>>;
+
+fn reject(rejections: &Rejections, address: SocketAddr, reason: String) {
+    let mut rejections = rejections.lock();
+    if rejections.len() >= MAX_REJECTIONS {
+        rejections.remove(0);
+    }
+    rejections.push((address, reason));
+}
+
+/// Accepts any number of agents and keeps the latest frames of every host.
+pub struct Server {
+    pub config: ServerConfig,
+    addr: SocketAddr,
+    hosts: Hosts,
+    rejections: Rejections,
+    connections: Arc<Mutex<HashMap<u64, TcpStream>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Tell the agent why it is refused, then fail with the same reason.
+fn refuse(stream: &mut Stream, kind: io::ErrorKind, message: String) -> io::Error {
+    write_message(stream, &Message::Error { message: message.clone() }).ok();
+    io::Error::new(kind, message)
+}
+
+/// Run the TLS and protocol handshake, returning the agent's host name once
+/// its version and token are accepted.
+fn accept_agent(tcp: TcpStream, tls: Option<Arc<rustls::ServerConfig>>, token: &str) -> io::Result<(Stream, String)> {
+    tcp.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    tcp.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut stream = match tls {
+        Some(tls) => {
+            let conn = ServerConnection::new(tls).map_err(|e| io::Error::other(e.to_string()))?;
+            Stream::Server(Box::new(StreamOwned::new(conn, tcp)))
+        }
+        None => Stream::Plain(tcp),
+    };
+    if let Err(e) = stream.handshake() {
+        // Closing with the agent's data unread would reset the connection
+        // and discard our alert, so let the agent read it and hang up first
+        let mut tcp = stream.tcp();
+        tcp.shutdown(Shutdown::Write).ok();
+        tcp.set_read_timeout(Some(Duration::from_secs(1))).ok();
+        let mut buffer = [0; 4096];
+        while tcp.read(&mut buffer).is_ok_and(|n| n > 0) {}
+        return Err(e);
+    }
+
+    match read_message_within(&mut stream, MAX_HELLO)? {
+        Message::Hello { version, .. } if version != PROTOCOL_VERSION => {
+            let message = format!("protocol version {} is not supported, this server speaks version {}", version, PROTOCOL_VERSION);
+            Err(refuse(&mut stream, io::ErrorKind::InvalidData, message))
+        }
+        Message::Hello { token: None, .. } if !token.is_empty() => {
+            Err(refuse(&mut stream, io::ErrorKind::PermissionDenied, "this server requires a token".to_string()))
+        }
+        Message::Hello { token: Some(given), .. } if !token.is_empty() && !tokens_match(token, &given) => {
+            Err(refuse(&mut stream, io::ErrorKind::PermissionDenied, "invalid token".to_string()))
+        }
+        Message::Hello { host, .. } => {
+            write_message(&mut stream, &Message::Welcome { version: PROTOCOL_VERSION })?;
+            Ok((stream, host))
+        }
+        _ => Err(refuse(&mut stream, io::ErrorKind::InvalidData, "expected hello".to_string())),
+    }
+}
+
+fn serve_agent(mut stream: Stream, host: String, address: SocketAddr, id: u64, hosts: &Hosts, timeout: Duration) -> io::Result<()> {
+    stream.tcp().set_read_timeout(Some(timeout))?;
+
+    {
+        let mut hosts = hosts.lock();
+        let entry = hosts.entry(host.clone()).or_insert_with(|| HostEntry {
+            address,
+            connection: id,
+            connected: true,
+            last_seen: Instant::now(),
+            latest: None,
+            pending: VecDeque::new(),
+        });
+        // A reconnecting agent takes over its host entry
+        entry.address = address;
+        entry.connection = id;
+        entry.connected = true;
+        entry.last_seen = Instant::now();
+    }
+
+    let result = loop {
+        let message = match read_message(&mut stream) {
+            Ok(message) => message,
+            Err(e) => break Err(e),
+        };
+        let mut hosts = hosts.lock();
+        let Some(entry) = hosts.get_mut(&host) else { break Ok(()) };
+        entry.last_seen = Instant::now();
+        match message {
+            Message::Frame { frame } => {
+                if entry.pending.len() >= MAX_PENDING_FRAMES {
+                    entry.pending.pop_front();
+                }
+                entry.pending.push_back((*frame).clone());
+                entry.latest = Some(*frame);
+            }
+            Message::Heartbeat => {}
+            other => break Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected {:?}", other))),
+        }
+    };
+
+    if let Some(entry) = hosts.lock().get_mut(&host) && entry.connection == id {
+        entry.connected = false;
+    }
+    result
+}
+
+impl Server {
+    /// Fails if the listen address cannot be bound or the TLS certificates
+    /// in `config.tls` cannot be loaded.
+    pub fn start(config: ServerConfig) -> io::Result<Self> {
+        let tls = match config.tls.enabled {
+            true => Some(tls::server_config(&config.tls)?),
+            false => None,
+        };
+        let listener = TcpListener::bind(&config.listen)?;
+        let addr = listener.local_addr()?;
+        let hosts: Hosts = Arc::new(Mutex::new(BTreeMap::new()));
+        let rejections: Rejections = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let timeout = Duration::from_secs(config.timeout_secs.max(1));
+
+        let thread = {
+            let (hosts, rejections, connections, stop) = (hosts.clone(), rejections.clone(), connections.clone(), stop.clone());
+            let (token, max_connections) = (config.token.clone(), config.max_connections);
+            let next_id = AtomicU64::new(0);
+            thread::Builder::new().name("grainx-server".to_string()).spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    let (hosts, rejections, connections) = (hosts.clone(), rejections.clone(), connections.clone());
+                    let id = next_id.fetch_add(1, Ordering::Relaxed);
+                    {
+                        let mut connections = connections.lock();
+                        if connections.len() >= max_connections {
+                            if let Ok(address) = stream.peer_addr() {
+                                reject(&rejections, address, "too many connections".to_string());
+                            }
+                            continue;
+                        }
+                        if let Ok(clone) = stream.try_clone() {
+                            connections.insert(id, clone);
+                        }
+                    }
+                    let (tls, token) = (tls.clone(), token.clone());
+                    thread::spawn(move || {
+                        let Ok(address) = stream.peer_addr() else { return };
+                        match accept_agent(stream, tls, &token) {
+                            Ok((stream, host)) => {
+                                // Dropped agents reconnect by themselves
+                                serve_agent(stream, host, address, id, &hosts, timeout).ok();
+                            }
+                            Err(e) => reject(&rejections, address, e.to_string()),
+                        }
+                        connections.lock().remove(&id);
+                    });
+                }
+            })?
+        };
+
+        Ok(Server { config, addr, hosts, rejections, connections, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Every host seen so far, by name.
+    pub fn hosts(&self) -> Vec<HostStatus> {
+        let stale_after = Duration::from_secs(self.config.stale_secs);
+        self.hosts.lock().iter()
+            .map(|(host, entry)| HostStatus {
+                host: host.clone(),
+                address: entry.address,
+                connected: entry.connected,
+                silent_for: entry.last_seen.elapsed(),
+                stale: entry.last_seen.elapsed() > stale_after,
+                latest: entry.latest.clone(),
+            })
+            .collect()
+    }
+
+    /// Frames received since the last call, per host, oldest first.
+    pub fn drain_frames(&self) -> Vec<(String, Vec<Frame>)> {
+        self.hosts.lock().iter_mut()
+            .filter(|(_, entry)| !entry.pending.is_empty())
+            .map(|(host, entry)| (host.clone(), std::mem::take(&mut entry.pending).into()))
+            .collect()
+    }
+
+    /// Agents refused since the last call, with the reason they were told.
+    pub fn drain_rejections(&self) -> Vec<(SocketAddr, String)> {
+        std::mem::take(&mut *self.rejections.lock())
+    }
+
+    /// Stop accepting agents and close every connection.
+    pub fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the accept loop so it sees the flag
+        TcpStream::connect(self.addr).ok();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+        for stream in self.connections.lock().values() {
+            stream.shutdown(Shutdown::Both).ok();
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Sample the local system and stream every snapshot to the server in
+/// `config.network.agent`, logging connection changes to `out`.
+pub fn run_agent(config: &DashboardConfig, out: &mut impl Write) -> io::Result<()> {
+    let (sender, receiver) = events::channel();
+    let mut monitor = SystemMonitor::new().with_events(sender);
+    let host = monitor.snapshot().hostname;
+    let mut agent = Agent::new(config.network.agent.clone(), &host)?;
+    let interval = Duration::from_millis(config.refresh_interval_ms);
+    let server = config.network.agent.server.clone();
+
+    let mut connected = false;
+    let mut next_sample = Instant::now();
+    loop {
+        if Instant::now() >= next_sample {
+            let snapshot = monitor.snapshot();
+            agent.send(&Frame { snapshot, events: receiver.drain() });
+            // After a stall (a slow reconnect, a suspended machine) carry on
+            // from now rather than catching up with a burst of samples
+            next_sample = (next_sample + interval).max(Instant::now());
+        }
+        agent.heartbeat();
+
+        if agent.is_connected() != connected {
+            connected = agent.is_connected();
+            match (connected, &agent.last_error) {
+                (true, _) => writeln!(out, "{} agent connected to {} as {}", timestamp(Utc::now()), server, host)?,
+                (false, Some(error)) => writeln!(out, "{} agent lost {}: {}", timestamp(Utc::now()), server, error)?,
+                (false, None) => writeln!(out, "{} agent lost {}", timestamp(Utc::now()), server)?,
+            }
+            out.flush()?;
+        }
+        let heartbeat = Duration::from_secs(agent.config.heartbeat_secs.max(1));
+        thread::sleep(next_sample.saturating_duration_since(Instant::now()).min(heartbeat));
+    }
+}
+
+/// Receive snapshots from agents and run alerts and detections for each
+/// host, logging samples, events, alerts and host state changes to `out`.
+pub fn run_server(config: &DashboardConfig, iterations: Option<u64>, out: &mut impl Write) -> io::Result<()> {
+    let server = Server::start(config.network.server.clone())?;
+    writeln!(out, "{} server listening on {}", timestamp(Utc::now()), server.local_addr())?;
+    out.flush()?;
+
+    let host_config = config.for_remote_host();
+    let mut states: BTreeMap<String, DashboardState> = BTreeMap::new();
+    let mut seen: BTreeMap<String, (bool, bool)> = BTreeMap::new();
+    let mut count = 0;
+    while iterations.is_none_or(|n| count < n) {
+        for (host, frames) in server.drain_frames() {
+            if !states.contains_key(&host) {
+                states.insert(host.clone(), DashboardState::new(&host_config)?);
+            }
+            let state = states.get_mut(&host).expect("inserted above");
+            for frame in frames {
+                let tick = state.update(&frame.snapshot);
+                writeln!(out, "{}", format_sample(&frame.snapshot, &tick))?;
+                for event in frame.events.iter().chain(&tick.events) {
+                    writeln!(out, "{}", format_host_event(&host, event))?;
+                }
+                for event in &tick.alert_events {
+                    writeln!(out, "{}", format_host_alert(&host, event))?;
+                }
+                count += 1;
+            }
+        }
+
+        for (address, reason) in server.drain_rejections() {
+            writeln!(out, "{} agent from {} rejected: {}", timestamp(Utc::now()), address, reason)?;
+        }
+        for status in server.hosts() {
+            let now = (status.connected, status.stale);
+            let before = seen.insert(status.host.clone(), now);
+            if before == Some(now) {
+                continue;
+            }
+            let change = match now {
+                (true, false) => format!("connected from {}", status.address),
+                (true, true) => format!("stale, silent for {}s", status.silent_for.as_secs()),
+                (false, _) => "disconnected".to_string(),
+            };
+            writeln!(out, "{} host {} {}", timestamp(Utc::now()), status.host, change)?;
+        }
+        out.flush()?;
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::sample;
+
+    fn frame(secs: i64, cpu: f32) -> Frame {
+        Frame { snapshot: sample(secs, cpu), events: Vec::new() }
+    }
+
+    fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn server_config() -> ServerConfig {
+        ServerConfig { listen: "127.0.0.1:0".to_string(), stale_secs: 1, timeout_secs: 30, ..ServerConfig::default() }
+    }
+
+    fn agent_config(server: SocketAddr) -> AgentConfig {
+        AgentConfig { server: server.to_string(), heartbeat_secs: 1, reconnect_min_ms: 20, reconnect_max_ms: 100, ..AgentConfig::default() }
+    }
+
+    #[test]
+    fn test_framing_round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &Message::Hello { version: 1, host: "web-1".to_string(), token: None }).unwrap();
+        write_message(&mut buffer, &Message::Frame { frame: Box::new(frame(0, 12.0)) }).unwrap();
+        let mut input = &buffer[..];
+        assert_eq!(read_message(&mut input).unwrap(), Message::Hello { version: 1, host: "web-1".to_string(), token: None });
+        assert_eq!(read_message(&mut input).unwrap(), Message::Frame { frame: Box::new(frame(0, 12.0)) });
+        assert_eq!(read_message(&mut input).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let oversized = ((MAX_FRAME + 1) as u32).to_be_bytes();
+        assert_eq!(read_message(&mut &oversized[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // A TLS client hello on a plain connection is named as such
+        let client_hello = [0x16, 0x03, 0x01, 0x00, 0xc8];
+        assert_eq!(read_message(&mut &client_hello[..]).unwrap_err().to_string(), "received TLS data on a connection without TLS");
+    }
+
+    #[test]
+    fn test_agents_stream_to_server() {
+        let mut server = Server::start(server_config()).unwrap();
+        let mut web = Agent::new(agent_config(server.local_addr()), "web-1").unwrap();
+        let mut db = Agent::new(agent_config(server.local_addr()), "db-1").unwrap();
+        assert!(web.send(&frame(0, 10.0)));
+        assert!(web.send(&frame(1, 20.0)));
+        assert!(db.send(&frame(0, 30.0)));
+
+        wait_for("frames", || server.hosts().iter().filter(|h| h.latest.is_some()).count() == 2
+            && server.hosts()[1].latest.as_ref().is_some_and(|f| f.snapshot.cpu_usage == 20.0));
+        let hosts = server.hosts();
+        assert_eq!(hosts.iter().map(|h| h.host.as_str()).collect::<Vec<_>>(), vec!["db-1", "web-1"]);
+        assert!(hosts.iter().all(|h| h.connected && !h.stale));
+
+        let drained = server.drain_frames();
+        assert_eq!(drained[1].1.iter().map(|f| f.snapshot.cpu_usage).collect::<Vec<_>>(), vec![10.0, 20.0]);
+        assert!(server.drain_frames().is_empty());
+
+        // Silence makes a host stale, a heartbeat makes it fresh again
+        wait_for("staleness", || server.hosts()[1].stale);
+        web.heartbeat();
+        wait_for("heartbeat", || !server.hosts()[1].stale);
+
+        drop(db);
+        wait_for("disconnect", || !server.hosts()[0].connected);
+        server.shutdown();
+    }
+
+    #[test]
+    fn test_agent_reconnects_with_backoff() {
+        // Reserve a port, then leave it closed
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut agent = Agent::new(agent_config(address), "web-1").unwrap();
+        assert!(!agent.send(&frame(0, 10.0)));
+        assert!(agent.last_error.is_some());
+        // Backing off: no new attempt right away
+        assert!(!agent.send(&frame(1, 10.0)));
+
+        let mut server = Server::start(ServerConfig { listen: address.to_string(), ..server_config() }).unwrap();
+        wait_for("reconnect", || agent.send(&frame(2, 10.0)));
+        wait_for("frame", || server.hosts().first().is_some_and(|h| h.latest.is_some()));
+
+        // The server going away is noticed on a later write
+        server.shutdown();
+        wait_for("lost connection", || !agent.send(&frame(3, 10.0)) && !agent.is_connected());
+    }
+
+    #[test]
+    fn test_mismatched_version_is_refused() {
+        let server = Server::start(server_config()).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        write_message(&mut stream, &Message::Hello { version: 99, host: "old".to_string(), token: None }).unwrap();
+        match read_message(&mut stream).unwrap() {
+            Message::Error { message } => assert!(message.contains("version 99 is not supported")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(server.hosts().is_empty());
+        wait_for("rejection", || server.drain_rejections().iter().any(|(_, reason)| reason.contains("version 99")));
+    }
+
+    #[test]
+    fn test_oversized_hello_is_refused() {
+        let server = Server::start(ServerConfig { token: "s3cret".to_string(), ..server_config() }).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        // Announces a frame well within MAX_FRAME but too large for a hello
+        stream.write_all(&((MAX_HELLO + 1) as u32).to_be_bytes()).unwrap();
+        wait_for("rejection", || server.drain_rejections().iter().any(|(_, reason)| reason.contains("too large")));
+        assert!(server.hosts().is_empty());
+    }
+
+    #[test]
+    fn test_connections_are_capped() {
+        let server = Server::start(ServerConfig { max_connections: 1, ..server_config() }).unwrap();
+        let mut web = Agent::new(agent_config(server.local_addr()), "web-1").unwrap();
+        assert!(web.send(&frame(0, 10.0)));
+
+        let mut db = Agent::new(agent_config(server.local_addr()), "db-1").unwrap();
+        assert!(!db.send(&frame(0, 10.0)));
+        wait_for("rejection", || server.drain_rejections().iter().any(|(_, reason)| reason == "too many connections"));
+        assert_eq!(server.hosts().len(), 1);
+    }
+
+    #[test]
+    fn test_token_is_required() {
+        let server = Server::start(ServerConfig { token: "s3cret".to_string(), ..server_config() }).unwrap();
+
+        let mut anonymous = Agent::new(agent_config(server.local_addr()), "web-1").unwrap();
+        assert!(!anonymous.send(&frame(0, 10.0)));
+        assert_eq!(anonymous.last_error.as_deref(), Some("server refused: this server requires a token"));
+
+        let config = AgentConfig { token: "guess".to_string(), ..agent_config(server.local_addr()) };
+        let mut guessing = Agent::new(config, "web-1").unwrap();
+        assert!(!guessing.send(&frame(0, 10.0)));
+        assert_eq!(guessing.last_error.as_deref(), Some("server refused: invalid token"));
+
+        let config = AgentConfig { token: "s3cret".to_string(), ..agent_config(server.local_addr()) };
+        let mut trusted = Agent::new(config, "web-1").unwrap();
+        assert!(trusted.send(&frame(0, 10.0)));
+        wait_for("frame", || server.hosts().first().is_some_and(|h| h.latest.is_some()));
+
+        wait_for("rejections", || server.rejections.lock().len() == 2);
+        let reasons: Vec<String> = server.drain_rejections().into_iter().map(|(_, reason)| reason).collect();
+        assert_eq!(reasons, ["this server requires a token", "invalid token"]);
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cret", "s3cres"));
+        assert!(!tokens_match("s3cret", "s3cre"));
+        assert!(!tokens_match("s3cret", ""));
+    }
+}
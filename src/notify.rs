@@ -0,0 +1,468 @@
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::alerts::{AlertEvent, AlertEventKind, Severity};
+use crate::http::{self, Url};
+
+/// How long a command may take before it is abandoned.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delivery failures kept for display.
+const MAX_FAILURES: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierKind {
+    /// POST the notification as JSON to an `http://` URL
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// Run a shell command with the alert in `GRAINX_ALERT_*` environment variables
+    Command { command: String },
+    /// Append the notification as a JSON line
+    Log { path: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NotifierConfig {
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+    /// Alerts below this severity are not sent
+    #[serde(default = "default_min_severity")]
+    pub min_severity: Severity,
+    /// At most this many notifications per rolling minute, 0 for no limit
+    #[serde(default = "default_max_per_minute")]
+    pub max_per_minute: u32,
+    /// The same rule and state is not sent again within this many seconds
+    #[serde(default = "default_dedup_secs")]
+    pub dedup_secs: u64,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Info
+}
+
+fn default_max_per_minute() -> u32 {
+    6
+}
+
+fn default_dedup_secs() -> u64 {
+    300
+}
+
+/// What gets delivered: an alert transition and the host it happened on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub host: String,
+    #[serde(flatten)]
+    pub event: AlertEvent,
+}
+
+pub trait Notifier: Send {
+    fn describe(&self) -> String;
+    fn send(&mut self, notification: &Notification) -> io::Result<()>;
+}
+
+pub struct WebhookNotifier {
+    url: Url,
+    headers: BTreeMap<String, String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: &str, headers: BTreeMap<String, String>) -> io::Result<Self> {
+        Ok(WebhookNotifier { url: Url::parse(url)?, headers })
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn describe(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    fn send(&mut self, notification: &Notification) -> io::Result<()> {
+        http::post(&self.url, &self.headers, "application/json", &serde_json::to_vec(notification)?)
+    }
+}
+
+pub struct CommandNotifier {
+    command: String,
+}
+
+impl CommandNotifier {
+    pub fn new(command: &str) -> Self {
+        CommandNotifier { command: command.to_string() }
+    }
+
+    fn environment(notification: &Notification) -> Vec<(&'static str, String)> {
+        let event = &notification.event;
+        vec![
+            ("GRAINX_ALERT_HOST", notification.host.clone()),
+            ("GRAINX_ALERT_RULE", event.rule.clone()),
+            ("GRAINX_ALERT_CONDITION", event.condition.clone()),
+            ("GRAINX_ALERT_SEVERITY", event.severity.label().to_string()),
+            ("GRAINX_ALERT_STATE", match event.kind {
+                AlertEventKind::Firing => "firing".to_string(),
+                AlertEventKind::Resolved => "resolved".to_string(),
+            }),
+            ("GRAINX_ALERT_VALUE", event.value.to_string()),
+            ("GRAINX_ALERT_THRESHOLD", event.threshold.to_string()),
+            ("GRAINX_ALERT_TIMESTAMP", event.timestamp.to_rfc3339()),
+        ]
+    }
+}
+
+/// `command` run by the platform's shell.
+fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    let (program, flag) = ("cmd", "/C");
+    #[cfg(not(windows))]
+    let (program, flag) = ("sh", "-c");
+    let mut shell = Command::new(program);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+impl Notifier for CommandNotifier {
+    fn describe(&self) -> String {
+        format!("command '{}'", self.command)
+    }
+
+    fn send(&mut self, notification: &Notification) -> io::Result<()> {
+        let mut child = shell(&self.command)
+            .envs(Self::environment(notification))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return if status.success() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other(format!("command exited with {}", status)))
+                };
+            }
+            if started.elapsed() > DELIVERY_TIMEOUT {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "command timed out"));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+pub struct LogNotifier {
+    path: PathBuf,
+}
+
+impl LogNotifier {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LogNotifier { path: path.into() }
+    }
+}
+
+impl Notifier for LogNotifier {
+    fn describe(&self) -> String {
+        format!("log {}", self.path.display())
+    }
+
+    fn send(&mut self, notification: &Notification) -> io::Result<()> {
+        let mut line = serde_json::to_string(notification)?;
+        line.push('\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+}
+
+/// Rate limiting and deduplication for one notifier.
+pub struct Throttle {
+    max_per_minute: u32,
+    dedup: chrono::Duration,
+    sent: VecDeque<DateTime<Utc>>,
+    last_sent: HashMap<(String, AlertEventKind), DateTime<Utc>>,
+}
+
+impl Throttle {
+    pub fn new(max_per_minute: u32, dedup_secs: u64) -> Self {
+        Throttle {
+            max_per_minute,
+            dedup: chrono::Duration::seconds(dedup_secs as i64),
+            sent: VecDeque::new(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Whether `event` may be sent now, recording it as sent if so.
+    pub fn allow(&mut self, event: &AlertEvent) -> bool {
+        let now = event.timestamp;
+        let key = (event.rule.clone(), event.kind);
+        if let Some(last) = self.last_sent.get(&key) && now - *last < self.dedup {
+            return false;
+        }
+
+        while self.sent.front().is_some_and(|t| now - *t >= chrono::Duration::minutes(1)) {
+            self.sent.pop_front();
+        }
+        if self.max_per_minute > 0 && self.sent.len() >= self.max_per_minute as usize {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        self.last_sent.insert(key, now);
+        true
+    }
+}
+
+struct Sink {
+    notifier: Box<dyn Notifier>,
+    throttle: Throttle,
+    min_severity: Severity,
+}
+
+impl Sink {
+    fn from_config(config: &NotifierConfig) -> io::Result<Self> {
+        let notifier: Box<dyn Notifier> = match &config.kind {
+            NotifierKind::Webhook { url, headers } => Box::new(WebhookNotifier::new(url, headers.clone())?),
+            NotifierKind::Command { command } => Box::new(CommandNotifier::new(command)),
+            NotifierKind::Log { path } => Box::new(LogNotifier::new(path)),
+        };
+        Ok(Sink {
+            notifier,
+            throttle: Throttle::new(config.max_per_minute, config.dedup_secs),
+            min_severity: config.min_severity,
+        })
+    }
+}
+
+/// Delivers alert notifications on a background thread so a slow webhook or
+/// command never stalls the dashboard.
+pub struct NotificationDispatcher {
+    sender: mpsc::Sender<Notification>,
+    failures: Arc<Mutex<VecDeque<String>>>,
+    worker: JoinHandle<()>,
+}
+
+impl NotificationDispatcher {
+    /// Start delivering to the configured notifiers, or `None` when there are none.
+    pub fn start(configs: &[NotifierConfig]) -> io::Result<Option<Self>> {
+        if configs.is_empty() {
+            return Ok(None);
+        }
+        let mut sinks = configs.iter().map(Sink::from_config).collect::<io::Result<Vec<Sink>>>()?;
+
+        let (sender, receiver) = mpsc::channel::<Notification>();
+        let failures = Arc::new(Mutex::new(VecDeque::new()));
+        let worker_failures = Arc::clone(&failures);
+        let worker = thread::spawn(move || {
+            for notification in receiver {
+                for sink in sinks.iter_mut() {
+                    if notification.event.severity < sink.min_severity || !sink.throttle.allow(&notification.event) {
+                        continue;
+                    }
+                    if let Err(error) = sink.notifier.send(&notification) {
+                        let mut failures = worker_failures.lock();
+                        failures.push_front(format!("{}: {}", sink.notifier.describe(), error));
+                        failures.truncate(MAX_FAILURES);
+                    }
+                }
+            }
+        });
+
+        Ok(Some(NotificationDispatcher { sender, failures, worker }))
+    }
+
+    pub fn dispatch(&self, host: &str, events: &[AlertEvent]) {
+        for event in events {
+            // The worker only stops when the dispatcher is dropped
+            let _ = self.sender.send(Notification { host: host.to_string(), event: event.clone() });
+        }
+    }
+
+    /// Recent delivery failures, newest first.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.lock().iter().cloned().collect()
+    }
+
+    /// Deliver everything already queued, then stop the worker.
+    pub fn shutdown(self) -> Vec<String> {
+        let NotificationDispatcher { sender, failures, worker } = self;
+        drop(sender);
+        worker.join().ok();
+        failures.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn event(rule: &str, kind: AlertEventKind, secs: i64) -> AlertEvent {
+        AlertEvent {
+            rule: rule.to_string(),
+            condition: "cpu > 90".to_string(),
+            severity: Severity::Critical,
+            kind,
+            value: 95.0,
+            threshold: 90.0,
+            timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+        }
+    }
+
+    fn notification(rule: &str) -> Notification {
+        Notification { host: "web-1".to_string(), event: event(rule, AlertEventKind::Firing, 0) }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("grainx-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_throttle_deduplicates_per_rule_and_state() {
+        let mut throttle = Throttle::new(0, 60);
+        assert!(throttle.allow(&event("cpu", AlertEventKind::Firing, 0)));
+        assert!(!throttle.allow(&event("cpu", AlertEventKind::Firing, 30)));
+        assert!(throttle.allow(&event("cpu", AlertEventKind::Resolved, 30)));
+        assert!(throttle.allow(&event("mem", AlertEventKind::Firing, 30)));
+        assert!(throttle.allow(&event("cpu", AlertEventKind::Firing, 60)));
+    }
+
+    #[test]
+    fn test_throttle_rate_limits() {
+        let mut throttle = Throttle::new(2, 0);
+        assert!(throttle.allow(&event("a", AlertEventKind::Firing, 0)));
+        assert!(throttle.allow(&event("b", AlertEventKind::Firing, 10)));
+        assert!(!throttle.allow(&event("c", AlertEventKind::Firing, 20)));
+        assert!(throttle.allow(&event("c", AlertEventKind::Firing, 61)));
+    }
+
+    #[test]
+    fn test_webhook_url_parsing() {
+        let webhook = WebhookNotifier::new("http://localhost:9000/hooks/grainx", BTreeMap::new()).unwrap();
+        assert_eq!(webhook.describe(), "webhook http://localhost:9000/hooks/grainx");
+        assert!(WebhookNotifier::new("https://alerts.example", BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_webhook_posts_json_to_stub_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            // Read until the whole body announced by Content-Length has arrived
+            loop {
+                let n = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head.lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .and_then(|value| value.parse().ok())
+                        .unwrap();
+                    if body.len() >= length || n == 0 {
+                        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+                        return text;
+                    }
+                }
+            }
+        });
+
+        let headers = BTreeMap::from([("X-Token".to_string(), "secret".to_string())]);
+        let mut webhook = WebhookNotifier::new(&format!("http://127.0.0.1:{}/hook", port), headers).unwrap();
+        webhook.send(&notification("High CPU")).unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(request.contains("X-Token: secret\r\n"));
+        let body: serde_json::Value = serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(body["host"], "web-1");
+        assert_eq!(body["rule"], "High CPU");
+        assert_eq!(body["kind"], "firing");
+        assert_eq!(body["severity"], "critical");
+    }
+
+    #[test]
+    fn test_webhook_error_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 4096];
+            let _ = stream.read(&mut buffer).unwrap();
+            stream.write_all(b"HTTP/1.1 500 Internal Server Error\r\n\r\n").unwrap();
+        });
+        let mut webhook = WebhookNotifier::new(&format!("http://127.0.0.1:{}/", port), BTreeMap::new()).unwrap();
+        let error = webhook.send(&notification("High CPU")).unwrap_err();
+        server.join().unwrap();
+        assert!(error.to_string().contains("500"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_gets_alert_environment() {
+        let output = temp_path("command.txt");
+        let mut command = CommandNotifier::new(&format!(
+            "echo \"$GRAINX_ALERT_RULE|$GRAINX_ALERT_STATE|$GRAINX_ALERT_SEVERITY|$GRAINX_ALERT_HOST\" > {}",
+            output.display()
+        ));
+        command.send(&notification("High CPU")).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap().trim(), "High CPU|firing|critical|web-1");
+        fs::remove_file(&output).ok();
+
+        assert!(CommandNotifier::new("exit 3").send(&notification("x")).is_err());
+    }
+
+    #[test]
+    fn test_dispatcher_appends_to_log() {
+        let path = temp_path("alerts.jsonl");
+        fs::remove_file(&path).ok();
+        let config = NotifierConfig {
+            kind: NotifierKind::Log { path: path.display().to_string() },
+            min_severity: Severity::Info,
+            max_per_minute: 0,
+            dedup_secs: 60,
+        };
+        let dispatcher = NotificationDispatcher::start(&[config]).unwrap().unwrap();
+        dispatcher.dispatch("web-1", &[
+            event("cpu", AlertEventKind::Firing, 0),
+            event("cpu", AlertEventKind::Firing, 10),
+            event("cpu", AlertEventKind::Resolved, 20),
+        ]);
+        assert!(dispatcher.shutdown().is_empty());
+
+        let lines: Vec<Notification> = fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].event.kind, AlertEventKind::Resolved);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_notifier_config() {
+        let config: NotifierConfig = serde_json::from_str(
+            r#"{ "type": "webhook", "url": "http://localhost:9000/", "min_severity": "critical" }"#
+        ).unwrap();
+        assert!(matches!(config.kind, NotifierKind::Webhook { .. }));
+        assert_eq!(config.min_severity, Severity::Critical);
+        assert_eq!(config.max_per_minute, 6);
+        assert!(NotificationDispatcher::start(&[]).unwrap().is_none());
+    }
+}
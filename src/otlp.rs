@@ -0,0 +1,346 @@
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use crate::push::{send_with_retries, HttpTransport, Transport};
+use crate::snapshot::Snapshot;
+
+/// Snapshots waiting for the worker; newer ones are dropped while it is full.
+const QUEUE_CAPACITY: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OtlpConfig {
+    pub enabled: bool,
+    /// OTLP/HTTP metrics URL of the collector
+    pub endpoint: String,
+    /// e.g. `{"Authorization": "Bearer ..."}`
+    pub headers: BTreeMap<String, String>,
+    /// Added to `service.name`, `host.name` and `os.type`, e.g. `{"deployment.environment": "prod"}`
+    pub resource_attributes: BTreeMap<String, String>,
+    /// Snapshots collected before they are sent in one request
+    pub batch_size: usize,
+    /// Further attempts at a request that failed
+    pub retries: u32,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            enabled: false,
+            endpoint: "http://127.0.0.1:4318/v1/metrics".to_string(),
+            headers: BTreeMap::new(),
+            resource_attributes: BTreeMap::new(),
+            batch_size: 5,
+            retries: 3,
+        }
+    }
+}
+
+fn attribute(key: &str, value: impl Into<Value>) -> Value {
+    let value = match value.into() {
+        Value::String(text) => json!({ "stringValue": text }),
+        number => json!({ "intValue": number.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn double_point(time: &str, attributes: Vec<Value>, value: f64) -> Value {
+    json!({ "attributes": attributes, "timeUnixNano": time, "asDouble": value })
+}
+
+fn int_gauge_point(time: &str, attributes: Vec<Value>, value: u64) -> Value {
+    json!({ "attributes": attributes, "timeUnixNano": time, "asInt": value.to_string() })
+}
+
+fn int_point(start: &str, time: &str, attributes: Vec<Value>, value: u64) -> Value {
+    json!({ "attributes": attributes, "startTimeUnixNano": start, "timeUnixNano": time, "asInt": value.to_string() })
+}
+
+fn gauge(name: &str, unit: &str, description: &str, points: Vec<Value>) -> Value {
+    json!({ "name": name, "unit": unit, "description": description, "gauge": { "dataPoints": points } })
+}
+
+/// A cumulative sum; `monotonic` for counters, not for usage that goes up and down.
+fn sum(name: &str, unit: &str, description: &str, monotonic: bool, points: Vec<Value>) -> Value {
+    json!({
+        "name": name,
+        "unit": unit,
+        "description": description,
+        "sum": { "aggregationTemporality": 2, "isMonotonic": monotonic, "dataPoints": points },
+    })
+}
+
+/// One snapshot as an OTLP `ResourceMetrics`, using the OpenTelemetry
+/// semantic convention names of the host metrics receiver.
+pub fn resource_metrics(snapshot: &Snapshot, resource_attributes: &BTreeMap<String, String>) -> Value {
+    let time = snapshot.timestamp.timestamp_nanos_opt().unwrap_or_default().to_string();
+    // Counters and usage sums start at boot
+    let boot = (snapshot.timestamp - chrono::Duration::seconds(snapshot.uptime as i64)).timestamp_nanos_opt().unwrap_or_default().to_string();
+    let cores = snapshot.cpu_cores.len().max(1) as f64;
+
+    let mut attributes = vec![
+        attribute("service.name", "grainx"),
+        attribute("host.name", snapshot.hostname.as_str()),
+        attribute("os.type", snapshot.os_name.to_lowercase()),
+    ];
+    attributes.extend(resource_attributes.iter().map(|(k, v)| attribute(k, v.as_str())));
+
+    let mut metrics = vec![
+        gauge("system.cpu.utilization", "1", "CPU usage per logical CPU.", snapshot.cpu_cores.iter()
+            .enumerate()
+            .map(|(core, usage)| double_point(&time, vec![attribute("cpu.logical_number", core as u64)], *usage as f64 / 100.0))
+            .collect()),
+        gauge("system.cpu.logical.count", "{cpu}", "Logical CPUs.", vec![int_gauge_point(&time, vec![], snapshot.cpu_cores.len() as u64)]),
+    ];
+    for (period, load) in ["1m", "5m", "15m"].iter().zip(snapshot.load_average) {
+        metrics.push(gauge(&format!("system.cpu.load_average.{}", period), "{thread}", "Load average.", vec![double_point(&time, vec![], load)]));
+    }
+    metrics.push(gauge("system.uptime", "s", "Time since boot.", vec![double_point(&time, vec![], snapshot.uptime as f64)]));
+
+    metrics.push(sum("system.memory.usage", "By", "Memory in use and free.", false, vec![
+        int_point(&boot, &time, vec![attribute("system.memory.state", "used")], snapshot.used_memory),
+        int_point(&boot, &time, vec![attribute("system.memory.state", "free")], snapshot.total_memory.saturating_sub(snapshot.used_memory)),
+    ]));
+    metrics.push(gauge("system.memory.utilization", "1", "Share of memory in use.", vec![
+        double_point(&time, vec![attribute("system.memory.state", "used")], snapshot.memory_percentage() / 100.0),
+    ]));
+    metrics.push(sum("system.paging.usage", "By", "Swap in use and free.", false, vec![
+        int_point(&boot, &time, vec![attribute("system.paging.state", "used")], snapshot.used_swap),
+        int_point(&boot, &time, vec![attribute("system.paging.state", "free")], snapshot.total_swap.saturating_sub(snapshot.used_swap)),
+    ]));
+
+    metrics.push(sum("system.network.io", "By", "Bytes received and transmitted per interface.", true, snapshot.interfaces.iter()
+        .flat_map(|interface| [("receive", interface.total_received), ("transmit", interface.total_transmitted)]
+            .map(|(direction, bytes)| int_point(&boot, &time, vec![
+                attribute("network.interface.name", interface.name.as_str()),
+                attribute("network.io.direction", direction),
+            ], bytes)))
+        .collect()));
+
+    metrics.push(sum("system.filesystem.usage", "By", "Filesystem space in use and free per mount point.", false, snapshot.disks.iter()
+        .flat_map(|disk| [("used", disk.total.saturating_sub(disk.available)), ("free", disk.available)]
+            .map(|(state, bytes)| int_point(&boot, &time, vec![
+                attribute("system.device", disk.name.as_str()),
+                attribute("system.filesystem.mountpoint", disk.mount_point.as_str()),
+                attribute("system.filesystem.state", state),
+            ], bytes)))
+        .collect()));
+
+    let process = |pid: usize, name: &str| vec![attribute("process.pid", pid as u64), attribute("process.executable.name", name)];
+    metrics.push(gauge("process.cpu.utilization", "1", "CPU usage of the top processes, as a share of all CPUs.", snapshot.processes.iter()
+        .map(|(pid, name, cpu, _)| double_point(&time, process(*pid, name), *cpu as f64 / 100.0 / cores))
+        .collect()));
+    metrics.push(sum("process.memory.usage", "By", "Resident memory of the top processes.", false, snapshot.processes.iter()
+        .map(|(pid, name, _, memory)| int_point(&boot, &time, process(*pid, name), *memory))
+        .collect()));
+
+    json!({
+        "resource": { "attributes": attributes },
+        "scopeMetrics": [{
+            "scope": { "name": "grainx", "version": env!("CARGO_PKG_VERSION") },
+            "metrics": metrics,
+        }],
+    })
+}
+
+/// An `ExportMetricsServiceRequest` in the OTLP JSON encoding.
+pub fn export_request(snapshots: &[Snapshot], resource_attributes: &BTreeMap<String, String>) -> Value {
+    json!({ "resourceMetrics": snapshots.iter().map(|s| resource_metrics(s, resource_attributes)).collect::<Vec<_>>() })
+}
+
+/// Sends snapshots to an OTLP/HTTP collector on a background thread, in
+/// batches of `batch_size`.
+pub struct OtlpExporter {
+    sender: mpsc::SyncSender<Snapshot>,
+    failing: Arc<AtomicBool>,
+    /// Snapshots dropped because the queue was full
+    dropped: AtomicU64,
+    last_error: Arc<Mutex<Option<String>>>,
+    worker: JoinHandle<()>,
+}
+
+impl OtlpExporter {
+    pub fn start(config: &OtlpConfig) -> io::Result<Self> {
+        let mut transport = HttpTransport::new(&config.endpoint, config.headers.clone())?.with_content_type("application/json");
+        let (sender, receiver) = mpsc::sync_channel::<Snapshot>(QUEUE_CAPACITY);
+        let failing = Arc::new(AtomicBool::new(false));
+        let last_error = Arc::new(Mutex::new(None));
+
+        let worker = {
+            let (config, failing, last_error) = (config.clone(), failing.clone(), last_error.clone());
+            thread::spawn(move || {
+                let mut batch = Vec::new();
+                let mut send = |batch: &mut Vec<Snapshot>| {
+                    if batch.is_empty() {
+                        return;
+                    }
+                    let body = export_request(batch, &config.resource_attributes).to_string();
+                    batch.clear();
+                    let result = send_with_retries(&mut transport, &[body], config.retries);
+                    failing.store(result.is_err(), Ordering::Relaxed);
+                    if let Err(e) = result {
+                        *last_error.lock() = Some(format!("{}: {}", transport.describe(), e));
+                    }
+                };
+                for snapshot in receiver.iter() {
+                    batch.push(snapshot);
+                    if batch.len() >= config.batch_size.max(1) {
+                        send(&mut batch);
+                    }
+                }
+                // Exporter dropped: send the partial batch
+                send(&mut batch);
+            })
+        };
+
+        Ok(OtlpExporter { sender, failing, dropped: AtomicU64::new(0), last_error, worker })
+    }
+
+    pub fn export(&self, snapshot: &Snapshot) {
+        // A collector that cannot keep up must not grow memory; the worker
+        // only disconnects when the exporter is dropped
+        if let Err(mpsc::TrySendError::Full(_)) = self.sender.try_send(snapshot.clone()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Snapshots dropped so far because the collector could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.failing.load(Ordering::Relaxed)
+    }
+
+    /// Send everything already queued, then stop the worker. Returns the last failure, if any.
+    pub fn shutdown(self) -> Option<String> {
+        let OtlpExporter { sender, last_error, worker, .. } = self;
+        drop(sender);
+        worker.join().ok();
+        last_error.lock().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Handler, HttpServer, Request, Response};
+    use crate::snapshot::{sample, DiskSnapshot, InterfaceSnapshot};
+
+    fn snapshot(secs: i64) -> Snapshot {
+        Snapshot {
+            cpu_cores: vec![25.0, 75.0],
+            interfaces: vec![InterfaceSnapshot { name: "eth0".to_string(), received: 0, transmitted: 0, total_received: 1000, total_transmitted: 2000 }],
+            disks: vec![DiskSnapshot { name: "sda1".to_string(), mount_point: "/".to_string(), total: 100, available: 40 }],
+            processes: vec![(7, "postgres".to_string(), 50.0, 4096)],
+            ..sample(secs, 50.0)
+        }
+    }
+
+    fn metric<'a>(resource: &'a Value, name: &str) -> &'a Value {
+        resource["scopeMetrics"][0]["metrics"].as_array().unwrap()
+            .iter()
+            .find(|m| m["name"] == name)
+            .unwrap_or_else(|| panic!("{} missing", name))
+    }
+
+    #[test]
+    fn test_semantic_convention_metrics() {
+        let attributes = BTreeMap::from([("deployment.environment".to_string(), "prod".to_string())]);
+        let resource = resource_metrics(&snapshot(0), &attributes);
+
+        let resource_attributes = resource["resource"]["attributes"].as_array().unwrap();
+        assert!(resource_attributes.contains(&json!({ "key": "host.name", "value": { "stringValue": "web-1" } })));
+        assert!(resource_attributes.contains(&json!({ "key": "deployment.environment", "value": { "stringValue": "prod" } })));
+
+        let cpu = &metric(&resource, "system.cpu.utilization")["gauge"]["dataPoints"];
+        assert_eq!(cpu[1]["asDouble"], 0.75);
+        assert_eq!(cpu[1]["attributes"][0], json!({ "key": "cpu.logical_number", "value": { "intValue": "1" } }));
+        assert_eq!(cpu[1]["timeUnixNano"], "1700000000000000000");
+        assert_eq!(metric(&resource, "system.cpu.logical.count")["gauge"]["dataPoints"][0]["asInt"], "2");
+
+        let memory = &metric(&resource, "system.memory.usage")["sum"];
+        assert_eq!(memory["isMonotonic"], false);
+        assert_eq!(memory["dataPoints"][1]["asInt"], "3072");
+        assert_eq!(memory["dataPoints"][0]["startTimeUnixNano"], "1699999900000000000");
+
+        let network = &metric(&resource, "system.network.io")["sum"];
+        assert_eq!(network["isMonotonic"], true);
+        assert_eq!(network["dataPoints"].as_array().unwrap().len(), 2);
+        assert_eq!(network["dataPoints"][1]["asInt"], "2000");
+
+        let filesystem = &metric(&resource, "system.filesystem.usage")["sum"]["dataPoints"];
+        assert_eq!(filesystem[0]["asInt"], "60");
+        // 50% of one core on a two-core host
+        assert_eq!(metric(&resource, "process.cpu.utilization")["gauge"]["dataPoints"][0]["asDouble"], 0.25);
+    }
+
+    #[test]
+    fn test_export_to_mock_collector() {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let handler: Handler = {
+            let bodies = bodies.clone();
+            Arc::new(move |request: &Request| {
+                assert_eq!(request.header("content-type"), Some("application/json"));
+                bodies.lock().push((request.path.clone(), String::from_utf8_lossy(&request.body).to_string()));
+                Response::json(200, &json!({}))
+            })
+        };
+        let server = HttpServer::start("127.0.0.1:0", handler).unwrap();
+        let config = OtlpConfig {
+            enabled: true,
+            endpoint: format!("http://{}/v1/metrics", server.local_addr()),
+            batch_size: 2,
+            retries: 0,
+            ..OtlpConfig::default()
+        };
+        let exporter = OtlpExporter::start(&config).unwrap();
+        for s in 0..3 {
+            exporter.export(&snapshot(s));
+        }
+        assert_eq!(exporter.shutdown(), None);
+
+        let bodies = bodies.lock();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].0, "/v1/metrics");
+        let first: Value = serde_json::from_str(&bodies[0].1).unwrap();
+        assert_eq!(first["resourceMetrics"].as_array().unwrap().len(), 2);
+        let second: Value = serde_json::from_str(&bodies[1].1).unwrap();
+        assert_eq!(second["resourceMetrics"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_unreachable_collector() {
+        let config = OtlpConfig { enabled: true, endpoint: "http://127.0.0.1:1/v1/metrics".to_string(), retries: 0, ..OtlpConfig::default() };
+        let exporter = OtlpExporter::start(&config).unwrap();
+        exporter.export(&snapshot(0));
+        assert!(exporter.shutdown().unwrap().contains("127.0.0.1:1"));
+        assert!(OtlpExporter::start(&OtlpConfig { endpoint: "https://collector".to_string(), ..OtlpConfig::default() }).is_err());
+    }
+
+    #[test]
+    fn test_full_queue_drops_snapshots() {
+        // Accepts nothing, so the first request hangs until the listener goes away
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = OtlpConfig {
+            enabled: true,
+            endpoint: format!("http://{}/v1/metrics", listener.local_addr().unwrap()),
+            batch_size: 1,
+            retries: 0,
+            ..OtlpConfig::default()
+        };
+        let exporter = OtlpExporter::start(&config).unwrap();
+        for s in 0..QUEUE_CAPACITY as i64 + 10 {
+            exporter.export(&snapshot(s));
+        }
+        assert!(exporter.dropped() >= 9);
+        drop(listener);
+        exporter.shutdown();
+    }
+}
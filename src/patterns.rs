@@ -0,0 +1,335 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use crate::events::EventKind;
+use crate::history::MetricHistory;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PatternConfig {
+    pub enabled: bool,
+    /// Metric series checked for patterns
+    pub metrics: Vec<String>,
+    /// Most recent samples examined on each check
+    pub window: usize,
+    /// Cycles shorter than this are treated as noise
+    pub min_period_secs: f64,
+    /// A series is "high" once it rises to `flap_high` and "low" once it falls
+    /// to `flap_low`; values in between keep the previous state
+    pub flap_high: f64,
+    pub flap_low: f64,
+    /// High/low changes within the window needed to call a series flapping
+    pub flap_min_changes: usize,
+    /// Smallest sudden drop that counts as a sawtooth reset
+    pub sawtooth_min_drop: f64,
+    pub sawtooth_min_cycles: usize,
+    /// Smallest level shift reported as a step
+    pub step_min_change: f64,
+    /// The same pattern on the same metric is reported at most once per cooldown
+    pub cooldown_secs: u64,
+}
+
+impl Default for PatternConfig {
+    fn default() -> Self {
+        PatternConfig {
+            enabled: true,
+            metrics: vec!["cpu".to_string(), "mem_pct".to_string()],
+            window: 60,
+            min_period_secs: 3.0,
+            flap_high: 70.0,
+            flap_low: 50.0,
+            flap_min_changes: 4,
+            sawtooth_min_drop: 10.0,
+            sawtooth_min_cycles: 3,
+            step_min_change: 20.0,
+            cooldown_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    /// Repeated swings between high and low
+    Flapping,
+    /// Slow climbs ended by sudden drops, e.g. garbage collection
+    Sawtooth,
+    /// A lasting shift to a new level
+    Step,
+}
+
+impl PatternKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PatternKind::Flapping => "flapping",
+            PatternKind::Sawtooth => "sawtooth",
+            PatternKind::Step => "step",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    /// Length of one cycle; steps have none
+    pub period_secs: Option<f64>,
+    /// Swing size for cycles; for steps the signed shift in level
+    pub amplitude: f64,
+}
+
+type Sample = (DateTime<Utc>, f64);
+
+fn secs_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn sum_sq_dev(values: &[f64]) -> f64 {
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum()
+}
+
+/// Repeated changes between high and low, with hysteresis between `low` and `high`.
+pub fn detect_flapping(samples: &[Sample], low: f64, high: f64, min_changes: usize, min_period_secs: f64) -> Option<Pattern> {
+    let mut state = None;
+    let mut changes = Vec::new();
+    let (mut highs, mut lows) = (Vec::new(), Vec::new());
+    for &(time, value) in samples {
+        let next = if value >= high {
+            Some(true)
+        } else if value <= low {
+            Some(false)
+        } else {
+            state
+        };
+        if state.is_some() && next != state {
+            changes.push(time);
+        }
+        state = next;
+        match state {
+            Some(true) if value >= high => highs.push(value),
+            Some(false) if value <= low => lows.push(value),
+            _ => {}
+        }
+    }
+
+    if changes.len() < min_changes.max(2) {
+        return None;
+    }
+    // Two changes make one full cycle
+    let period = 2.0 * secs_between(changes[0], changes[changes.len() - 1]) / (changes.len() - 1) as f64;
+    if period < min_period_secs {
+        return None;
+    }
+    Some(Pattern { kind: PatternKind::Flapping, period_secs: Some(period), amplitude: mean(&highs) - mean(&lows) })
+}
+
+/// Gradual climbs that end in sudden drops of at least `min_drop`, at a regular interval.
+pub fn detect_sawtooth(samples: &[Sample], min_drop: f64, min_cycles: usize, min_period_secs: f64) -> Option<Pattern> {
+    let resets: Vec<usize> = (1..samples.len())
+        .filter(|&i| samples[i - 1].1 - samples[i].1 >= min_drop)
+        .collect();
+    if resets.len() < min_cycles.max(2) {
+        return None;
+    }
+
+    // Each stretch between two resets has to climb back gradually; a single
+    // jump up is a square wave, which is flapping rather than a sawtooth
+    for pair in resets.windows(2) {
+        let climb = &samples[pair[0]..pair[1]];
+        if climb.len() < 3 {
+            return None;
+        }
+        let rise = climb[climb.len() - 1].1 - climb[0].1;
+        let largest_step = climb.windows(2).map(|w| w[1].1 - w[0].1).fold(f64::MIN, f64::max);
+        if rise < min_drop / 2.0 || largest_step > rise / 2.0 {
+            return None;
+        }
+    }
+
+    let intervals: Vec<f64> = resets.windows(2)
+        .map(|pair| secs_between(samples[pair[0]].0, samples[pair[1]].0))
+        .collect();
+    let period = mean(&intervals);
+    if period < min_period_secs || intervals.iter().any(|i| (i - period).abs() > period / 2.0) {
+        return None;
+    }
+
+    let drops: Vec<f64> = resets.iter().map(|&i| samples[i - 1].1 - samples[i].1).collect();
+    Some(Pattern { kind: PatternKind::Sawtooth, period_secs: Some(period), amplitude: mean(&drops) })
+}
+
+/// A shift of at least `min_change` between two otherwise steady levels, each
+/// held for at least `min_period_secs`.
+pub fn detect_step(samples: &[Sample], min_change: f64, min_period_secs: f64) -> Option<Pattern> {
+    const MIN_SIDE: usize = 3;
+    if samples.len() < 2 * MIN_SIDE {
+        return None;
+    }
+    let values: Vec<f64> = samples.iter().map(|(_, v)| *v).collect();
+
+    let (split, shift) = (MIN_SIDE..=values.len() - MIN_SIDE)
+        .filter(|&k| secs_between(samples[0].0, samples[k - 1].0) >= min_period_secs
+            && secs_between(samples[k].0, samples[samples.len() - 1].0) >= min_period_secs)
+        .map(|k| (k, mean(&values[k..]) - mean(&values[..k])))
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+
+    // The levels on either side must be steady compared to the shift, which
+    // keeps flapping and sawtooth series from looking like steps
+    let noise = ((sum_sq_dev(&values[..split]) + sum_sq_dev(&values[split..])) / (values.len() - 2) as f64).sqrt();
+    if shift.abs() < min_change || shift.abs() < 4.0 * noise {
+        return None;
+    }
+    Some(Pattern { kind: PatternKind::Step, period_secs: None, amplitude: shift })
+}
+
+/// Every pattern `config` asks for that is present in `samples`.
+pub fn detect(samples: &[Sample], config: &PatternConfig) -> Vec<Pattern> {
+    [
+        detect_flapping(samples, config.flap_low, config.flap_high, config.flap_min_changes, config.min_period_secs),
+        detect_sawtooth(samples, config.sawtooth_min_drop, config.sawtooth_min_cycles, config.min_period_secs),
+        detect_step(samples, config.step_min_change, config.min_period_secs),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Runs pattern detection over the recorded history, reporting each
+/// pattern once per metric until its cooldown runs out.
+pub struct PatternDetector {
+    pub config: PatternConfig,
+    last_reported: HashMap<(String, PatternKind), DateTime<Utc>>,
+}
+
+impl PatternDetector {
+    pub fn new(config: PatternConfig) -> Self {
+        PatternDetector { config, last_reported: HashMap::new() }
+    }
+
+    pub fn check(&mut self, history: &MetricHistory, now: DateTime<Utc>) -> Vec<EventKind> {
+        let cooldown = chrono::Duration::seconds(self.config.cooldown_secs as i64);
+        let mut events = Vec::new();
+        for metric in &self.config.metrics {
+            let Some(series) = history.samples(metric) else { continue };
+            let samples: Vec<Sample> = series.iter().skip(series.len().saturating_sub(self.config.window)).copied().collect();
+
+            for pattern in detect(&samples, &self.config) {
+                let key = (metric.clone(), pattern.kind);
+                if self.last_reported.get(&key).is_some_and(|last| now - *last < cooldown) {
+                    continue;
+                }
+                self.last_reported.insert(key, now);
+                events.push(EventKind::Pattern {
+                    metric: metric.clone(),
+                    pattern: pattern.kind,
+                    period_secs: pattern.period_secs,
+                    amplitude: pattern.amplitude,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: impl IntoIterator<Item = f64>) -> Vec<Sample> {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        values.into_iter()
+            .enumerate()
+            .map(|(i, v)| (start + chrono::Duration::seconds(i as i64), v))
+            .collect()
+    }
+
+    fn square(high: f64, low: f64, half_period: usize, n: usize) -> Vec<f64> {
+        (0..n).map(|i| if (i / half_period).is_multiple_of(2) { high } else { low }).collect()
+    }
+
+    fn sawtooth(n: usize) -> Vec<f64> {
+        // Climbs 30 -> 66 over 10s, then drops straight back
+        (0..n).map(|i| 30.0 + 4.0 * (i % 10) as f64).collect()
+    }
+
+    #[test]
+    fn test_flapping() {
+        let pattern = detect_flapping(&series(square(90.0, 20.0, 3, 30)), 50.0, 70.0, 4, 3.0).unwrap();
+        assert_eq!(pattern.kind, PatternKind::Flapping);
+        assert_eq!(pattern.period_secs, Some(6.0));
+        assert_eq!(pattern.amplitude, 70.0);
+
+        // Wobbling inside the hysteresis band is not a state change
+        let calm: Vec<f64> = (0..30).map(|i| if i % 2 == 0 { 55.0 } else { 65.0 }).collect();
+        assert!(detect_flapping(&series(calm), 50.0, 70.0, 4, 3.0).is_none());
+    }
+
+    #[test]
+    fn test_flapping_min_period() {
+        // Alternating every sample is a 2s cycle, under the 3s minimum
+        let jitter = series(square(90.0, 20.0, 1, 30));
+        assert!(detect_flapping(&jitter, 50.0, 70.0, 4, 3.0).is_none());
+        assert!(detect_flapping(&jitter, 50.0, 70.0, 4, 2.0).is_some());
+    }
+
+    #[test]
+    fn test_high_low_high_is_found_in_longer_history() {
+        // The old check only looked at exactly three readings
+        let values = [30.0, 30.0, 80.0, 80.0, 80.0, 30.0, 30.0, 30.0, 80.0, 80.0, 80.0, 30.0, 30.0, 30.0, 80.0];
+        let pattern = detect_flapping(&series(values), 50.0, 70.0, 4, 3.0).unwrap();
+        assert_eq!(pattern.period_secs, Some(6.0));
+    }
+
+    #[test]
+    fn test_sawtooth() {
+        let pattern = detect_sawtooth(&series(sawtooth(45)), 10.0, 3, 3.0).unwrap();
+        assert_eq!(pattern.kind, PatternKind::Sawtooth);
+        assert_eq!(pattern.period_secs, Some(10.0));
+        assert_eq!(pattern.amplitude, 36.0);
+
+        // A square wave drops just as suddenly but also jumps back up
+        assert!(detect_sawtooth(&series(square(90.0, 20.0, 5, 45)), 10.0, 3, 3.0).is_none());
+    }
+
+    #[test]
+    fn test_step() {
+        let mut values = vec![20.0; 15];
+        values.extend(vec![60.0; 15]);
+        let pattern = detect_step(&series(values.clone()), 20.0, 3.0).unwrap();
+        assert_eq!(pattern.kind, PatternKind::Step);
+        assert_eq!(pattern.amplitude, 40.0);
+
+        values.reverse();
+        assert_eq!(detect_step(&series(values), 20.0, 3.0).unwrap().amplitude, -40.0);
+        assert!(detect_step(&series(vec![20.0; 30]), 20.0, 3.0).is_none());
+    }
+
+    #[test]
+    fn test_patterns_do_not_overlap() {
+        let config = PatternConfig::default();
+        let kinds = |values: Vec<f64>| detect(&series(values), &config).into_iter().map(|p| p.kind).collect::<Vec<_>>();
+        assert_eq!(kinds(square(90.0, 20.0, 4, 40)), vec![PatternKind::Flapping]);
+        assert_eq!(kinds(sawtooth(45)), vec![PatternKind::Sawtooth]);
+    }
+
+    #[test]
+    fn test_detector_cooldown() {
+        let mut history = MetricHistory::new(100);
+        let samples = series(square(90.0, 20.0, 3, 30));
+        for (time, value) in &samples {
+            history.record("cpu", *time, *value);
+        }
+        let now = samples[samples.len() - 1].0;
+
+        let mut detector = PatternDetector::new(PatternConfig::default());
+        let events = detector.check(&history, now);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], EventKind::Pattern { metric, pattern: PatternKind::Flapping, .. } if metric == "cpu"));
+        assert!(detector.check(&history, now + chrono::Duration::seconds(10)).is_empty());
+        assert_eq!(detector.check(&history, now + chrono::Duration::seconds(300)).len(), 1);
+    }
+}
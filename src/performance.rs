@@ -66,7 +66,7 @@ impl PerformanceMonitor {
         let avg_cpu = self.cpu_load_history.iter().sum::<f32>() / self.cpu_load_history.len() as f32;
         
         // Adaptive refresh based on system load
-        let refresh_ms = if avg_cpu > 90.0 {
+        if avg_cpu > 90.0 {
             2000 // Very slow refresh when system is overloaded
         } else if avg_cpu > 70.0 {
             1000 // Normal refresh
@@ -74,9 +74,7 @@ impl PerformanceMonitor {
             500  // Fast refresh
         } else {
             250  // Very fast refresh when system is idle
-        };
-
-        refresh_ms
+        }
     }
 
     pub fn should_skip_frame(&self, cpu_usage: f32) -> bool {
@@ -91,6 +89,22 @@ impl PerformanceMonitor {
     pub fn toggle_adaptive_refresh(&mut self) {
         self.adaptive_refresh = !self.adaptive_refresh;
     }
+
+    /// Refresh every `interval_ms`, or adapt to the system load on `None`.
+    pub fn set_refresh_interval(&mut self, interval_ms: Option<u64>) {
+        match interval_ms {
+            Some(interval_ms) => {
+                self.target_fps = 1000.0 / interval_ms.max(1) as f64;
+                self.adaptive_refresh = false;
+            }
+            None => self.adaptive_refresh = true,
+        }
+    }
+
+    /// The fixed refresh interval, `None` while it adapts to the load.
+    pub fn refresh_interval(&self) -> Option<u64> {
+        (!self.adaptive_refresh).then(|| (1000.0 / self.target_fps).round() as u64)
+    }
 }
 
 // Memory pool for reducing allocations
@@ -151,7 +165,6 @@ impl StringCache {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
 
     #[test]
     fn test_performance_monitor_creation() {
@@ -174,6 +187,16 @@ mod tests {
         assert!(refresh_time <= 500); // Should be fast when CPU is low
     }
 
+    #[test]
+    fn test_fixed_refresh_interval() {
+        let mut perf = PerformanceMonitor::new(60.0);
+        perf.set_refresh_interval(Some(300));
+        assert_eq!(perf.refresh_interval(), Some(300));
+        assert!(perf.calculate_adaptive_refresh(95.0).abs_diff(300) <= 1);
+        perf.set_refresh_interval(None);
+        assert_eq!(perf.refresh_interval(), None);
+    }
+
     #[test]
     fn test_frame_skipping() {
         let perf = PerformanceMonitor::new(60.0);
@@ -0,0 +1,240 @@
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use crate::alerts::{AlertEngine, AlertState};
+use crate::custom_metrics::CustomMetricValue;
+use crate::http::{Handler, HttpServer, Request, Response};
+use crate::snapshot::Snapshot;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PrometheusConfig {
+    pub enabled: bool,
+    /// Address the `/metrics` listener binds to
+    pub listen: String,
+}
+
+impl Default for PrometheusConfig {
+    fn default() -> Self {
+        PrometheusConfig { enabled: false, listen: "127.0.0.1:9184".to_string() }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Builds one exposition document, keeping the samples of a metric family together.
+struct Exposition {
+    text: String,
+    labels: Vec<(&'static str, String)>,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        let all = self.labels.iter().map(|(k, v)| (*k, v.as_str())).chain(labels.iter().copied());
+        let rendered: Vec<String> = all.map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v))).collect();
+        if !rendered.is_empty() {
+            let _ = write!(self.text, "{{{}}}", rendered.join(","));
+        }
+        let value = if value.is_nan() {
+            "NaN".to_string()
+        } else if value.is_infinite() {
+            if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+        } else {
+            value.to_string()
+        };
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// Render everything grainx knows about this tick in the Prometheus text format.
+pub fn render(snapshot: &Snapshot, custom_values: &[CustomMetricValue], alerts: &AlertEngine) -> String {
+    let mut out = Exposition { text: String::new(), labels: vec![("host", snapshot.hostname.clone())] };
+
+    out.gauge("grainx_cpu_usage_percent", "Global CPU usage.", snapshot.cpu_usage as f64);
+    out.family("grainx_cpu_core_usage_percent", "gauge", "CPU usage per core.");
+    for (core, usage) in snapshot.cpu_cores.iter().enumerate() {
+        out.sample("grainx_cpu_core_usage_percent", &[("core", &core.to_string())], *usage as f64);
+    }
+    out.family("grainx_load_average", "gauge", "System load average.");
+    for (period, load) in ["1m", "5m", "15m"].iter().zip(snapshot.load_average) {
+        out.sample("grainx_load_average", &[("period", period)], load);
+    }
+    out.gauge("grainx_uptime_seconds", "System uptime.", snapshot.uptime as f64);
+
+    out.gauge("grainx_memory_used_bytes", "Used memory.", snapshot.used_memory as f64);
+    out.gauge("grainx_memory_total_bytes", "Total memory.", snapshot.total_memory as f64);
+    out.gauge("grainx_swap_used_bytes", "Used swap.", snapshot.used_swap as f64);
+    out.gauge("grainx_swap_total_bytes", "Total swap.", snapshot.total_swap as f64);
+
+    out.family("grainx_network_receive_bytes_total", "counter", "Bytes received per interface.");
+    for interface in &snapshot.interfaces {
+        out.sample("grainx_network_receive_bytes_total", &[("interface", &interface.name)], interface.total_received as f64);
+    }
+    out.family("grainx_network_transmit_bytes_total", "counter", "Bytes transmitted per interface.");
+    for interface in &snapshot.interfaces {
+        out.sample("grainx_network_transmit_bytes_total", &[("interface", &interface.name)], interface.total_transmitted as f64);
+    }
+
+    out.family("grainx_disk_total_bytes", "gauge", "Disk size per mount point.");
+    for disk in &snapshot.disks {
+        out.sample("grainx_disk_total_bytes", &[("device", &disk.name), ("mount", &disk.mount_point)], disk.total as f64);
+    }
+    out.family("grainx_disk_available_bytes", "gauge", "Available disk space per mount point.");
+    for disk in &snapshot.disks {
+        out.sample("grainx_disk_available_bytes", &[("device", &disk.name), ("mount", &disk.mount_point)], disk.available as f64);
+    }
+    out.gauge("grainx_disk_read_bytes", "Bytes read by all processes since the previous sample.", snapshot.disk_read as f64);
+    out.gauge("grainx_disk_written_bytes", "Bytes written by all processes since the previous sample.", snapshot.disk_written as f64);
+
+    out.family("grainx_process_cpu_percent", "gauge", "CPU usage of the top processes.");
+    for (pid, name, cpu, _) in &snapshot.processes {
+        out.sample("grainx_process_cpu_percent", &[("pid", &pid.to_string()), ("name", name)], *cpu as f64);
+    }
+    out.family("grainx_process_memory_bytes", "gauge", "Memory of the top processes.");
+    for (pid, name, _, memory) in &snapshot.processes {
+        out.sample("grainx_process_memory_bytes", &[("pid", &pid.to_string()), ("name", name)], *memory as f64);
+    }
+
+    out.family("grainx_custom_metric", "gauge", "Custom formula metrics from the dashboard config.");
+    for value in custom_values {
+        if let Ok(v) = value.value {
+            out.sample("grainx_custom_metric", &[("name", &value.name)], v);
+        }
+    }
+
+    out.family("grainx_alert_state", "gauge", "1 for the state each alert rule is in.");
+    for rule in &alerts.rules {
+        let current = match rule.state {
+            AlertState::Inactive | AlertState::Resolved { .. } => "inactive",
+            AlertState::Pending { .. } => "pending",
+            AlertState::Firing { .. } => "firing",
+        };
+        for state in ["inactive", "pending", "firing"] {
+            let labels = [("rule", rule.config.display_name()), ("severity", rule.config.severity.label()), ("state", state)];
+            out.sample("grainx_alert_state", &labels, if state == current { 1.0 } else { 0.0 });
+        }
+    }
+
+    out.text
+}
+
+/// Serves the latest rendered exposition on `/metrics`.
+pub struct PrometheusExporter {
+    latest: Arc<Mutex<String>>,
+    server: HttpServer,
+}
+
+impl PrometheusExporter {
+    pub fn start(config: &PrometheusConfig) -> io::Result<Self> {
+        let latest = Arc::new(Mutex::new(String::new()));
+        let handler: Handler = {
+            let latest = latest.clone();
+            Arc::new(move |request: &Request| match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/metrics") => Response::new(200, "text/plain; version=0.0.4; charset=utf-8", latest.lock().clone()),
+                (_, "/metrics") => Response::text(405, "only GET is supported\n"),
+                _ => Response::not_found(),
+            })
+        };
+        let server = HttpServer::start(&config.listen, handler)?;
+        Ok(PrometheusExporter { latest, server })
+    }
+
+    pub fn publish(&self, exposition: String) {
+        *self.latest.lock() = exposition;
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+
+    /// Why accepting connections is currently failing, if it is.
+    pub fn last_error(&self) -> Option<String> {
+        self.server.last_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertRuleConfig, Severity};
+    use crate::custom_metrics::ThresholdLevel;
+    use crate::snapshot::{sample, DiskSnapshot, InterfaceSnapshot};
+    use std::collections::BTreeMap;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            cpu_cores: vec![40.0, 45.0],
+            interfaces: vec![InterfaceSnapshot { name: "eth0".to_string(), received: 10, transmitted: 20, total_received: 1000, total_transmitted: 2000 }],
+            load_average: [0.5, 0.25, 0.125],
+            disks: vec![DiskSnapshot { name: "sda1".to_string(), mount_point: "/".to_string(), total: 100, available: 40 }],
+            processes: vec![(7, "my \"app\"".to_string(), 12.0, 4096)],
+            ..sample(0, 42.5)
+        }
+    }
+
+    fn alerts() -> AlertEngine {
+        let mut engine = AlertEngine::new(&[AlertRuleConfig {
+            name: "Hot".to_string(),
+            condition: "cpu > 40".to_string(),
+            severity: Severity::Critical,
+            clear: None,
+        }]);
+        let metrics = BTreeMap::from([("cpu".to_string(), 42.5)]);
+        engine.evaluate(&metrics, snapshot().timestamp);
+        engine
+    }
+
+    #[test]
+    fn test_render_exposition() {
+        let custom = vec![CustomMetricValue { name: "cpu_x2".to_string(), value: Ok(85.0), level: ThresholdLevel::Normal }];
+        let text = render(&snapshot(), &custom, &alerts());
+
+        assert!(text.contains("# TYPE grainx_cpu_usage_percent gauge\ngrainx_cpu_usage_percent{host=\"web-1\"} 42.5\n"));
+        assert!(text.contains("grainx_cpu_core_usage_percent{host=\"web-1\",core=\"1\"} 45\n"));
+        assert!(text.contains("grainx_network_receive_bytes_total{host=\"web-1\",interface=\"eth0\"} 1000\n"));
+        assert!(text.contains("grainx_disk_available_bytes{host=\"web-1\",device=\"sda1\",mount=\"/\"} 40\n"));
+        assert!(text.contains("grainx_process_cpu_percent{host=\"web-1\",pid=\"7\",name=\"my \\\"app\\\"\"} 12\n"));
+        assert!(text.contains("grainx_custom_metric{host=\"web-1\",name=\"cpu_x2\"} 85\n"));
+        assert!(text.contains("grainx_alert_state{host=\"web-1\",rule=\"Hot\",severity=\"critical\",state=\"firing\"} 1\n"));
+        assert!(text.contains("grainx_alert_state{host=\"web-1\",rule=\"Hot\",severity=\"critical\",state=\"inactive\"} 0\n"));
+        assert!(text.contains("grainx_load_average{host=\"web-1\",period=\"15m\"} 0.125\n"));
+    }
+
+    #[test]
+    fn test_every_sample_has_a_type() {
+        let text = render(&snapshot(), &[], &alerts());
+        let typed: Vec<&str> = text.lines().filter_map(|line| line.strip_prefix("# TYPE ")).filter_map(|rest| rest.split(' ').next()).collect();
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            assert!(typed.contains(&name), "{} has no TYPE line", name);
+        }
+    }
+
+    #[test]
+    fn test_scrape_over_http() {
+        let exporter = PrometheusExporter::start(&PrometheusConfig { enabled: true, listen: "127.0.0.1:0".to_string() }).unwrap();
+        exporter.publish(render(&snapshot(), &[], &alerts()));
+
+        let (status, body) = crate::http::request(exporter.local_addr(), "GET", "/metrics", &[], b"").unwrap();
+        assert_eq!(status, 200);
+        assert!(body.contains("grainx_memory_total_bytes{host=\"web-1\"} 4096"));
+        assert_eq!(crate::http::request(exporter.local_addr(), "POST", "/metrics", &[], b"").unwrap().0, 405);
+        assert_eq!(crate::http::request(exporter.local_addr(), "GET", "/", &[], b"").unwrap().0, 404);
+    }
+}
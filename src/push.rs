@@ -0,0 +1,561 @@
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::http::{self, Url};
+
+/// Largest UDP datagram sent; stays under a typical 1500 byte MTU.
+const MAX_DATAGRAM: usize = 1400;
+/// Delivery failures kept for display.
+const MAX_FAILURES: usize = 10;
+/// Samples waiting for the worker; newer ones are dropped while it is full.
+const QUEUE_CAPACITY: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PushKind {
+    /// InfluxDB line protocol POSTed to a write URL, e.g.
+    /// `http://localhost:8086/api/v2/write?org=ops&bucket=grainx`
+    InfluxHttp {
+        url: String,
+        /// e.g. `{"Authorization": "Token ..."}`
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// InfluxDB line protocol in UDP datagrams
+    InfluxUdp { address: String },
+    /// StatsD gauges in UDP datagrams. Tags are only sent with `dogstatsd`.
+    Statsd {
+        address: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+        #[serde(default)]
+        dogstatsd: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PushConfig {
+    #[serde(flatten)]
+    pub kind: PushKind,
+    /// Added to every point next to `host`, e.g. `{"env": "prod"}`
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Metrics starting with any of these prefixes are not sent
+    #[serde(default = "default_skip")]
+    pub skip: Vec<String>,
+    /// Samples collected before they are sent together
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Further attempts at a batch that failed to send, with growing pauses in between
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_prefix() -> String {
+    "grainx".to_string()
+}
+
+fn default_skip() -> Vec<String> {
+    vec!["proc_cpu[".to_string(), "proc_mem[".to_string()]
+}
+
+fn default_batch_size() -> usize {
+    10
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// One tick's metrics, tagged with the host they were taken on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub timestamp: DateTime<Utc>,
+    pub tags: BTreeMap<String, String>,
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// Split `disk[/var]` into `("disk", Some("/var"))`.
+fn split_keyed(metric: &str) -> (&str, Option<&str>) {
+    match metric.strip_suffix(']').and_then(|rest| rest.split_once('[')) {
+        Some((family, key)) => (family, Some(key)),
+        None => (metric, None),
+    }
+}
+
+fn influx_escape(text: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn influx_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .map(|(k, v)| format!(",{}={}", influx_escape(k, &[',', '=', ' ']), influx_escape(v, &[',', '=', ' '])))
+        .collect()
+}
+
+/// InfluxDB line protocol: plain metrics become fields of one `grainx` line,
+/// each keyed metric a `grainx_<family>` line with the key as a tag.
+pub fn encode_influx(sample: &Sample) -> Vec<String> {
+    let timestamp = sample.timestamp.timestamp_nanos_opt().unwrap_or_default();
+    let tags = influx_tags(&sample.tags);
+    let mut fields = Vec::new();
+    let mut lines = Vec::new();
+    for (metric, value) in sample.metrics.iter().filter(|(_, v)| v.is_finite()) {
+        match split_keyed(metric) {
+            (name, None) => fields.push(format!("{}={}", influx_escape(name, &[',', '=', ' ']), value)),
+            (family, Some(key)) => lines.push(format!("grainx_{}{},key={} value={} {}",
+                influx_escape(family, &[',', ' ']), tags, influx_escape(key, &[',', '=', ' ']), value, timestamp)),
+        }
+    }
+    if !fields.is_empty() {
+        lines.insert(0, format!("grainx{} {} {}", tags, fields.join(","), timestamp));
+    }
+    lines
+}
+
+fn statsd_name(text: &str) -> String {
+    text.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+/// StatsD gauges, `<prefix>.<metric>:<value>|g`. DogStatsD lines carry the
+/// tags and the key of keyed metrics as `|#tag:value`; plain StatsD folds the key into the name.
+pub fn encode_statsd(sample: &Sample, prefix: &str, dogstatsd: bool) -> Vec<String> {
+    let tags: Vec<String> = sample.tags.iter().map(|(k, v)| format!("{}:{}", statsd_name(k), v.replace(['|', ',', '#'], "_"))).collect();
+    sample.metrics.iter()
+        .filter(|(_, v)| v.is_finite())
+        .map(|(metric, value)| {
+            let (family, key) = split_keyed(metric);
+            if dogstatsd {
+                let mut tags = tags.clone();
+                if let Some(key) = key {
+                    tags.push(format!("key:{}", key.replace(['|', ',', '#'], "_")));
+                }
+                let tags = if tags.is_empty() { String::new() } else { format!("|#{}", tags.join(",")) };
+                format!("{}.{}:{}|g{}", prefix, statsd_name(family), value, tags)
+            } else {
+                match key {
+                    Some(key) => format!("{}.{}.{}:{}|g", prefix, statsd_name(family), statsd_name(key), value),
+                    None => format!("{}.{}:{}|g", prefix, statsd_name(family), value),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Pack newline-separated lines into payloads of at most `limit` bytes.
+/// A single line longer than the limit gets a payload of its own.
+pub fn pack(lines: &[String], limit: usize) -> Vec<String> {
+    let mut payloads: Vec<String> = Vec::new();
+    for line in lines {
+        match payloads.last_mut() {
+            Some(payload) if payload.len() + 1 + line.len() <= limit => {
+                payload.push('\n');
+                payload.push_str(line);
+            }
+            _ => payloads.push(line.clone()),
+        }
+    }
+    payloads
+}
+
+pub trait Transport: Send {
+    fn describe(&self) -> String;
+    fn send(&mut self, lines: &[String]) -> io::Result<()>;
+}
+
+pub struct HttpTransport {
+    url: Url,
+    headers: BTreeMap<String, String>,
+    content_type: String,
+}
+
+impl HttpTransport {
+    pub fn new(url: &str, headers: BTreeMap<String, String>) -> io::Result<Self> {
+        Ok(HttpTransport { url: Url::parse(url)?, headers, content_type: "text/plain; charset=utf-8".to_string() })
+    }
+
+    pub fn with_content_type(mut self, content_type: &str) -> Self {
+        self.content_type = content_type.to_string();
+        self
+    }
+}
+
+impl Transport for HttpTransport {
+    fn describe(&self) -> String {
+        self.url.to_string()
+    }
+
+    fn send(&mut self, lines: &[String]) -> io::Result<()> {
+        http::post(&self.url, &self.headers, &self.content_type, lines.join("\n").as_bytes())
+    }
+}
+
+pub struct UdpTransport {
+    address: String,
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn new(address: &str) -> io::Result<Self> {
+        let target = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", address)))?;
+        let local = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        Ok(UdpTransport { address: address.to_string(), socket })
+    }
+}
+
+impl Transport for UdpTransport {
+    fn describe(&self) -> String {
+        format!("udp {}", self.address)
+    }
+
+    fn send(&mut self, lines: &[String]) -> io::Result<()> {
+        for payload in pack(lines, MAX_DATAGRAM) {
+            self.socket.send(payload.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Send `lines`, trying again up to `retries` times with a doubling pause.
+pub fn send_with_retries(transport: &mut dyn Transport, lines: &[String], retries: u32) -> io::Result<()> {
+    let mut pause = Duration::from_millis(250);
+    let mut attempt = 0;
+    loop {
+        match transport.send(lines) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= retries => return Err(e),
+            Err(_) => {
+                thread::sleep(pause);
+                pause *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+enum Encoding {
+    Influx,
+    Statsd { prefix: String, dogstatsd: bool },
+}
+
+struct Output {
+    encoding: Encoding,
+    transport: Box<dyn Transport>,
+    tags: BTreeMap<String, String>,
+    skip: Vec<String>,
+    batch_size: usize,
+    retries: u32,
+    pending: Vec<String>,
+    pending_samples: usize,
+}
+
+impl Output {
+    fn from_config(config: &PushConfig) -> io::Result<Self> {
+        let (encoding, transport): (Encoding, Box<dyn Transport>) = match &config.kind {
+            PushKind::InfluxHttp { url, headers } => (Encoding::Influx, Box::new(HttpTransport::new(url, headers.clone())?)),
+            PushKind::InfluxUdp { address } => (Encoding::Influx, Box::new(UdpTransport::new(address)?)),
+            PushKind::Statsd { address, prefix, dogstatsd } => (
+                Encoding::Statsd { prefix: prefix.clone(), dogstatsd: *dogstatsd },
+                Box::new(UdpTransport::new(address)?),
+            ),
+        };
+        Ok(Output {
+            encoding,
+            transport,
+            tags: config.tags.clone(),
+            skip: config.skip.clone(),
+            batch_size: config.batch_size.max(1),
+            retries: config.retries,
+            pending: Vec::new(),
+            pending_samples: 0,
+        })
+    }
+
+    fn add(&mut self, sample: &Sample) {
+        let mut sample = sample.clone();
+        sample.metrics.retain(|metric, _| !self.skip.iter().any(|prefix| metric.starts_with(prefix.as_str())));
+        // Configured tags win over the built-in host tag
+        sample.tags.extend(self.tags.clone());
+        self.pending.extend(match &self.encoding {
+            Encoding::Influx => encode_influx(&sample),
+            Encoding::Statsd { prefix, dogstatsd } => encode_statsd(&sample, prefix, *dogstatsd),
+        });
+        self.pending_samples += 1;
+    }
+
+    /// Send what is pending. A batch that still fails after the retries is
+    /// dropped so a dead endpoint cannot grow memory.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let lines = std::mem::take(&mut self.pending);
+        self.pending_samples = 0;
+        send_with_retries(self.transport.as_mut(), &lines, self.retries)
+    }
+}
+
+/// Pushes every sample to the configured outputs on a background thread,
+/// batching them so a slow or unreachable endpoint never stalls the dashboard.
+pub struct PushDispatcher {
+    sender: mpsc::SyncSender<Sample>,
+    failures: Arc<Mutex<VecDeque<String>>>,
+    /// Whether the last batch sent to each output failed
+    failing: Arc<Vec<AtomicBool>>,
+    /// Samples dropped because the queue was full
+    dropped: AtomicU64,
+    worker: JoinHandle<()>,
+}
+
+impl PushDispatcher {
+    /// Start pushing to the configured outputs, or `None` when there are none.
+    pub fn start(configs: &[PushConfig]) -> io::Result<Option<Self>> {
+        if configs.is_empty() {
+            return Ok(None);
+        }
+        let mut outputs = configs.iter().map(Output::from_config).collect::<io::Result<Vec<Output>>>()?;
+
+        let (sender, receiver) = mpsc::sync_channel::<Sample>(QUEUE_CAPACITY);
+        let failures = Arc::new(Mutex::new(VecDeque::new()));
+        let failing: Arc<Vec<AtomicBool>> = Arc::new(outputs.iter().map(|_| AtomicBool::new(false)).collect());
+        let worker_failures = Arc::clone(&failures);
+        let worker_failing = Arc::clone(&failing);
+        let worker = thread::spawn(move || {
+            let flush = |index: usize, output: &mut Output| {
+                let result = output.flush();
+                worker_failing[index].store(result.is_err(), Ordering::Relaxed);
+                if let Err(error) = result {
+                    let mut failures = worker_failures.lock();
+                    failures.push_front(format!("{}: {}", output.transport.describe(), error));
+                    failures.truncate(MAX_FAILURES);
+                }
+            };
+            for sample in receiver.iter() {
+                for (index, output) in outputs.iter_mut().enumerate() {
+                    output.add(&sample);
+                    if output.pending_samples >= output.batch_size {
+                        flush(index, output);
+                    }
+                }
+            }
+            // Dispatcher dropped: send the partial batches
+            for (index, output) in outputs.iter_mut().enumerate() {
+                flush(index, output);
+            }
+        });
+
+        Ok(Some(PushDispatcher { sender, failures, failing, dropped: AtomicU64::new(0), worker }))
+    }
+
+    pub fn push(&self, host: &str, timestamp: DateTime<Utc>, metrics: &BTreeMap<String, f64>) {
+        let tags = BTreeMap::from([("host".to_string(), host.to_string())]);
+        // A worker stuck on a slow endpoint must not hold up the dashboard or
+        // grow memory; it only disconnects when the dispatcher is dropped
+        if let Err(mpsc::TrySendError::Full(_)) = self.sender.try_send(Sample { timestamp, tags, metrics: metrics.clone() }) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Samples dropped so far because the outputs could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Recent delivery failures, newest first.
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.lock().iter().cloned().collect()
+    }
+
+    /// Whether the last batch sent to any output failed.
+    pub fn is_failing(&self) -> bool {
+        self.failing.iter().any(|failing| failing.load(Ordering::Relaxed))
+    }
+
+    /// Send everything already queued, including partial batches, then stop the worker.
+    pub fn shutdown(self) -> Vec<String> {
+        let PushDispatcher { sender, failures, worker, .. } = self;
+        drop(sender);
+        worker.join().ok();
+        failures.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Handler, HttpServer, Request, Response};
+
+    fn sample() -> Sample {
+        Sample {
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            tags: BTreeMap::from([("host".to_string(), "web 1".to_string())]),
+            metrics: BTreeMap::from([
+                ("cpu".to_string(), 12.5),
+                ("mem_pct".to_string(), 40.0),
+                ("disk[/var]".to_string(), 85.0),
+            ]),
+        }
+    }
+
+    fn config(kind: PushKind) -> PushConfig {
+        PushConfig {
+            kind,
+            tags: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+            skip: default_skip(),
+            batch_size: 2,
+            retries: 0,
+        }
+    }
+
+    #[test]
+    fn test_influx_line_protocol() {
+        assert_eq!(encode_influx(&sample()), vec![
+            "grainx,host=web\\ 1 cpu=12.5,mem_pct=40 1700000000000000000".to_string(),
+            "grainx_disk,host=web\\ 1,key=/var value=85 1700000000000000000".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_statsd_lines() {
+        assert_eq!(encode_statsd(&sample(), "grainx", false), vec![
+            "grainx.cpu:12.5|g".to_string(),
+            "grainx.disk._var:85|g".to_string(),
+            "grainx.mem_pct:40|g".to_string(),
+        ]);
+        assert_eq!(encode_statsd(&sample(), "grainx", true)[1], "grainx.disk:85|g|#host:web 1,key:/var");
+    }
+
+    #[test]
+    fn test_pack_respects_limit() {
+        let lines: Vec<String> = (0..10).map(|i| format!("line-{:04}", i)).collect();
+        let payloads = pack(&lines, 25);
+        assert_eq!(payloads.len(), 5);
+        assert!(payloads.iter().all(|p| p.len() <= 25));
+        assert_eq!(payloads.join("\n").lines().count(), 10);
+    }
+
+    #[test]
+    fn test_statsd_over_udp() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let kind = PushKind::Statsd { address: listener.local_addr().unwrap().to_string(), prefix: "app".to_string(), dogstatsd: true };
+        let dispatcher = PushDispatcher::start(&[config(kind)]).unwrap().unwrap();
+        let metrics = BTreeMap::from([("cpu".to_string(), 50.0), ("proc_cpu[sshd]".to_string(), 1.0)]);
+        dispatcher.push("web-1", sample().timestamp, &metrics);
+        dispatcher.push("web-1", sample().timestamp, &metrics);
+
+        let mut buffer = [0u8; 2048];
+        let n = listener.recv(&mut buffer).unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..n]).to_string();
+        assert_eq!(datagram, "app.cpu:50|g|#env:prod,host:web-1\napp.cpu:50|g|#env:prod,host:web-1");
+        assert!(dispatcher.shutdown().is_empty());
+    }
+
+    #[test]
+    fn test_influx_udp_flushes_partial_batch_on_shutdown() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let kind = PushKind::InfluxUdp { address: listener.local_addr().unwrap().to_string() };
+        let dispatcher = PushDispatcher::start(&[config(kind)]).unwrap().unwrap();
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 50.0)]));
+        assert!(dispatcher.shutdown().is_empty());
+
+        let mut buffer = [0u8; 2048];
+        let n = listener.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"grainx,env=prod,host=web-1 cpu=50 1700000000000000000");
+    }
+
+    #[test]
+    fn test_influx_http_retries() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Handler = {
+            let requests = requests.clone();
+            Arc::new(move |request: &Request| {
+                let mut requests = requests.lock();
+                requests.push(String::from_utf8_lossy(&request.body).to_string());
+                // Fail the first attempt
+                if requests.len() == 1 { Response::text(503, "busy") } else { Response::new(204, "text/plain", "") }
+            })
+        };
+        let server = HttpServer::start("127.0.0.1:0", handler).unwrap();
+        let mut config = config(PushKind::InfluxHttp {
+            url: format!("http://{}/api/v2/write?bucket=grainx", server.local_addr()),
+            headers: BTreeMap::new(),
+        });
+        config.retries = 1;
+        let dispatcher = PushDispatcher::start(&[config]).unwrap().unwrap();
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 1.0)]));
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 2.0)]));
+        assert!(dispatcher.shutdown().is_empty());
+
+        let requests = requests.lock();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].lines().count(), 2);
+        assert!(requests[1].ends_with("cpu=2 1700000000000000000"));
+    }
+
+    #[test]
+    fn test_failing_is_tracked_per_output() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let dead = config(PushKind::InfluxHttp { url: "http://127.0.0.1:1/write".to_string(), headers: BTreeMap::new() });
+        let healthy = config(PushKind::InfluxUdp { address: listener.local_addr().unwrap().to_string() });
+        let dispatcher = PushDispatcher::start(&[dead, healthy]).unwrap().unwrap();
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 1.0)]));
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 2.0)]));
+
+        // The healthy output is flushed after the dead one and must not clear its flag
+        let mut buffer = [0u8; 2048];
+        listener.recv(&mut buffer).unwrap();
+        assert!(dispatcher.is_failing());
+        dispatcher.shutdown();
+    }
+
+    #[test]
+    fn test_full_queue_drops_samples() {
+        // Accepts nothing, so the first delivery hangs until the listener goes away
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = config(PushKind::InfluxHttp { url: format!("http://{}/write", listener.local_addr().unwrap()), headers: BTreeMap::new() });
+        config.batch_size = 1;
+        let dispatcher = PushDispatcher::start(&[config]).unwrap().unwrap();
+        for _ in 0..QUEUE_CAPACITY + 10 {
+            dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 1.0)]));
+        }
+        assert!(dispatcher.dropped() >= 9);
+        drop(listener);
+        dispatcher.shutdown();
+    }
+
+    #[test]
+    fn test_failures_are_reported() {
+        let kind = PushKind::InfluxHttp { url: "http://127.0.0.1:1/write".to_string(), headers: BTreeMap::new() };
+        let dispatcher = PushDispatcher::start(&[config(kind)]).unwrap().unwrap();
+        dispatcher.push("web-1", sample().timestamp, &BTreeMap::from([("cpu".to_string(), 1.0)]));
+        let failures = dispatcher.shutdown();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].starts_with("http://127.0.0.1:1/write"));
+
+        let config: PushConfig = serde_json::from_str(r#"{ "type": "statsd", "address": "127.0.0.1:8125" }"#).unwrap();
+        assert_eq!((config.batch_size, config.retries), (10, 3));
+        assert!(matches!(config.kind, PushKind::Statsd { dogstatsd: false, .. }));
+        assert!(PushDispatcher::start(&[]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,258 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use crate::events::Event;
+use crate::snapshot::Snapshot;
+
+/// One recorded tick: the snapshot and the events the monitor raised with it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub snapshot: Snapshot,
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+// Recordings are JSON lines: a header naming the format, then one frame per line.
+const FORMAT: &str = "grainx-recording";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// Appends frames to a recording file as they are captured.
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut out, &Header { format: FORMAT.to_string(), version: VERSION })?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(Recorder { out })
+    }
+
+    pub fn record(&mut self, snapshot: &Snapshot, events: &[Event]) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &Frame { snapshot: snapshot.clone(), events: events.to_vec() })?;
+        writeln!(self.out)?;
+        // Flushed every frame so a crash keeps everything up to it
+        self.out.flush()
+    }
+}
+
+pub struct Recording {
+    pub frames: Vec<Frame>,
+}
+
+impl Recording {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header: Header = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a grainx recording", path)))?,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is empty", path))),
+        };
+        if header.format != FORMAT || header.version != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("{} is a {} v{} file, expected {} v{}", path, header.format, header.version, FORMAT, VERSION)));
+        }
+
+        let lines: Vec<String> = lines.collect::<io::Result<_>>()?;
+        let mut frames = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(frame) => frames.push(frame),
+                // The last line may have been cut off while recording
+                Err(_) if i == lines.len() - 1 => break,
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} line {}: {}", path, i + 2, e))),
+            }
+        }
+        if frames.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has no frames", path)));
+        }
+        Ok(Recording { frames })
+    }
+}
+
+/// Playback speeds, slowest first.
+pub const SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+/// Plays a recording back against a replay clock that can be paused,
+/// sped up or slowed down, and moved with seeks.
+pub struct Player {
+    frames: Vec<Frame>,
+    /// Index of the next frame to hand out
+    next: usize,
+    clock: DateTime<Utc>,
+    speed: usize,
+    pub paused: bool,
+    seeked: bool,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let clock = recording.frames[0].snapshot.timestamp;
+        Player { frames: recording.frames, next: 0, clock, speed: 2, paused: false, seeked: false }
+    }
+
+    /// Move the replay clock on by `elapsed` wall time and return the frames it passed.
+    pub fn advance(&mut self, elapsed: std::time::Duration) -> &[Frame] {
+        if !self.paused {
+            let scaled = elapsed.as_secs_f64() * self.speed();
+            self.clock = (self.clock + chrono::Duration::milliseconds((scaled * 1000.0) as i64)).min(self.end());
+        }
+        let start = self.next;
+        while self.next < self.frames.len() && self.frames[self.next].snapshot.timestamp <= self.clock {
+            self.next += 1;
+        }
+        &self.frames[start..self.next]
+    }
+
+    /// Jump by `delta` (negative to rewind). The frame at the new position is
+    /// handed out by the next `advance`, even while paused.
+    pub fn seek(&mut self, delta: chrono::Duration) {
+        self.clock = (self.clock + delta).clamp(self.start(), self.end());
+        let reached = self.frames.partition_point(|frame| frame.snapshot.timestamp <= self.clock);
+        self.next = reached.max(1) - 1;
+        self.seeked = true;
+    }
+
+    /// Whether a seek happened since the last call, so derived state needs rebuilding.
+    pub fn take_seek(&mut self) -> bool {
+        std::mem::take(&mut self.seeked)
+    }
+
+    /// Up to `count` frames before the current position, to rebuild history from.
+    pub fn history(&self, count: usize) -> &[Frame] {
+        &self.frames[self.next.saturating_sub(count)..self.next]
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = self.speed.saturating_sub(1);
+    }
+
+    pub fn speed(&self) -> f64 {
+        SPEEDS[self.speed]
+    }
+
+    pub fn clock(&self) -> DateTime<Utc> {
+        self.clock
+    }
+
+    pub fn start(&self) -> DateTime<Utc> {
+        self.frames[0].snapshot.timestamp
+    }
+
+    pub fn end(&self) -> DateTime<Utc> {
+        self.frames[self.frames.len() - 1].snapshot.timestamp
+    }
+
+    /// Frames handed out so far, and the total.
+    pub fn position(&self) -> (usize, usize) {
+        (self.next, self.frames.len())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventKind;
+    use crate::snapshot::sample;
+    use std::time::Duration;
+
+    fn snapshot(secs: i64, cpu: f32) -> Snapshot {
+        Snapshot { processes: vec![(1, "init".to_string(), 0.1, 2048)], ..sample(secs, cpu) }
+    }
+
+    fn recording(seconds: i64) -> Recording {
+        Recording { frames: (0..seconds).map(|s| Frame { snapshot: snapshot(s, s as f32), events: Vec::new() }).collect() }
+    }
+
+    fn temp_path(test: &str) -> String {
+        std::env::temp_dir().join(format!("grainx-recording-{}-{}.grx", std::process::id(), test)).display().to_string()
+    }
+
+    #[test]
+    fn test_record_and_load() {
+        let path = temp_path("round-trip");
+        let mut recorder = Recorder::create(&path).unwrap();
+        let events = vec![Event { timestamp: snapshot(0, 0.0).timestamp, kind: EventKind::Bottleneck { cpu: 97.0, readings: 5 } }];
+        recorder.record(&snapshot(0, 12.0), &events).unwrap();
+        recorder.record(&snapshot(1, 15.0), &[]).unwrap();
+        drop(recorder);
+
+        let recording = Recording::load(&path).unwrap();
+        assert_eq!(recording.frames.len(), 2);
+        assert_eq!(recording.frames[0].snapshot, snapshot(0, 12.0));
+        assert_eq!(recording.frames[0].events, events);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_load_tolerates_cut_off_last_frame() {
+        let path = temp_path("cut-off");
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder.record(&snapshot(0, 12.0), &[]).unwrap();
+        drop(recorder);
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"snapshot\":{\"timest").unwrap();
+        assert_eq!(Recording::load(&path).unwrap().frames.len(), 1);
+
+        std::fs::write(&path, "{\"hello\":1}\n").unwrap();
+        assert_eq!(Recording::load(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_playback_follows_clock_and_speed() {
+        let mut player = Player::new(recording(10));
+        assert_eq!(player.advance(Duration::ZERO).len(), 1);
+        assert_eq!(player.advance(Duration::from_millis(2500)).len(), 2);
+
+        player.faster();
+        assert_eq!(player.speed(), 2.0);
+        assert_eq!(player.advance(Duration::from_secs(2)).len(), 4);
+
+        player.toggle_pause();
+        assert!(player.advance(Duration::from_secs(5)).is_empty());
+        player.toggle_pause();
+        assert_eq!(player.advance(Duration::from_secs(60)).len(), 3);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut player = Player::new(recording(10));
+        player.advance(Duration::from_secs(8));
+        player.toggle_pause();
+
+        player.seek(chrono::Duration::seconds(-5));
+        assert!(player.take_seek());
+        assert!(!player.take_seek());
+        assert_eq!(player.history(2).iter().map(|f| f.snapshot.cpu_usage).collect::<Vec<_>>(), vec![1.0, 2.0]);
+        // Delivered even though paused
+        let frames = player.advance(Duration::from_secs(1));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].snapshot.cpu_usage, 3.0);
+
+        player.seek(chrono::Duration::seconds(-60));
+        assert_eq!(player.clock(), player.start());
+        assert_eq!(player.advance(Duration::ZERO)[0].snapshot.cpu_usage, 0.0);
+    }
+}
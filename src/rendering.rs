@@ -1,5 +1,5 @@
 use std::io::{self, Write};
-use crossterm::{cursor, execute, style::{Color, SetForegroundColor}};
+use crossterm::{cursor, execute, terminal, style::{Color, SetForegroundColor}};
 
 pub struct Rect {
     pub x: u16,
@@ -12,11 +12,33 @@ pub struct AdvancedCanvas {
     stdout: io::Stdout,
 }
 
+impl Default for AdvancedCanvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AdvancedCanvas {
     pub fn new() -> Self {
         AdvancedCanvas { stdout: io::stdout() }
     }
 
+    /// Terminal size in cells, falling back to 80x24 when it cannot be queried.
+    pub fn size(&self) -> (u16, u16) {
+        terminal::size().unwrap_or((80, 24))
+    }
+
+    /// Whether `rect` lies entirely inside the terminal.
+    pub fn fits(&self, rect: &Rect) -> bool {
+        let (width, height) = self.size();
+        rect.x + rect.width <= width && rect.y + rect.height <= height
+    }
+
+    /// Blank the whole screen, e.g. when switching between views.
+    pub fn clear(&mut self) -> io::Result<()> {
+        execute!(self.stdout, terminal::Clear(terminal::ClearType::All))
+    }
+
     pub fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
         execute!(self.stdout, cursor::MoveTo(x, y))
     }
@@ -47,84 +69,204 @@ impl AdvancedCanvas {
 
     /// Draw a braille-based graph line for high-resolution
     pub fn draw_braille_line(&mut self, points: &[(f64, f64)], rect: &Rect) -> io::Result<()> {
-        let braille_width = rect.width * 2;
-        let braille_height = rect.height * 4;
-        
-        let mut grid = vec![vec![false; braille_height as usize]; braille_width as usize];
-        
-        // Map each point to the grid
-        for i in 0..points.len() - 1 {
-            // Simple line drawing algorithm (Bresenham's or similar could be used)
-            let (x1, y1) = points[i];
-            let (x2, y2) = points[i+1];
+        let mut grid = BrailleGrid::new(rect);
+        grid.plot_line(points);
+
+        for y in 0..rect.height {
+            self.set_cursor(rect.x, rect.y + y)?;
+            let row: String = (0..rect.width).map(|x| grid.cell_char(x, y)).collect();
+            self.draw_str(&row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw a braille graph line followed by a forecast ribbon.
+    ///
+    /// Each band entry is `(x, low, high)` in the same coordinates as `points`; cells
+    /// that only contain ribbon dots are drawn in `band_color`.
+    pub fn draw_braille_forecast(
+        &mut self,
+        points: &[(f64, f64)],
+        band: &[(f64, f64, f64)],
+        rect: &Rect,
+        line_color: Color,
+        band_color: Color,
+    ) -> io::Result<()> {
+        let mut line = BrailleGrid::new(rect);
+        line.plot_line(points);
+        let mut ribbon = BrailleGrid::new(rect);
+        for &(x, low, high) in band {
+            ribbon.fill_column(x, low, high);
+        }
+
+        for y in 0..rect.height {
+            self.set_cursor(rect.x, rect.y + y)?;
+            for x in 0..rect.width {
+                let line_bits = line.cell_bits(x, y);
+                let ribbon_bits = ribbon.cell_bits(x, y);
+                self.set_color(if line_bits == 0 && ribbon_bits != 0 { band_color } else { line_color })?;
+                self.draw_str(&braille_char(line_bits | ribbon_bits).to_string())?;
+            }
+        }
 
-            let dx = (x2 - x1).abs();
-            let dy = (y2 - y1).abs();
+        Ok(())
+    }
+}
 
-            let sx = if x1 < x2 { 1.0 } else { -1.0 };
-            let sy = if y1 < y2 { 1.0 } else { -1.0 };
+/// One-line block sparkline of the last `width` values, scaled to their own range.
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let values = &values[values.len().saturating_sub(width)..];
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    values.iter()
+        .map(|v| {
+            let level = if range > f64::EPSILON { ((v - min) / range * 7.0).round() as usize } else { 0 };
+            BLOCKS[level.min(7)]
+        })
+        .collect()
+}
 
-            let mut err = dx - dy;
+fn braille_char(bits: u32) -> char {
+    std::char::from_u32(0x2800 + bits).unwrap_or('?')
+}
 
-            let mut current_x = x1;
-            let mut current_y = y1;
+/// Dot matrix behind the braille graphs: every character cell holds 2x4 dots.
+///
+/// Graph coordinates are in cells with y growing upwards from the bottom of the
+/// rect, so a value of `rect.height` sits on the top row.
+struct BrailleGrid {
+    width: usize,
+    height: usize,
+    dots: Vec<Vec<bool>>,
+}
 
-            loop {
-                let gx = (current_x / rect.width as f64 * braille_width as f64).round() as usize;
-                let gy = (current_y / rect.height as f64 * braille_height as f64).round() as usize;
+impl BrailleGrid {
+    fn new(rect: &Rect) -> Self {
+        let width = rect.width as usize * 2;
+        let height = rect.height as usize * 4;
+        BrailleGrid { width, height, dots: vec![vec![false; height]; width] }
+    }
 
-                if gx < braille_width as usize && gy < braille_height as usize {
-                    grid[gx][gy] = true;
-                }
+    fn to_dot(&self, x: f64, y: f64) -> (i64, i64) {
+        let gx = (x * 2.0).round() as i64;
+        let gy = self.height as i64 - 1 - (y * 4.0).round() as i64;
+        (gx, gy)
+    }
 
-                if current_x == x2 && current_y == y2 {
+    fn set(&mut self, gx: i64, gy: i64) {
+        if gx >= 0 && gy >= 0 && (gx as usize) < self.width && (gy as usize) < self.height {
+            self.dots[gx as usize][gy as usize] = true;
+        }
+    }
+
+    /// Connect consecutive points with Bresenham lines in dot space.
+    fn plot_line(&mut self, points: &[(f64, f64)]) {
+        if let [(x, y)] = points {
+            let (gx, gy) = self.to_dot(*x, *y);
+            self.set(gx, gy);
+        }
+
+        for pair in points.windows(2) {
+            let (mut x0, mut y0) = self.to_dot(pair[0].0, pair[0].1);
+            let (x1, y1) = self.to_dot(pair[1].0, pair[1].1);
+
+            let dx = (x1 - x0).abs();
+            let dy = -(y1 - y0).abs();
+            let sx = if x0 < x1 { 1 } else { -1 };
+            let sy = if y0 < y1 { 1 } else { -1 };
+            let mut err = dx + dy;
+
+            loop {
+                self.set(x0, y0);
+                if x0 == x1 && y0 == y1 {
                     break;
                 }
-
-                let e2 = 2.0 * err;
-                if e2 > -dy {
-                    err -= dy;
-                    current_x += sx;
+                let e2 = 2 * err;
+                if e2 >= dy {
+                    err += dy;
+                    x0 += sx;
                 }
-                if e2 < dx {
+                if e2 <= dx {
                     err += dx;
-                    current_y += sy;
+                    y0 += sy;
                 }
             }
         }
-        
-        for y in 0..rect.height {
-            for x in 0..rect.width {
-                let mut braille_value: u32 = 0;
-                // Braille dots are 2x4 within a character cell
-                // Mapping: 1 4
-                //          2 5
-                //          3 6
-                //          7 8 (bottom-most dot)
-                
-                // Top-left dot (1)
-                if grid[x as usize * 2][y as usize * 4] { braille_value |= 0x01; }
-                // Middle-left dot (2)
-                if grid[x as usize * 2][y as usize * 4 + 1] { braille_value |= 0x02; }
-                // Bottom-left dot (3)
-                if grid[x as usize * 2][y as usize * 4 + 2] { braille_value |= 0x04; }
-                // Top-right dot (4)
-                if grid[x as usize * 2 + 1][y as usize * 4] { braille_value |= 0x08; }
-                // Middle-right dot (5)
-                if grid[x as usize * 2 + 1][y as usize * 4 + 1] { braille_value |= 0x10; }
-                // Bottom-right dot (6)
-                if grid[x as usize * 2 + 1][y as usize * 4 + 2] { braille_value |= 0x20; }
-                // Bottom-most dot (7) - this is the 7th dot in the braille character
-                if grid[x as usize * 2][y as usize * 4 + 3] { braille_value |= 0x40; }
-                // 8th dot (8) - this is the 8th dot in the braille character
-                if grid[x as usize * 2 + 1][y as usize * 4 + 3] { braille_value |= 0x80; }
-
-                let braille_char = std::char::from_u32(0x2800 + braille_value).unwrap_or('?');
-                self.set_cursor(rect.x + x, rect.y + y)?;
-                self.draw_str(&braille_char.to_string())?;
+    }
+
+    /// Fill every dot of the cell column at `x` between `low` and `high`.
+    fn fill_column(&mut self, x: f64, low: f64, high: f64) {
+        let (gx, bottom) = self.to_dot(x, low.min(high));
+        let (_, top) = self.to_dot(x, low.max(high));
+        for gy in top..=bottom {
+            self.set(gx, gy);
+            self.set(gx + 1, gy);
+        }
+    }
+
+    fn cell_bits(&self, x: u16, y: u16) -> u32 {
+        // Braille dots are 2x4 within a character cell
+        // Mapping: 1 4
+        //          2 5
+        //          3 6
+        //          7 8 (bottom-most dot)
+        const DOT_BITS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+        let mut bits = 0;
+        for (dx, column) in DOT_BITS.iter().enumerate() {
+            for (dy, bit) in column.iter().enumerate() {
+                if self.dots[x as usize * 2 + dx][y as usize * 4 + dy] {
+                    bits |= bit;
+                }
             }
         }
-        
-        Ok(())
+        bits
+    }
+
+    fn cell_char(&self, x: u16, y: u16) -> char {
+        braille_char(self.cell_bits(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0.0, 3.5, 7.0], 10), "▁▅█");
+        assert_eq!(sparkline(&[1.0, 2.0, 5.0, 5.0], 2), "▁▁");
+        assert_eq!(sparkline(&[], 5), "");
+    }
+
+    #[test]
+    fn test_braille_line_with_fractional_points() {
+        let rect = Rect { x: 0, y: 0, width: 4, height: 2 };
+        let mut grid = BrailleGrid::new(&rect);
+        grid.plot_line(&[(0.0, 0.3), (1.0, 0.9), (2.0, 1.7)]);
+        assert!(grid.dots[0][6]);
+        assert!(grid.dots[2][3]);
+        assert!(grid.dots[4][0]);
+    }
+
+    #[test]
+    fn test_braille_grid_grows_upwards() {
+        let rect = Rect { x: 0, y: 0, width: 2, height: 2 };
+        let mut grid = BrailleGrid::new(&rect);
+        grid.plot_line(&[(0.0, 0.0)]);
+        assert_eq!(grid.cell_bits(0, 0), 0);
+        assert_eq!(grid.cell_bits(0, 1), 0x40);
+    }
+
+    #[test]
+    fn test_braille_ribbon_fills_both_dot_columns() {
+        let rect = Rect { x: 0, y: 0, width: 2, height: 1 };
+        let mut grid = BrailleGrid::new(&rect);
+        grid.fill_column(1.0, 0.0, 1.0);
+        assert_eq!(grid.cell_bits(1, 0), 0xFF);
+        assert_eq!(grid.cell_bits(0, 0), 0);
     }
-}
\ No newline at end of file
+}
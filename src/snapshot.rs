@@ -0,0 +1,147 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+/// Flat metric name for one member of a keyed family, e.g. `disk[/var]`.
+pub fn keyed_metric(family: &str, key: &str) -> String {
+    format!("{}[{}]", family, key)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiskSnapshot {
+    pub name: String,
+    pub mount_point: String,
+    pub total: u64,
+    pub available: u64,
+}
+
+impl DiskSnapshot {
+    pub fn used_percentage(&self) -> f64 {
+        if self.total > 0 {
+            ((self.total - self.available) as f64 / self.total as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InterfaceSnapshot {
+    pub name: String,
+    /// Bytes since the previous tick
+    pub received: u64,
+    pub transmitted: u64,
+    /// Bytes since the interface came up
+    pub total_received: u64,
+    pub total_transmitted: u64,
+}
+
+/// Everything collected from the system in one tick.
+///
+/// The dashboard only ever draws from a snapshot, so the same data can come
+/// from the local `SystemMonitor` or from elsewhere.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub hostname: String,
+    pub cpu_usage: f32,
+    pub cpu_cores: Vec<f32>,
+    pub used_memory: u64,
+    pub total_memory: u64,
+    pub used_swap: u64,
+    pub total_swap: u64,
+    pub network_rx: u64,
+    pub network_tx: u64,
+    /// Per-interface traffic; `network_rx`/`network_tx` are its sums
+    #[serde(default)]
+    pub interfaces: Vec<InterfaceSnapshot>,
+    /// Bytes read and written by all processes since the previous tick
+    pub disk_read: u64,
+    pub disk_written: u64,
+    /// 1, 5 and 15 minute load averages
+    pub load_average: [f64; 3],
+    pub disks: Vec<DiskSnapshot>,
+    pub processes: Vec<(usize, String, f32, u64)>,
+    pub os_name: String,
+    pub kernel_version: String,
+    pub uptime: u64,
+}
+
+impl Snapshot {
+    pub fn memory_percentage(&self) -> f64 {
+        if self.total_memory > 0 {
+            (self.used_memory as f64 / self.total_memory as f64) * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn swap_percentage(&self) -> Option<f64> {
+        if self.total_swap > 0 {
+            Some((self.used_swap as f64 / self.total_swap as f64) * 100.0)
+        } else {
+            None
+        }
+    }
+
+    /// Every numeric metric in the snapshot under its flat name.
+    ///
+    /// Scalars use plain names (`cpu`, `mem_pct`, `load1`, ...); per-core, per-mount
+    /// and per-process values use `family[key]` (see `keyed_metric`).
+    pub fn metrics(&self) -> BTreeMap<String, f64> {
+        let mut metrics = BTreeMap::new();
+        metrics.insert("cpu".to_string(), self.cpu_usage as f64);
+        metrics.insert("mem_pct".to_string(), self.memory_percentage());
+        metrics.insert("mem_used".to_string(), self.used_memory as f64);
+        metrics.insert("mem_total".to_string(), self.total_memory as f64);
+        if let Some(swap) = self.swap_percentage() {
+            metrics.insert("swap_pct".to_string(), swap);
+        }
+        metrics.insert("net_rx".to_string(), self.network_rx as f64);
+        metrics.insert("net_tx".to_string(), self.network_tx as f64);
+        metrics.insert("disk_read".to_string(), self.disk_read as f64);
+        metrics.insert("disk_write".to_string(), self.disk_written as f64);
+        metrics.insert("load1".to_string(), self.load_average[0]);
+        metrics.insert("load5".to_string(), self.load_average[1]);
+        metrics.insert("load15".to_string(), self.load_average[2]);
+
+        for (i, usage) in self.cpu_cores.iter().enumerate() {
+            metrics.insert(keyed_metric("cpu_core", &i.to_string()), *usage as f64);
+        }
+        for disk in self.disks.iter().filter(|d| d.total > 0) {
+            metrics.insert(keyed_metric("disk", &disk.mount_point), disk.used_percentage());
+        }
+        for (_, name, cpu, memory) in &self.processes {
+            *metrics.entry(keyed_metric("proc_cpu", name)).or_insert(0.0) += *cpu as f64;
+            *metrics.entry(keyed_metric("proc_mem", name)).or_insert(0.0) += *memory as f64;
+        }
+        metrics
+    }
+}
+
+/// A quiet host `secs` seconds after a fixed time with `cpu` percent on its
+/// one core; tests override whatever else they look at.
+#[cfg(test)]
+pub(crate) fn sample(secs: i64, cpu: f32) -> Snapshot {
+    Snapshot {
+        timestamp: DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap(),
+        hostname: "web-1".to_string(),
+        cpu_usage: cpu,
+        cpu_cores: vec![cpu],
+        used_memory: 1024,
+        total_memory: 4096,
+        used_swap: 0,
+        total_swap: 0,
+        network_rx: 0,
+        network_tx: 0,
+        interfaces: Vec::new(),
+        disk_read: 0,
+        disk_written: 0,
+        load_average: [0.5; 3],
+        disks: Vec::new(),
+        processes: Vec::new(),
+        os_name: "Linux".to_string(),
+        kernel_version: "6.1".to_string(),
+        uptime: 100,
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::io;
+use crate::history::MetricHistory;
+use crate::tsdb::{Point, Resolution, TimeSeriesStore};
+
+/// Graph spans to zoom through, narrowest first.
+pub const ZOOM_LEVELS: [(&str, i64); 4] = [("1m", 60), ("5m", 300), ("1h", 3600), ("24h", 86_400)];
+
+/// Split `from..to` into `columns` equal buckets and merge every point into
+/// the bucket its timestamp falls in. Empty buckets are `None`.
+pub fn bucket(points: &[Point], from: DateTime<Utc>, to: DateTime<Utc>, columns: usize) -> Vec<Option<Point>> {
+    let mut buckets: Vec<Option<Point>> = vec![None; columns];
+    let span = (to - from).num_milliseconds();
+    if columns == 0 || span <= 0 {
+        return buckets;
+    }
+    for point in points.iter().filter(|p| p.timestamp >= from && p.timestamp <= to) {
+        let column = ((point.timestamp - from).num_milliseconds() as i128 * columns as i128 / span as i128) as usize;
+        let column = column.min(columns - 1);
+        match &mut buckets[column] {
+            Some(bucket) => bucket.merge(point),
+            slot => {
+                let start = from + chrono::Duration::milliseconds(span * column as i64 / columns as i64);
+                *slot = Some(Point { timestamp: start, ..*point });
+            }
+        }
+    }
+    buckets
+}
+
+/// Start and end of a graph window.
+type Window = (DateTime<Utc>, DateTime<Utc>);
+
+/// Which stretch of history the graphs show: the live tail, or a zoomed
+/// window that can be panned back through stored history.
+pub struct TimeView {
+    /// Index into `ZOOM_LEVELS`; `None` while following live data
+    zoom: Option<usize>,
+    /// How far the right edge of the window is behind the latest sample
+    offset: chrono::Duration,
+    /// Graph column the cursor reads out, if shown
+    pub cursor: Option<u16>,
+    /// Graph width in columns, set when the graphs are drawn
+    pub columns: u16,
+    cache: HashMap<String, (Window, Vec<Option<Point>>)>,
+}
+
+impl Default for TimeView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeView {
+    pub fn new() -> Self {
+        TimeView { zoom: None, offset: chrono::Duration::zero(), cursor: None, columns: 80, cache: HashMap::new() }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.zoom.is_none()
+    }
+
+    pub fn span(&self) -> Option<chrono::Duration> {
+        self.zoom.map(|zoom| chrono::Duration::seconds(ZOOM_LEVELS[zoom].1))
+    }
+
+    pub fn zoom_label(&self) -> &'static str {
+        self.zoom.map(|zoom| ZOOM_LEVELS[zoom].0).unwrap_or("live")
+    }
+
+    pub fn offset(&self) -> chrono::Duration {
+        self.offset
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.zoom = Some(self.zoom.map(|zoom| zoom.saturating_sub(1)).unwrap_or(0));
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.zoom = Some(self.zoom.map(|zoom| (zoom + 1).min(ZOOM_LEVELS.len() - 1)).unwrap_or(1));
+    }
+
+    /// Move the window by half its span; negative goes back in time.
+    pub fn pan(&mut self, direction: i32) {
+        if self.zoom.is_none() {
+            self.zoom = Some(0);
+        }
+        let step = self.span().expect("zoomed above") / 2;
+        self.offset = (self.offset - step * direction).max(chrono::Duration::zero());
+    }
+
+    pub fn toggle_cursor(&mut self) {
+        self.cursor = match self.cursor {
+            Some(_) => None,
+            None => Some(self.columns.saturating_sub(1)),
+        };
+    }
+
+    /// Move the cursor by `delta` columns, panning when it runs off either edge.
+    pub fn move_cursor(&mut self, delta: i32) {
+        let Some(cursor) = self.cursor else { return };
+        let target = cursor as i32 + delta;
+        if target < 0 {
+            self.pan(-1);
+            self.cursor = Some((self.columns / 2).saturating_sub(1));
+        } else if target >= self.columns as i32 {
+            if self.offset > chrono::Duration::zero() {
+                self.pan(1);
+                self.cursor = Some(self.columns / 2);
+            }
+        } else {
+            self.cursor = Some(target as u16);
+        }
+    }
+
+    /// Back to following live data.
+    pub fn reset(&mut self) {
+        self.zoom = None;
+        self.offset = chrono::Duration::zero();
+        self.cursor = None;
+        self.cache.clear();
+    }
+
+    /// The window shown when the latest sample is at `now`.
+    pub fn window(&self, now: DateTime<Utc>) -> Option<Window> {
+        let span = self.span()?;
+        let to = now - self.offset;
+        Some((to - span, to))
+    }
+
+    /// `metric` bucketed into one point per graph column for the current window.
+    ///
+    /// The in-memory history is used when it reaches back far enough, otherwise
+    /// the store at the resolution that suits the span. Store reads are cached
+    /// until the window moves by a whole column.
+    pub fn columns_for(
+        &mut self,
+        metric: &str,
+        history: &MetricHistory,
+        store: Option<&TimeSeriesStore>,
+        now: DateTime<Utc>,
+    ) -> io::Result<Vec<Option<Point>>> {
+        let Some((from, to)) = self.window(now) else { return Ok(Vec::new()) };
+        let columns = self.columns.max(1) as usize;
+        let column_ms = ((to - from).num_milliseconds() / columns as i64).max(1);
+        // Snap to column boundaries so samples stay in the same column between frames
+        let to = DateTime::from_timestamp_millis(to.timestamp_millis().div_euclid(column_ms) * column_ms + column_ms).unwrap_or(to);
+        let from = to - chrono::Duration::milliseconds(column_ms * columns as i64);
+
+        let in_memory = history.samples(metric).and_then(|samples| samples.front()).is_some_and(|(oldest, _)| *oldest <= from);
+        let store = match store {
+            Some(store) if !in_memory => store,
+            _ => {
+                let points: Vec<Point> = history.since(metric, from).into_iter().map(|(t, v)| Point::sample(t, v)).collect();
+                return Ok(bucket(&points, from, to, columns));
+            }
+        };
+
+        if let Some((window, buckets)) = self.cache.get(metric) && *window == (from, to) {
+            return Ok(buckets.clone());
+        }
+        let points = store.query(metric, from, to, Resolution::for_span(to - from))?;
+        let buckets = bucket(&points, from, to, columns);
+        self.cache.insert(metric.to_string(), ((from, to), buckets.clone()));
+        Ok(buckets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_bucket_aggregates_samples_per_column() {
+        let points: Vec<Point> = (0..60).map(|s| Point::sample(at(s), if s % 2 == 0 { 10.0 } else { 30.0 })).collect();
+        let buckets = bucket(&points, at(0), at(60), 6);
+        assert_eq!(buckets.len(), 6);
+        let first = buckets[0].unwrap();
+        assert_eq!(first.timestamp, at(0));
+        assert_eq!((first.min, first.max, first.avg, first.count), (10.0, 30.0, 20.0, 10));
+        assert_eq!(buckets[5].unwrap().timestamp, at(50));
+    }
+
+    #[test]
+    fn test_bucket_leaves_gaps_empty() {
+        let points = vec![Point::sample(at(0), 5.0), Point::sample(at(55), 7.0), Point::sample(at(90), 9.0)];
+        let buckets = bucket(&points, at(0), at(60), 6);
+        assert!(buckets[0].is_some());
+        assert!(buckets[1..5].iter().all(|b| b.is_none()));
+        assert_eq!(buckets[5].unwrap().avg, 7.0);
+    }
+
+    #[test]
+    fn test_zoom_and_pan() {
+        let mut view = TimeView::new();
+        assert!(view.is_live());
+        assert!(view.window(at(0)).is_none());
+
+        view.zoom_out();
+        assert_eq!(view.zoom_label(), "5m");
+        view.zoom_out();
+        view.zoom_out();
+        view.zoom_out();
+        assert_eq!(view.zoom_label(), "24h");
+        view.zoom_in();
+        assert_eq!(view.zoom_label(), "1h");
+
+        view.pan(-1);
+        assert_eq!(view.window(at(7200)), Some((at(1800), at(5400))));
+        view.pan(1);
+        view.pan(1);
+        assert_eq!(view.offset(), chrono::Duration::zero());
+
+        view.reset();
+        assert!(view.is_live());
+    }
+
+    #[test]
+    fn test_cursor_pans_at_edges() {
+        let mut view = TimeView::new();
+        view.columns = 10;
+        view.zoom_in();
+        view.toggle_cursor();
+        assert_eq!(view.cursor, Some(9));
+
+        // Already at the live edge: nothing to the right
+        view.move_cursor(1);
+        assert_eq!((view.cursor, view.offset()), (Some(9), chrono::Duration::zero()));
+
+        view.move_cursor(-9);
+        assert_eq!(view.cursor, Some(0));
+        view.move_cursor(-1);
+        assert_eq!(view.offset(), chrono::Duration::seconds(30));
+        assert_eq!(view.cursor, Some(4));
+    }
+
+    #[test]
+    fn test_columns_from_memory_history() {
+        let mut history = MetricHistory::new(200);
+        for s in 0..120 {
+            history.record("cpu", at(s), s as f64);
+        }
+        let mut view = TimeView::new();
+        view.columns = 6;
+        view.zoom_in();
+        let buckets = view.columns_for("cpu", &history, None, at(119)).unwrap();
+        assert_eq!(buckets.len(), 6);
+        assert!(buckets.iter().all(|b| b.is_some()));
+        let last = buckets[5].unwrap();
+        assert!(last.max >= 119.0 - 10.0 && last.max <= 119.0);
+    }
+}
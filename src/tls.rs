@@ -0,0 +1,143 @@
+use rustls::crypto::{ring, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use serde::{Serialize, Deserialize};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// PEM certificate chain presented to the other side. Required on the
+    /// server; on an agent it is only needed for mutual TLS.
+    pub cert: String,
+    /// PEM private key of `cert`
+    pub key: String,
+    /// PEM CA bundle the other side is verified against. Required on an
+    /// agent; on the server it makes client certificates mandatory.
+    pub ca: String,
+    /// Name the agent expects in the server certificate, instead of the
+    /// host part of its server address
+    pub server_name: String,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn open(path: &str, what: &str) -> io::Result<BufReader<File>> {
+    if path.is_empty() {
+        return Err(invalid(format!("tls.{} is required", what)));
+    }
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot read tls.{} {}: {}", what, path, e)))
+}
+
+fn load_certs(path: &str, what: &str) -> io::Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut open(path, what)?).collect::<io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(invalid(format!("tls.{} {} contains no PEM certificates", what, path)));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> io::Result<PrivateKeyDer<'static>> {
+    rustls_pemfile::private_key(&mut open(path, "key")?)?
+        .ok_or_else(|| invalid(format!("tls.key {} contains no PEM private key", path)))
+}
+
+fn load_roots(path: &str) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path, "ca")? {
+        roots.add(cert).map_err(|e| invalid(format!("tls.ca {}: {}", path, e)))?;
+    }
+    Ok(roots)
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn rustls_error(e: impl std::fmt::Display) -> io::Error {
+    invalid(format!("tls: {}", e))
+}
+
+/// Server side: present `cert`, and require a client certificate signed by
+/// `ca` when one is configured.
+pub fn server_config(config: &TlsConfig) -> io::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(rustls_error)?;
+    let builder = if config.ca.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(&config.ca)?), provider())
+            .build()
+            .map_err(rustls_error)?;
+        builder.with_client_cert_verifier(verifier)
+    };
+    let server = builder
+        .with_single_cert(load_certs(&config.cert, "cert")?, load_key(&config.key)?)
+        .map_err(rustls_error)?;
+    Ok(Arc::new(server))
+}
+
+/// Agent side: verify the server against `ca`, and present `cert` when one
+/// is configured.
+pub fn client_config(config: &TlsConfig) -> io::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(rustls_error)?
+        .with_root_certificates(load_roots(&config.ca)?);
+    let client = if config.cert.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        builder
+            .with_client_auth_cert(load_certs(&config.cert, "cert")?, load_key(&config.key)?)
+            .map_err(rustls_error)?
+    };
+    Ok(Arc::new(client))
+}
+
+/// The name to verify the server certificate against: `server_name` if set,
+/// otherwise the host part of `address`.
+pub fn server_name(config: &TlsConfig, address: &str) -> io::Result<ServerName<'static>> {
+    let name = if config.server_name.is_empty() {
+        let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    } else {
+        config.server_name.as_str()
+    };
+    ServerName::try_from(name.to_string()).map_err(|_| invalid(format!("invalid tls server name '{}'", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_name() {
+        let config = TlsConfig::default();
+        assert_eq!(server_name(&config, "monitor.example.com:9190").unwrap().to_str(), "monitor.example.com");
+        assert_eq!(server_name(&config, "127.0.0.1:9190").unwrap().to_str(), "127.0.0.1");
+        assert_eq!(server_name(&config, "[::1]:9190").unwrap().to_str(), "::1");
+        let config = TlsConfig { server_name: "grainx.internal".to_string(), ..TlsConfig::default() };
+        assert_eq!(server_name(&config, "10.0.0.5:9190").unwrap().to_str(), "grainx.internal");
+    }
+
+    #[test]
+    fn test_missing_files_are_reported() {
+        let config = TlsConfig { enabled: true, ..TlsConfig::default() };
+        assert_eq!(client_config(&config).unwrap_err().to_string(), "tls.ca is required");
+        assert_eq!(server_config(&config).unwrap_err().to_string(), "tls.cert is required");
+
+        let config = TlsConfig { enabled: true, ca: "/nonexistent/ca.pem".to_string(), ..TlsConfig::default() };
+        let error = client_config(&config).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().starts_with("cannot read tls.ca /nonexistent/ca.pem"));
+    }
+}
@@ -0,0 +1,511 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use crate::config::data_dir;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub enabled: bool,
+    /// Directory the segment files are kept in, `history` in the per-user
+    /// data directory when empty
+    pub path: String,
+    /// How long each resolution is kept
+    pub raw_retention_secs: u64,
+    pub minute_retention_secs: u64,
+    pub hour_retention_secs: u64,
+    /// Metrics whose name starts with one of these are not stored
+    pub skip: Vec<String>,
+}
+
+impl StorageConfig {
+    pub fn dir(&self) -> io::Result<PathBuf> {
+        if self.path.is_empty() {
+            Ok(data_dir()?.join("history"))
+        } else {
+            Ok(PathBuf::from(&self.path))
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            enabled: true,
+            path: String::new(),
+            raw_retention_secs: 86_400,
+            minute_retention_secs: 7 * 86_400,
+            hour_retention_secs: 90 * 86_400,
+            // Per-process series come and go with every process and would dominate the store
+            skip: vec!["proc_cpu[".to_string(), "proc_mem[".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Resolution {
+    /// Every sample as recorded
+    Raw,
+    Minute,
+    Hour,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [Resolution::Raw, Resolution::Minute, Resolution::Hour];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw",
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Resolution::Raw => 0,
+            Resolution::Minute => 1,
+            Resolution::Hour => 2,
+        }
+    }
+
+    pub fn bucket_secs(&self) -> i64 {
+        match self {
+            Resolution::Raw => 1,
+            Resolution::Minute => 60,
+            Resolution::Hour => 3600,
+        }
+    }
+
+    /// Time covered by one segment file
+    fn segment_secs(&self) -> i64 {
+        match self {
+            Resolution::Raw => 3600,
+            Resolution::Minute => 86_400,
+            Resolution::Hour => 30 * 86_400,
+        }
+    }
+
+    /// The coarsest resolution that still gives a graph of `span` enough points.
+    pub fn for_span(span: chrono::Duration) -> Self {
+        if span <= chrono::Duration::hours(2) {
+            Resolution::Raw
+        } else if span <= chrono::Duration::days(3) {
+            Resolution::Minute
+        } else {
+            Resolution::Hour
+        }
+    }
+}
+
+/// One stored value: a raw sample, or the summary of a rollup bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    /// Sample time, or the start of the bucket
+    pub timestamp: DateTime<Utc>,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub count: u32,
+}
+
+impl Point {
+    pub fn sample(timestamp: DateTime<Utc>, value: f64) -> Self {
+        Point { timestamp, min: value, max: value, avg: value, count: 1 }
+    }
+
+    pub fn merge(&mut self, other: &Point) {
+        let count = self.count + other.count;
+        self.avg = (self.avg * self.count as f64 + other.avg * other.count as f64) / count as f64;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count = count;
+    }
+}
+
+fn floor_to(timestamp: DateTime<Utc>, secs: i64) -> i64 {
+    timestamp.timestamp().div_euclid(secs) * secs
+}
+
+// Segment files start with a magic, a format version and the resolution code,
+// followed by records appended back to back:
+//   u16 name length, name, i64 timestamp (ms), f64 min, f64 max, f64 avg, u32 count
+// all little-endian. A record cut short by a crash ends the segment.
+const MAGIC: &[u8; 4] = b"GRXS";
+const VERSION: u8 = 1;
+
+fn write_record(out: &mut impl Write, name: &str, point: &Point) -> io::Result<()> {
+    let name_len = u16::try_from(name.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "metric name too long"))?;
+    out.write_all(&name_len.to_le_bytes())?;
+    out.write_all(name.as_bytes())?;
+    out.write_all(&point.timestamp.timestamp_millis().to_le_bytes())?;
+    out.write_all(&point.min.to_le_bytes())?;
+    out.write_all(&point.max.to_le_bytes())?;
+    out.write_all(&point.avg.to_le_bytes())?;
+    out.write_all(&point.count.to_le_bytes())
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_record(input: &mut impl Read) -> io::Result<(String, Point)> {
+    let name_len = u16::from_le_bytes(read_array(input)?) as usize;
+    let mut name = vec![0; name_len];
+    input.read_exact(&mut name)?;
+    let name = String::from_utf8(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let millis = i64::from_le_bytes(read_array(input)?);
+    let timestamp = DateTime::from_timestamp_millis(millis)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "timestamp out of range"))?;
+    let min = f64::from_le_bytes(read_array(input)?);
+    let max = f64::from_le_bytes(read_array(input)?);
+    let avg = f64::from_le_bytes(read_array(input)?);
+    let count = u32::from_le_bytes(read_array(input)?);
+    Ok((name, Point { timestamp, min, max, avg, count }))
+}
+
+/// Every record of `metric` in one segment file.
+fn read_segment(path: &Path, resolution: Resolution, metric: &str) -> io::Result<Vec<Point>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let header: [u8; 6] = read_array(&mut input)?;
+    if &header[..4] != MAGIC || header[4] != VERSION || header[5] != resolution.code() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a grainx {} segment", path.display(), resolution.name())));
+    }
+
+    let mut points = Vec::new();
+    loop {
+        match read_record(&mut input) {
+            Ok((name, point)) if name == metric => points.push(point),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(points)
+}
+
+struct SegmentWriter {
+    start: i64,
+    out: BufWriter<File>,
+}
+
+/// Append-only on-disk history of every metric, kept at raw, 1 minute and
+/// 1 hour resolution with separate retention for each.
+///
+/// Rollup buckets are built in memory and written when their minute or hour
+/// is over, or partially on `close`; buckets written twice are merged when read.
+pub struct TimeSeriesStore {
+    pub config: StorageConfig,
+    dir: PathBuf,
+    writers: HashMap<Resolution, SegmentWriter>,
+    minute: BTreeMap<String, Point>,
+    hour: BTreeMap<String, Point>,
+}
+
+impl TimeSeriesStore {
+    pub fn open(config: StorageConfig) -> io::Result<Self> {
+        let dir = config.dir()?;
+        fs::create_dir_all(&dir)?;
+        let mut store = TimeSeriesStore { config, dir, writers: HashMap::new(), minute: BTreeMap::new(), hour: BTreeMap::new() };
+        store.enforce_retention(Utc::now())?;
+        Ok(store)
+    }
+
+    fn retention(&self, resolution: Resolution) -> chrono::Duration {
+        let secs = match resolution {
+            Resolution::Raw => self.config.raw_retention_secs,
+            Resolution::Minute => self.config.minute_retention_secs,
+            Resolution::Hour => self.config.hour_retention_secs,
+        };
+        chrono::Duration::seconds(secs as i64)
+    }
+
+    /// Segment files of `resolution` with their start time, oldest first.
+    fn segments(&self, resolution: Resolution) -> io::Result<Vec<(i64, PathBuf)>> {
+        let prefix = format!("{}-", resolution.name());
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let start = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|name| name.strip_suffix(".seg"))
+                .and_then(|start| start.parse().ok());
+            if let Some(start) = start {
+                segments.push((start, path));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Delete segments whose newest possible record is past retention.
+    pub fn enforce_retention(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        for resolution in Resolution::ALL {
+            let cutoff = (now - self.retention(resolution)).timestamp();
+            for (start, path) in self.segments(resolution)? {
+                if start + resolution.segment_secs() <= cutoff && self.writers.get(&resolution).is_none_or(|w| w.start != start) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, resolution: Resolution, name: &str, point: &Point) -> io::Result<()> {
+        let start = floor_to(point.timestamp, resolution.segment_secs());
+        if self.writers.get(&resolution).is_none_or(|w| w.start != start) {
+            if let Some(mut previous) = self.writers.remove(&resolution) {
+                previous.out.flush()?;
+            }
+            let path = self.dir.join(format!("{}-{}.seg", resolution.name(), start));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut out = BufWriter::new(file);
+            if out.get_ref().metadata()?.len() == 0 {
+                out.write_all(MAGIC)?;
+                out.write_all(&[VERSION, resolution.code()])?;
+            }
+            self.writers.insert(resolution, SegmentWriter { start, out });
+            self.enforce_retention(point.timestamp)?;
+        }
+        write_record(&mut self.writers.get_mut(&resolution).expect("writer just opened").out, name, point)
+    }
+
+    /// Fold a finished minute bucket into its hour, writing out the hour it replaces.
+    fn roll_into_hour(&mut self, name: &str, minute: Point) -> io::Result<()> {
+        let hour_start = floor_to(minute.timestamp, 3600);
+        match self.hour.get_mut(name) {
+            Some(hour) if hour.timestamp.timestamp() == hour_start => hour.merge(&minute),
+            _ => {
+                let hour = Point { timestamp: DateTime::from_timestamp(hour_start, 0).expect("floored timestamp"), ..minute };
+                if let Some(previous) = self.hour.insert(name.to_string(), hour) {
+                    self.write(Resolution::Hour, name, &previous)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Close every rollup bucket that started before `timestamp`'s minute or hour.
+    fn close_buckets_before(&mut self, timestamp: DateTime<Utc>) -> io::Result<()> {
+        let minute_start = floor_to(timestamp, 60);
+        let finished: Vec<String> = self.minute.iter()
+            .filter(|(_, point)| point.timestamp.timestamp() < minute_start)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in finished {
+            let point = self.minute.remove(&name).expect("listed above");
+            self.write(Resolution::Minute, &name, &point)?;
+            self.roll_into_hour(&name, point)?;
+        }
+
+        let hour_start = floor_to(timestamp, 3600);
+        let finished: Vec<String> = self.hour.iter()
+            .filter(|(_, point)| point.timestamp.timestamp() < hour_start)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in finished {
+            let point = self.hour.remove(&name).expect("listed above");
+            self.write(Resolution::Hour, &name, &point)?;
+        }
+        Ok(())
+    }
+
+    fn skipped(&self, name: &str) -> bool {
+        self.config.skip.iter().any(|prefix| name.starts_with(prefix.as_str()))
+    }
+
+    /// Record one tick of metrics.
+    pub fn append(&mut self, timestamp: DateTime<Utc>, metrics: &BTreeMap<String, f64>) -> io::Result<()> {
+        self.close_buckets_before(timestamp)?;
+        let minute_start = DateTime::from_timestamp(floor_to(timestamp, 60), 0).expect("floored timestamp");
+        for (name, &value) in metrics {
+            if !value.is_finite() || self.skipped(name) {
+                continue;
+            }
+            let sample = Point::sample(timestamp, value);
+            self.write(Resolution::Raw, name, &sample)?;
+            self.minute.entry(name.clone())
+                .and_modify(|bucket| bucket.merge(&sample))
+                .or_insert(Point { timestamp: minute_start, ..sample });
+        }
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for writer in self.writers.values_mut() {
+            writer.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write out the unfinished rollup buckets, e.g. before exiting.
+    pub fn close(&mut self) -> io::Result<()> {
+        for (name, point) in std::mem::take(&mut self.minute) {
+            self.write(Resolution::Minute, &name, &point)?;
+            self.roll_into_hour(&name, point)?;
+        }
+        for (name, point) in std::mem::take(&mut self.hour) {
+            self.write(Resolution::Hour, &name, &point)?;
+        }
+        self.flush()
+    }
+
+    /// Points of `metric` between `from` and `to` at `resolution`, oldest first,
+    /// including the rollup bucket still being filled.
+    pub fn query(&self, metric: &str, from: DateTime<Utc>, to: DateTime<Utc>, resolution: Resolution) -> io::Result<Vec<Point>> {
+        let mut points = Vec::new();
+        for (start, path) in self.segments(resolution)? {
+            if start + resolution.segment_secs() > from.timestamp() && start <= to.timestamp() {
+                points.extend(read_segment(&path, resolution, metric)?);
+            }
+        }
+        let open = match resolution {
+            Resolution::Raw => None,
+            Resolution::Minute => self.minute.get(metric),
+            Resolution::Hour => self.hour.get(metric),
+        };
+        points.extend(open.copied());
+        if resolution == Resolution::Hour {
+            // The hour bucket only sees minutes once they are finished
+            points.extend(self.minute.get(metric).map(|minute| {
+                Point { timestamp: DateTime::from_timestamp(floor_to(minute.timestamp, 3600), 0).expect("floored timestamp"), ..*minute }
+            }));
+        }
+
+        points.retain(|p| p.timestamp >= from && p.timestamp <= to);
+        points.sort_by_key(|p| p.timestamp);
+        let mut merged: Vec<Point> = Vec::with_capacity(points.len());
+        for point in points {
+            match merged.last_mut() {
+                Some(last) if resolution != Resolution::Raw && last.timestamp == point.timestamp => last.merge(&point),
+                _ => merged.push(point),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(test: &str) -> StorageConfig {
+        let path = std::env::temp_dir().join(format!("grainx-tsdb-{}-{}", std::process::id(), test));
+        fs::remove_dir_all(&path).ok();
+        StorageConfig { path: path.display().to_string(), ..StorageConfig::default() }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        // An hour boundary recent enough to be inside the default retention
+        DateTime::from_timestamp(floor_to(Utc::now(), 3600) - 6 * 3600 + secs, 0).unwrap()
+    }
+
+    fn cpu(value: f64) -> BTreeMap<String, f64> {
+        BTreeMap::from([("cpu".to_string(), value), ("proc_cpu[sh]".to_string(), 1.0)])
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        let config = config("raw");
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        for i in 0..5 {
+            store.append(at(i), &cpu(i as f64 * 10.0)).unwrap();
+        }
+        let points = store.query("cpu", at(1), at(3), Resolution::Raw).unwrap();
+        assert_eq!(points.iter().map(|p| p.avg).collect::<Vec<_>>(), vec![10.0, 20.0, 30.0]);
+        assert!(store.query("proc_cpu[sh]", at(0), at(5), Resolution::Raw).unwrap().is_empty());
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_rollups() {
+        let config = config("rollups");
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        // Two full minutes and part of a third, one sample every 10s
+        for i in 0..15 {
+            store.append(at(i * 10), &cpu(if i % 6 == 0 { 90.0 } else { 30.0 })).unwrap();
+        }
+
+        let minutes = store.query("cpu", at(0), at(180), Resolution::Minute).unwrap();
+        assert_eq!(minutes.len(), 3);
+        assert_eq!(minutes[0].timestamp, at(0));
+        assert_eq!((minutes[0].min, minutes[0].max, minutes[0].avg, minutes[0].count), (30.0, 90.0, 40.0, 6));
+        assert_eq!(minutes[2].count, 3);
+
+        let hours = store.query("cpu", at(0), at(3600), Resolution::Hour).unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].count, 15);
+        assert_eq!(hours[0].max, 90.0);
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_history_survives_restart() {
+        let config = config("restart");
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        for i in 0..3 {
+            store.append(at(i), &cpu(20.0)).unwrap();
+        }
+        store.close().unwrap();
+        drop(store);
+
+        // The same minute continues after the restart
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        store.append(at(10), &cpu(80.0)).unwrap();
+        assert_eq!(store.query("cpu", at(0), at(60), Resolution::Raw).unwrap().len(), 4);
+        let minute = store.query("cpu", at(0), at(60), Resolution::Minute).unwrap();
+        assert_eq!(minute.len(), 1);
+        assert_eq!((minute[0].count, minute[0].avg), (4, 35.0));
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_truncated_record_is_ignored() {
+        let config = config("truncated");
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        store.append(at(0), &cpu(20.0)).unwrap();
+        store.append(at(1), &cpu(30.0)).unwrap();
+        drop(store);
+
+        let (_, path) = TimeSeriesStore::open(config.clone()).unwrap().segments(Resolution::Raw).unwrap().remove(0);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 5).unwrap();
+
+        let store = TimeSeriesStore::open(config.clone()).unwrap();
+        assert_eq!(store.query("cpu", at(0), at(5), Resolution::Raw).unwrap().len(), 1);
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_retention() {
+        let config = StorageConfig { raw_retention_secs: 3600, ..config("retention") };
+        let mut store = TimeSeriesStore::open(config.clone()).unwrap();
+        store.append(at(0), &cpu(20.0)).unwrap();
+        store.append(at(3 * 3600), &cpu(20.0)).unwrap();
+
+        assert_eq!(store.segments(Resolution::Raw).unwrap().len(), 1);
+        assert!(store.query("cpu", at(0), at(10), Resolution::Raw).unwrap().is_empty());
+        // Rollups are kept for longer
+        assert_eq!(store.query("cpu", at(0), at(10), Resolution::Minute).unwrap().len(), 1);
+        fs::remove_dir_all(&config.path).ok();
+    }
+
+    #[test]
+    fn test_resolution_for_span() {
+        assert_eq!(Resolution::for_span(chrono::Duration::minutes(30)), Resolution::Raw);
+        assert_eq!(Resolution::for_span(chrono::Duration::hours(12)), Resolution::Minute);
+        assert_eq!(Resolution::for_span(chrono::Duration::days(7)), Resolution::Hour);
+    }
+
+    #[test]
+    fn test_default_dir_is_per_user() {
+        assert_eq!(StorageConfig::default().dir().unwrap(), data_dir().unwrap().join("history"));
+        assert_eq!(config("dir").dir().unwrap(), PathBuf::from(config("dir").path));
+    }
+}
@@ -1,22 +1,722 @@
-use crate::monitor::SystemMonitor;
-use crate::rendering::{AdvancedCanvas, Rect};
-use crate::analytics::{AnomalyDetector, TimeSeriesPoint, calculate_correlation, evaluate_metric_formula, predict_next_value};
+use crate::rendering::{sparkline, AdvancedCanvas, Rect};
+use crate::alerts::{AlertEngine, AlertEvent, AlertEventKind, AlertState, Severity};
+use crate::api::ApiServer;
+use crate::analytics::{AnomalyDetector, CorrelationMatrix, CorrelationPair, TimeSeriesPoint};
 use crate::config::DashboardConfig;
+use crate::custom_metrics::{CustomMetricValue, CustomMetrics, ThresholdLevel};
+use crate::events::{Event, EventLog};
+use crate::exhaustion::{format_duration, ExhaustionTracker};
+use crate::export::FileExporter;
+use crate::expr::MetricContext;
+use crate::forecast::{forecast, ForecastPoint};
+use crate::history::MetricHistory;
+use crate::learning::ThresholdLearner;
+use crate::network::HostStatus;
+use crate::patterns::PatternDetector;
+use crate::prometheus::{self, PrometheusExporter};
+use crate::push::PushDispatcher;
+use crate::recording::Player;
+use crate::timeline::TimeView;
+use crate::tsdb::{Point, Resolution, TimeSeriesStore};
+use crate::notify::NotificationDispatcher;
+use crate::otlp::OtlpExporter;
 use crate::performance::PerformanceMonitor;
+use crate::snapshot::{keyed_metric, Snapshot};
 use crossterm::style::Color;
+use std::collections::BTreeMap;
 use std::io;
-use std::collections::HashMap;
+use std::time::Duration;
 use chrono::Utc;
 
+/// Rolling state the dashboard keeps between frames.
+pub struct DashboardState {
+    pub cpu_points: Vec<(f64, f64)>,
+    pub mem_points: Vec<(f64, f64)>,
+    pub metrics: MetricHistory,
+    pub iteration_count: i32,
+    pub current_cpu_y_val: f64, // For smooth animation
+    pub current_mem_y_val: f64, // For smooth animation
+    pub exhaustion: ExhaustionTracker,
+    pub custom_metrics: CustomMetrics,
+    pub alerts: AlertEngine,
+    /// Delivers alert transitions to the configured notifiers, if any
+    pub notifications: Option<NotificationDispatcher>,
+    pub events: EventLog,
+    pub learner: ThresholdLearner,
+    pub patterns: PatternDetector,
+    /// On-disk history, if storage is enabled
+    pub store: Option<TimeSeriesStore>,
+    /// Last failure writing to or reading from the store
+    pub store_error: Option<String>,
+    /// Zoom, pan and cursor position of the graphs
+    pub view: TimeView,
+    /// Side panel shown in place of the CPU graph
+    pub overlay: PanelOverlay,
+    /// Serves the latest tick on `/metrics`, if enabled
+    pub exporter: Option<PrometheusExporter>,
+    /// Writes every sample to CSV or JSON-lines files, if enabled
+    pub export: Option<FileExporter>,
+    /// Last failure writing an export file
+    pub export_error: Option<String>,
+    /// Pushes every sample to InfluxDB or StatsD, if configured
+    pub push: Option<PushDispatcher>,
+    /// Sends every snapshot to an OpenTelemetry collector, if enabled
+    pub otlp: Option<OtlpExporter>,
+    /// Serves the latest tick and recent history as JSON, if enabled
+    pub api: Option<ApiServer>,
+    /// Outcome of the last config reload
+    pub reload_status: Option<ReloadStatus>,
+}
+
+/// What became of the last attempt to reload the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReloadStatus {
+    /// Applied, except for the listed sections that need a restart
+    Applied { at: chrono::DateTime<Utc>, restart_required: Vec<&'static str> },
+    /// The file was rejected and the running config kept
+    Failed { at: chrono::DateTime<Utc>, error: String },
+}
+
+/// A side panel drawn over the CPU graph, for terminals too small to show
+/// the panels next to the graphs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanelOverlay {
+    #[default]
+    Off,
+    Alerts,
+    Events,
+    Learning,
+}
+
+impl PanelOverlay {
+    /// The panel after this one, cycling back to the graph.
+    pub fn next(self) -> Self {
+        match self {
+            PanelOverlay::Off => PanelOverlay::Alerts,
+            PanelOverlay::Alerts => PanelOverlay::Events,
+            PanelOverlay::Events => PanelOverlay::Learning,
+            PanelOverlay::Learning => PanelOverlay::Off,
+        }
+    }
+}
+
+/// Everything derived from one snapshot before anything is drawn.
+pub struct Tick {
+    /// Snapshot metrics plus successfully evaluated custom metrics
+    pub metrics: BTreeMap<String, f64>,
+    pub custom_values: Vec<CustomMetricValue>,
+    pub alert_events: Vec<AlertEvent>,
+    /// Events raised while processing the snapshot, e.g. learned thresholds moving
+    pub events: Vec<Event>,
+}
+
+impl DashboardState {
+    pub fn new(config: &DashboardConfig) -> io::Result<Self> {
+        let store = if config.storage.enabled {
+            Some(TimeSeriesStore::open(config.storage.clone())?)
+        } else {
+            None
+        };
+        let mut metrics = MetricHistory::new(config.graph_history_size);
+        if let Some(store) = &store {
+            backfill(&mut metrics, store, config.graph_history_size)?;
+        }
+
+        Ok(DashboardState {
+            cpu_points: Vec::new(),
+            mem_points: Vec::new(),
+            metrics,
+            iteration_count: 0,
+            current_cpu_y_val: 0.0,
+            current_mem_y_val: 0.0,
+            exhaustion: ExhaustionTracker::new(config.exhaustion.clone()),
+            custom_metrics: CustomMetrics::compile(&config.custom_metrics),
+            alerts: AlertEngine::new(&config.alerts),
+            notifications: NotificationDispatcher::start(&config.notifiers)?,
+            events: EventLog::new(50),
+            // A corrupt state file only costs what was learned so far
+            learner: ThresholdLearner::load(config.learning.clone())
+                .unwrap_or_else(|_| ThresholdLearner::new(config.learning.clone())),
+            patterns: PatternDetector::new(config.patterns.clone()),
+            store,
+            store_error: None,
+            view: TimeView::new(),
+            overlay: PanelOverlay::Off,
+            exporter: if config.prometheus.enabled { Some(PrometheusExporter::start(&config.prometheus)?) } else { None },
+            export: if config.export.enabled { Some(FileExporter::new(config.export.clone())?) } else { None },
+            export_error: None,
+            push: PushDispatcher::start(&config.push)?,
+            otlp: if config.otlp.enabled { Some(OtlpExporter::start(&config.otlp)?) } else { None },
+            api: if config.api.enabled { Some(ApiServer::start(&config.api)?) } else { None },
+            reload_status: None,
+        })
+    }
+
+    /// Switch `config` to a freshly loaded file without losing history, or
+    /// keep it if loading failed. Thresholds, display options, custom
+    /// metrics, alert rules, exhaustion and pattern settings apply right
+    /// away, and a changed `refresh_interval_ms` becomes the fixed refresh
+    /// interval; see `DashboardConfig::applied_to` for the rest.
+    pub fn reload(&mut self, config: &mut DashboardConfig, loaded: Result<DashboardConfig, String>, perf: &mut PerformanceMonitor) -> &ReloadStatus {
+        let at = Utc::now();
+        let status = match loaded {
+            Ok(loaded) => {
+                let (loaded, restart_required) = loaded.applied_to(config);
+                self.metrics.set_capacity(loaded.graph_history_size);
+                self.custom_metrics = CustomMetrics::compile(&loaded.custom_metrics);
+                self.alerts.reconfigure(&loaded.alerts);
+                self.exhaustion.config = loaded.exhaustion.clone();
+                self.patterns.config = loaded.patterns.clone();
+                if loaded.refresh_interval_ms != config.refresh_interval_ms {
+                    perf.set_refresh_interval(Some(loaded.refresh_interval_ms));
+                }
+                *config = loaded;
+                ReloadStatus::Applied { at, restart_required }
+            }
+            Err(error) => ReloadStatus::Failed { at, error },
+        };
+        self.reload_status.insert(status)
+    }
+
+    /// Record a snapshot into the history and run custom metrics, alerts,
+    /// notifications and exhaustion tracking on it.
+    pub fn update(&mut self, snapshot: &Snapshot) -> Tick {
+        // Metric history (bounded by graph_history_size). Exhaustion
+        // forecasts join the metrics so alert rules can use them.
+        self.exhaustion.record(snapshot);
+        let mut metrics = snapshot.metrics();
+        metrics.extend(self.exhaustion.metrics());
+        self.metrics.record_all(snapshot.timestamp, &metrics);
+        let custom_values = self.custom_metrics.evaluate(&mut metrics, &self.metrics);
+        for value in &custom_values {
+            if let Ok(v) = value.value {
+                self.metrics.record(&value.name, snapshot.timestamp, v);
+            }
+        }
+        if let Some((oldest, _)) = self.metrics.samples("cpu").and_then(|samples| samples.front()) {
+            self.metrics.prune_stale(*oldest);
+        }
+
+        let alert_events = self.alerts.evaluate(&MetricContext { current: &metrics, history: &self.metrics }, snapshot.timestamp);
+        if let Some(notifications) = &self.notifications {
+            notifications.dispatch(&snapshot.hostname, &alert_events);
+        }
+        if let Some(store) = &mut self.store {
+            self.store_error = store.append(snapshot.timestamp, &metrics).err().map(|e| e.to_string());
+        }
+        if let Some(export) = &mut self.export {
+            self.export_error = export.write(snapshot.timestamp, &snapshot.hostname, &metrics).err().map(|e| e.to_string());
+        }
+        if let Some(push) = &self.push {
+            push.push(&snapshot.hostname, snapshot.timestamp, &metrics);
+        }
+        if let Some(otlp) = &self.otlp {
+            otlp.export(snapshot);
+        }
+
+        let mut events = Vec::new();
+        if self.learner.config.enabled {
+            events.extend(self.learner.observe(&metrics, snapshot.timestamp)
+                .into_iter()
+                .map(|kind| Event { timestamp: snapshot.timestamp, kind }));
+            // Failures here surface when the state is saved on exit
+            self.learner.save_if_due(snapshot.timestamp, chrono::Duration::minutes(5)).ok();
+        }
+        if self.patterns.config.enabled {
+            events.extend(self.patterns.check(&self.metrics, snapshot.timestamp)
+                .into_iter()
+                .map(|kind| Event { timestamp: snapshot.timestamp, kind }));
+        }
+        self.events.extend(events.iter().cloned());
+        if let Some(exporter) = &self.exporter {
+            exporter.publish(prometheus::render(snapshot, &custom_values, &self.alerts));
+        }
+        if let Some(api) = &self.api {
+            api.publish(snapshot, &metrics, &self.alerts);
+        }
+
+        Tick { metrics, custom_values, alert_events, events }
+    }
+}
+
+/// Seed the in-memory history with the last hour of stored CPU and memory
+/// samples, so the graphs pick up where the previous run left off. Other
+/// series, and expressions over them such as `avg_over`, start empty.
+fn backfill(history: &mut MetricHistory, store: &TimeSeriesStore, capacity: usize) -> io::Result<()> {
+    let now = Utc::now();
+    for metric in ["cpu", "mem_pct"] {
+        let points = store.query(metric, now - chrono::Duration::hours(1), now, Resolution::Raw)?;
+        for point in &points[points.len().saturating_sub(capacity)..] {
+            history.record(metric, point.timestamp, point.avg);
+        }
+    }
+    Ok(())
+}
+
+/// Graph points for a percentage series, used to start the graphs from stored history.
+fn graph_points(values: &[f64], height: u16, capacity: usize) -> Vec<(f64, f64)> {
+    values[values.len().saturating_sub(capacity)..]
+        .iter()
+        .enumerate()
+        .map(|(x, v)| (x as f64, v / 100.0 * height as f64))
+        .collect()
+}
+
+/// Bucketed history of `metric` for the zoomed graph window. Store read
+/// failures are reported like write failures and leave the graph empty.
+fn view_columns(state: &mut DashboardState, metric: &str, now: chrono::DateTime<Utc>) -> Vec<Option<Point>> {
+    match state.view.columns_for(metric, &state.metrics, state.store.as_ref(), now) {
+        Ok(columns) => columns,
+        Err(e) => {
+            state.store_error = Some(e.to_string());
+            Vec::new()
+        }
+    }
+}
+
+/// Draw bucketed percentage history: the average as a line and, where a
+/// column holds several samples, their min-max range as a band.
+fn draw_history_graph(canvas: &mut AdvancedCanvas, columns: &[Option<Point>], rect: &Rect, color: Color) -> io::Result<()> {
+    let scale = rect.height as f64 / 100.0;
+    let points: Vec<(f64, f64)> = columns.iter()
+        .enumerate()
+        .filter_map(|(x, point)| point.map(|p| (x as f64, p.avg.clamp(0.0, 100.0) * scale)))
+        .collect();
+    let band: Vec<(f64, f64, f64)> = columns.iter()
+        .enumerate()
+        .filter_map(|(x, point)| point.filter(|p| p.count > 1).map(|p| (x as f64, p.min.clamp(0.0, 100.0) * scale, p.max.clamp(0.0, 100.0) * scale)))
+        .collect();
+    canvas.draw_braille_forecast(&points, &band, rect, color, Color::DarkGrey)
+}
+
+fn describe_column(name: &str, point: &Option<Point>) -> String {
+    match point {
+        Some(p) if p.count > 1 => format!("{} {:.1}% ({:.1}-{:.1}, {} samples)", name, p.avg, p.min, p.max, p.count),
+        Some(p) => format!("{} {:.1}%", name, p.avg),
+        None => format!("{} -", name),
+    }
+}
+
+/// The window, zoom and cursor readout on `status_y`, and the cursor marker on `marker_y`.
+fn draw_time_view(
+    canvas: &mut AdvancedCanvas,
+    view: &TimeView,
+    now: chrono::DateTime<Utc>,
+    cpu_columns: &[Option<Point>],
+    mem_columns: &[Option<Point>],
+    status_y: u16,
+    marker_y: u16,
+) -> io::Result<()> {
+    let width = view.columns as usize;
+    for y in [status_y, marker_y] {
+        canvas.set_cursor(0, y)?;
+        canvas.draw_str(&" ".repeat(width))?;
+    }
+    let Some((from, to)) = view.window(now) else { return Ok(()) };
+
+    let local = |t: chrono::DateTime<Utc>| t.with_timezone(&chrono::Local);
+    let mut line = format!("[{}] {} - {}", view.zoom_label(), local(from).format("%m-%d %H:%M:%S"), local(to).format("%H:%M:%S"));
+    if let Some(cursor) = view.cursor {
+        let column = cursor as usize;
+        let cpu = cpu_columns.get(column).copied().flatten();
+        let mem = mem_columns.get(column).copied().flatten();
+        match cpu.or(mem) {
+            Some(point) => line = format!("{} | {} {} {}", view.zoom_label(), local(point.timestamp).format("%m-%d %H:%M:%S"),
+                describe_column("cpu", &cpu), describe_column("mem", &mem)),
+            None => line.push_str(" | no data"),
+        }
+        canvas.set_cursor(cursor, marker_y)?;
+        canvas.set_color(Color::Yellow)?;
+        canvas.draw_str("▲")?;
+    }
+    canvas.set_cursor(0, status_y)?;
+    canvas.set_color(Color::Yellow)?;
+    canvas.draw_str(&line.chars().take(width).collect::<String>())
+}
+
+/// Append a graph point, scrolling older points off the left edge once `capacity` is reached.
+fn push_graph_point(points: &mut Vec<(f64, f64)>, y: f64, capacity: usize) {
+    let x = points.last().map(|p| p.0 + 1.0).unwrap_or(0.0);
+    points.push((x, y));
+    while points.len() > capacity.max(1) {
+        points.remove(0);
+        for p in points.iter_mut() {
+            p.0 -= 1.0;
+        }
+    }
+}
+
+/// Map percentage forecasts onto the graph columns following the last point.
+fn forecast_band(points: &[(f64, f64)], forecast: &[ForecastPoint], rect: &Rect) -> Vec<(f64, f64, f64)> {
+    let last_x = points.last().map(|p| p.0).unwrap_or(0.0);
+    let scale = rect.height as f64 / 100.0;
+    forecast.iter()
+        .enumerate()
+        .map(|(h, point)| {
+            let point = point.clamped(0.0, 100.0);
+            (last_x + h as f64 + 1.0, point.lower * scale, point.upper * scale)
+        })
+        .collect()
+}
+
+/// System-wide series shown in the correlation matrix, in display order.
+const CORRELATION_METRICS: [&str; 7] = ["cpu", "mem_pct", "net_rx", "net_tx", "disk_read", "disk_write", "load1"];
+/// How many of the busiest processes join the matrix.
+const CORRELATION_TOP_PROCESSES: usize = 3;
+/// Absolute correlation at which a pair is highlighted.
+const STRONG_CORRELATION: f64 = 0.7;
+const CORRELATION_MAX_LAG: usize = 5;
+
+/// The correlated metrics that have history, plus the current top processes.
+fn correlation_names(history: &MetricHistory, snapshot: &Snapshot) -> Vec<String> {
+    let mut names: Vec<String> = CORRELATION_METRICS.iter()
+        .filter(|name| history.samples(name).is_some())
+        .map(|name| name.to_string())
+        .collect();
+    let system = names.len();
+    for (_, name, _, _) in &snapshot.processes {
+        let metric = keyed_metric("proc_cpu", name);
+        if names.len() >= system + CORRELATION_TOP_PROCESSES {
+            break;
+        }
+        if !names.contains(&metric) && history.samples(&metric).is_some() {
+            names.push(metric);
+        }
+    }
+    names
+}
+
+/// Compact label for matrix headers: process series show just the process name.
+fn short_label(metric: &str) -> String {
+    let label = metric.strip_prefix("proc_cpu[")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(metric);
+    label.chars().take(8).collect()
+}
+
+fn correlation_color(correlation: f64) -> Color {
+    if correlation.abs() >= STRONG_CORRELATION {
+        if correlation > 0.0 { Color::Red } else { Color::Cyan }
+    } else if correlation.abs() >= 0.4 {
+        Color::Yellow
+    } else {
+        Color::DarkGrey
+    }
+}
+
+fn draw_correlation_panel(
+    canvas: &mut AdvancedCanvas,
+    rect: &Rect,
+    matrix: &CorrelationMatrix,
+    pairs: &[CorrelationPair],
+) -> io::Result<()> {
+    for y in rect.y..(rect.y + rect.height) {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&" ".repeat(rect.width as usize))?;
+    }
+
+    canvas.set_cursor(rect.x, rect.y)?;
+    canvas.set_color(Color::Cyan)?;
+    canvas.draw_str("Correlations")?;
+
+    // Header row with one 6-wide column per series
+    canvas.set_cursor(rect.x, rect.y + 1)?;
+    canvas.set_color(Color::White)?;
+    let header: String = matrix.names.iter().map(|name| format!("{:>6}", short_label(name).chars().take(5).collect::<String>())).collect();
+    canvas.draw_str(&format!("{:9}{}", "", header))?;
+
+    let mut y = rect.y + 2;
+    for (i, name) in matrix.names.iter().enumerate() {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.set_color(Color::White)?;
+        canvas.draw_str(&format!("{:9}", short_label(name)))?;
+        for j in 0..matrix.names.len() {
+            match matrix.get(i, j) {
+                _ if i == j => {
+                    canvas.set_color(Color::DarkGrey)?;
+                    canvas.draw_str(&format!("{:>6}", "--"))?;
+                }
+                Some(correlation) => {
+                    canvas.set_color(correlation_color(correlation))?;
+                    canvas.draw_str(&format!("{:>6.2}", correlation))?;
+                }
+                None => {
+                    canvas.set_color(Color::DarkGrey)?;
+                    canvas.draw_str(&format!("{:>6}", "n/a"))?;
+                }
+            }
+        }
+        y += 1;
+    }
+
+    // Strongest relationships, with the lag at which they peak
+    for pair in pairs.iter().take((rect.y + rect.height).saturating_sub(y + 1) as usize) {
+        y += 1;
+        canvas.set_cursor(rect.x, y)?;
+        canvas.set_color(correlation_color(pair.correlation))?;
+        let lag = if pair.lag == 0 {
+            String::new()
+        } else {
+            format!(" (peak {:+.2} at lag {:+})", pair.lagged_correlation, pair.lag)
+        };
+        canvas.draw_str(&format!("{} ~ {}: {:+.2}{}", short_label(&pair.first), short_label(&pair.second), pair.correlation, lag))?;
+    }
+
+    Ok(())
+}
+
+fn level_color(level: ThresholdLevel) -> Color {
+    match level {
+        ThresholdLevel::Normal => Color::Green,
+        ThresholdLevel::Warning => Color::Yellow,
+        ThresholdLevel::Critical => Color::Red,
+    }
+}
+
+fn draw_custom_metrics_panel(
+    canvas: &mut AdvancedCanvas,
+    rect: &Rect,
+    custom: &CustomMetrics,
+    values: &[CustomMetricValue],
+    history: &MetricHistory,
+) -> io::Result<()> {
+    for y in rect.y..(rect.y + rect.height) {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&" ".repeat(rect.width as usize))?;
+    }
+
+    canvas.set_cursor(rect.x, rect.y)?;
+    canvas.set_color(Color::Cyan)?;
+    canvas.draw_str("Custom Metrics")?;
+
+    let spark_width = (rect.width as usize).saturating_sub(30);
+    for (i, (metric, value)) in custom.metrics.iter().zip(values).enumerate().take(rect.height.saturating_sub(1) as usize) {
+        let y = rect.y + 1 + i as u16;
+        canvas.set_cursor(rect.x, y)?;
+        canvas.set_color(Color::White)?;
+        canvas.draw_str(&format!("{:16.16} ", metric.config.name))?;
+        match &value.value {
+            Ok(v) => {
+                canvas.set_color(level_color(value.level))?;
+                canvas.draw_str(&format!("{:>12} ", metric.config.format_value(*v)))?;
+                if metric.config.graph {
+                    canvas.set_color(Color::DarkGrey)?;
+                    canvas.draw_str(&sparkline(&history.values(&metric.config.name), spark_width))?;
+                }
+            }
+            Err(error) => {
+                canvas.set_color(Color::DarkGrey)?;
+                let message: String = error.to_string().chars().take(rect.width as usize - 17).collect();
+                canvas.draw_str(&message)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Info => Color::Cyan,
+        Severity::Warning => Color::Yellow,
+        Severity::Critical => Color::Red,
+    }
+}
+
+fn draw_alerts_panel(
+    canvas: &mut AdvancedCanvas,
+    rect: &Rect,
+    alerts: &AlertEngine,
+    notify_failures: &[String],
+    now: chrono::DateTime<Utc>,
+) -> io::Result<()> {
+    for y in rect.y..(rect.y + rect.height) {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&" ".repeat(rect.width as usize))?;
+    }
+
+    let firing = alerts.firing();
+    let pending = alerts.pending();
+    canvas.set_cursor(rect.x, rect.y)?;
+    canvas.set_color(if firing.is_empty() { Color::Cyan } else { Color::Red })?;
+    canvas.draw_str(&format!("Alerts: {} firing, {} pending", firing.len(), pending.len()))?;
+
+    let width = rect.width as usize;
+    // Keep the last row for the newest delivery failure
+    let bottom = rect.y + rect.height - u16::from(!notify_failures.is_empty());
+    if let Some(failure) = notify_failures.first() {
+        canvas.set_cursor(rect.x, bottom)?;
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str(&format!("notify failed: {}", failure).chars().take(width).collect::<String>())?;
+    }
+
+    let mut y = rect.y + 1;
+    let value = |v: Option<f64>| v.map(|v| format!("{:.1}", v)).unwrap_or_else(|| "n/a".to_string());
+
+    for rule in firing.iter().chain(pending.iter()) {
+        if y >= bottom {
+            return Ok(());
+        }
+        let line = match rule.state {
+            AlertState::Firing { since } => {
+                canvas.set_color(severity_color(rule.config.severity))?;
+                format!("{:8} {} = {} for {}{}", rule.config.severity.label().to_uppercase(), rule.config.display_name(),
+                    value(rule.last_value), format_duration((now - since).to_std().unwrap_or_default()),
+                    if rule.acknowledged { " (ack)" } else { "" })
+            }
+            _ => {
+                canvas.set_color(Color::DarkGrey)?;
+                format!("{:8} {} = {}", "pending", rule.config.display_name(), value(rule.last_value))
+            }
+        };
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&line.chars().take(width).collect::<String>())?;
+        y += 1;
+    }
+
+    for event in alerts.recent() {
+        if y >= bottom {
+            break;
+        }
+        canvas.set_cursor(rect.x, y)?;
+        canvas.set_color(match event.kind {
+            AlertEventKind::Firing => severity_color(event.severity),
+            AlertEventKind::Resolved => Color::Green,
+        })?;
+        let line = format!("{} {}", event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S"), event.describe());
+        canvas.draw_str(&line.chars().take(width).collect::<String>())?;
+        y += 1;
+    }
+
+    Ok(())
+}
+
+fn draw_events_panel(canvas: &mut AdvancedCanvas, rect: &Rect, events: &EventLog) -> io::Result<()> {
+    for y in rect.y..(rect.y + rect.height) {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&" ".repeat(rect.width as usize))?;
+    }
+
+    canvas.set_cursor(rect.x, rect.y)?;
+    canvas.set_color(Color::Cyan)?;
+    canvas.draw_str("Events")?;
+    if events.is_empty() {
+        canvas.set_cursor(rect.x, rect.y + 1)?;
+        canvas.set_color(Color::DarkGrey)?;
+        canvas.draw_str("nothing detected yet")?;
+    }
+
+    for (i, event) in events.recent().take(rect.height.saturating_sub(1) as usize).enumerate() {
+        canvas.set_cursor(rect.x, rect.y + 1 + i as u16)?;
+        canvas.set_color(severity_color(event.severity()))?;
+        let line = format_event(event);
+        canvas.draw_str(&line.chars().take(rect.width as usize).collect::<String>())?;
+    }
+
+    Ok(())
+}
+
+fn draw_learning_panel(canvas: &mut AdvancedCanvas, rect: &Rect, learner: &ThresholdLearner) -> io::Result<()> {
+    for y in rect.y..(rect.y + rect.height) {
+        canvas.set_cursor(rect.x, y)?;
+        canvas.draw_str(&" ".repeat(rect.width as usize))?;
+    }
+
+    canvas.set_cursor(rect.x, rect.y)?;
+    let local = |at: &chrono::DateTime<Utc>| at.with_timezone(&chrono::Local).format("%H:%M:%S");
+    let (color, title) = match learner.last_reset() {
+        None => (Color::Cyan, "Learned thresholds ('l' to reset)".to_string()),
+        Some((at, None)) => (Color::Cyan, format!("Learned thresholds ('l' to reset) - reset at {}", local(&at))),
+        Some((at, Some(error))) => (Color::Red, format!("Learned thresholds - reset at {} not saved: {}", local(&at), error)),
+    };
+    canvas.set_color(color)?;
+    canvas.draw_str(&title.chars().take(rect.width as usize).collect::<String>())?;
+
+    for (i, metric) in learner.config.metrics.iter().enumerate().take(rect.height.saturating_sub(1) as usize) {
+        canvas.set_cursor(rect.x, rect.y + 1 + i as u16)?;
+        let line = match learner.baselines().find(|(name, _)| name == metric) {
+            Some((_, baseline)) if baseline.samples >= learner.config.min_samples => {
+                canvas.set_color(Color::White)?;
+                format!("{:10} threshold {:5.1}  baseline {:5.1} ± {:4.1}  ({} samples)",
+                    metric, baseline.threshold, baseline.mean, baseline.std_dev(), baseline.samples)
+            }
+            Some((_, baseline)) => {
+                canvas.set_color(Color::DarkGrey)?;
+                format!("{:10} threshold {:5.1}  learning ({}/{} samples)",
+                    metric, baseline.threshold, baseline.samples, learner.config.min_samples)
+            }
+            None => {
+                canvas.set_color(Color::DarkGrey)?;
+                format!("{:10} threshold {:5.1}  no data yet", metric, learner.threshold(metric))
+            }
+        };
+        canvas.draw_str(&line.chars().take(rect.width as usize).collect::<String>())?;
+    }
+
+    Ok(())
+}
+
+/// Replay position, speed and controls on one line.
+pub fn draw_replay_status(canvas: &mut AdvancedCanvas, player: &Player, y: u16) -> io::Result<()> {
+    let (shown, total) = player.position();
+    let state = if player.is_finished() {
+        "END"
+    } else if player.paused {
+        "PAUSED"
+    } else {
+        "PLAYING"
+    };
+    canvas.set_cursor(0, y)?;
+    canvas.set_color(Color::Magenta)?;
+    let line = format!("REPLAY {} {}x | {} | frame {}/{} | space=pause +/-=speed ←/→=seek",
+        state,
+        player.speed(),
+        player.clock().with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+        shown,
+        total);
+    canvas.draw_str(&format!("{:80}", line))
+}
+
+/// Status line under a remote host's dashboard in the multi-host view.
+pub fn draw_host_status(canvas: &mut AdvancedCanvas, status: &HostStatus, y: u16) -> io::Result<()> {
+    let state = match (status.connected, status.stale) {
+        (false, _) => "DISCONNECTED".to_string(),
+        (true, true) => format!("STALE {}s", status.silent_for.as_secs()),
+        (true, false) => "LIVE".to_string(),
+    };
+    canvas.set_cursor(0, y)?;
+    canvas.set_color(if status.connected && !status.stale { Color::Magenta } else { Color::Yellow })?;
+    let line = format!("HOST {} {} | {} | esc=back to hosts", status.host, state, status.address);
+    canvas.draw_str(&format!("{:80}", line))
+}
+
+/// The outcome of the last config reload on one line.
+pub fn draw_reload_status(canvas: &mut AdvancedCanvas, status: &ReloadStatus, y: u16) -> io::Result<()> {
+    let local = |at: &chrono::DateTime<Utc>| at.with_timezone(&chrono::Local).format("%H:%M:%S");
+    let (color, line) = match status {
+        ReloadStatus::Applied { at, restart_required } if restart_required.is_empty() => {
+            (Color::Green, format!("CONFIG reloaded at {}", local(at)))
+        }
+        ReloadStatus::Applied { at, restart_required } => {
+            (Color::Yellow, format!("CONFIG reloaded at {}; restart to apply {}", local(at), restart_required.join(", ")))
+        }
+        ReloadStatus::Failed { at, error } => (Color::Red, format!("CONFIG rejected at {}, keeping the running config: {}", local(at), error)),
+    };
+    canvas.set_cursor(0, y)?;
+    canvas.set_color(color)?;
+    canvas.draw_str(&format!("{:80}", line.chars().take(160).collect::<String>()))
+}
+
+/// One-line event text for the events panel.
+fn format_event(event: &Event) -> String {
+    format!("{} {}", event.timestamp.with_timezone(&chrono::Local).format("%H:%M:%S"), event.describe())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn draw_dashboard(
     canvas: &mut AdvancedCanvas, 
-    monitor: &mut SystemMonitor, 
-    processes: &Vec<(usize, String, f32, u64)>, 
-    cpu_points: &mut Vec<(f64, f64)>, 
-    mem_points: &mut Vec<(f64, f64)>, 
-    cpu_history: &mut Vec<f64>,
-    dummy_metric_history: &mut Vec<f64>,
-    iteration_count: &mut i32,
+    snapshot: &Snapshot,
+    state: &mut DashboardState,
     selected_process: usize,
     dashboard_config: &DashboardConfig,
     anomaly_detector: &AnomalyDetector,
@@ -24,29 +724,28 @@ pub async fn draw_dashboard(
     mem_rect: &Rect,
     network_start_y: u16,
     proc_start_y: u16,
-    current_cpu_y_val: &mut f64,
-    current_mem_y_val: &mut f64,
     perf_monitor: &mut PerformanceMonitor
 ) -> io::Result<()> {
     // Clear screen
     canvas.set_cursor(0, 0)?;
     
-    // Get system metrics
-    let cpu_usage = monitor.get_cpu_usage();
-    let (used_memory, total_memory) = monitor.get_memory_usage();
-    let memory_percentage = (used_memory as f64 / total_memory as f64) * 100.0;
-    let (rx_bytes, tx_bytes) = monitor.get_network_io();
-    let cpu_cores = monitor.get_cpu_cores();
-    let disk_info = monitor.get_disk_usage();
-    let (os_name, kernel_version, uptime) = monitor.get_system_info();
+    // System metrics for this tick
+    let cpu_usage = snapshot.cpu_usage;
+    let (used_memory, total_memory) = (snapshot.used_memory, snapshot.total_memory);
+    let memory_percentage = snapshot.memory_percentage();
+    let (rx_bytes, tx_bytes) = (snapshot.network_rx, snapshot.network_tx);
+    let cpu_cores = &snapshot.cpu_cores;
+    let disk_info = &snapshot.disks;
+    let (os_name, kernel_version, uptime) = (&snapshot.os_name, &snapshot.kernel_version, snapshot.uptime);
+    let processes = &snapshot.processes;
     
     // Update iteration count
-    *iteration_count += 1;
+    state.iteration_count += 1;
     
     // === HEADER ===
     canvas.set_color(Color::Cyan)?;
     canvas.set_cursor(0, 0)?;
-    canvas.draw_str(&format!("=== grainx System Monitor === Iteration: {} ===", iteration_count))?;
+    canvas.draw_str(&format!("=== grainx System Monitor === Iteration: {} ===", state.iteration_count))?;
     
     // === CPU SECTION ===
     canvas.set_cursor(0, 2)?;
@@ -65,15 +764,47 @@ pub async fn draw_dashboard(
     canvas.set_cursor(12, 2)?;
     canvas.draw_str(&format!("{:6.2}%", cpu_usage))?;
     
-    // CPU History and Analytics
-    cpu_history.push(cpu_usage as f64);
-    dummy_metric_history.push((cpu_usage as f64 * 0.5) + (*iteration_count as f64 * 0.1));
-    
-    // Keep history size manageable (using config)
-    if cpu_history.len() > dashboard_config.graph_history_size {
-        cpu_history.remove(0);
-        dummy_metric_history.remove(0);
+    let tick = state.update(snapshot);
+    let custom_values = tick.custom_values;
+    let firing_count = state.alerts.firing().iter().filter(|rule| !rule.acknowledged).count();
+    canvas.set_cursor(60, 0)?;
+    if firing_count > 0 {
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str(&format!("ALERTS: {:<3}", firing_count))?;
+    } else {
+        canvas.draw_str(&" ".repeat(11))?;
     }
+    canvas.set_cursor(72, 0)?;
+    if state.store_error.is_some() {
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str("HISTORY!")?;
+    } else if state.export_error.is_some() {
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str("EXPORT! ")?;
+    } else if state.push.as_ref().is_some_and(|push| push.is_failing()) || state.otlp.as_ref().is_some_and(|otlp| otlp.is_failing()) {
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str("PUSH!   ")?;
+    } else if state.exporter.as_ref().is_some_and(|exporter| exporter.last_error().is_some()) || state.api.as_ref().is_some_and(|api| api.last_error().is_some()) {
+        canvas.set_color(Color::Red)?;
+        canvas.draw_str("HTTP!   ")?;
+    } else {
+        canvas.draw_str(&" ".repeat(8))?;
+    }
+    let cpu_history = state.metrics.values("cpu");
+    let mem_history = state.metrics.values("mem_pct");
+
+    // Forecasts for the graph ribbons, leaving room for them at the right edge
+    let (cpu_forecast, mem_forecast) = if dashboard_config.show_predictions {
+        (forecast(&cpu_history, &dashboard_config.forecast),
+         forecast(&mem_history, &dashboard_config.forecast))
+    } else {
+        (None, None)
+    };
+    let forecast_columns = if dashboard_config.show_predictions {
+        dashboard_config.forecast.horizon.min(cpu_rect.width as usize / 2)
+    } else {
+        0
+    };
     
     // Anomaly Detection
     let current_point = TimeSeriesPoint {
@@ -87,17 +818,30 @@ pub async fn draw_dashboard(
         canvas.draw_str(&format!("! ANOMALY: {}", anomaly.message))?;
     }
     
+    // Zoomed or panned history replaces the live graphs
+    state.view.columns = cpu_rect.width;
+    let (cpu_view, mem_view) = if state.view.is_live() {
+        (None, None)
+    } else {
+        (Some(view_columns(state, "cpu", snapshot.timestamp)), Some(view_columns(state, "mem_pct", snapshot.timestamp)))
+    };
+
+    // Start from stored history after a restart
+    if state.cpu_points.is_empty() && cpu_history.len() > 1 {
+        let history = &cpu_history[..cpu_history.len() - 1];
+        state.cpu_points = graph_points(history, cpu_rect.height, cpu_rect.width as usize - forecast_columns);
+        state.current_cpu_y_val = state.cpu_points.last().map(|p| p.1).unwrap_or(0.0);
+    }
+    if state.mem_points.is_empty() && mem_history.len() > 1 {
+        let history = &mem_history[..mem_history.len() - 1];
+        state.mem_points = graph_points(history, mem_rect.height, mem_rect.width as usize - forecast_columns);
+        state.current_mem_y_val = state.mem_points.last().map(|p| p.1).unwrap_or(0.0);
+    }
+
     // CPU Graph
     let target_cpu_y = (cpu_usage as f64 / 100.0) * cpu_rect.height as f64;
-    *current_cpu_y_val = *current_cpu_y_val * 0.8 + target_cpu_y * 0.2;
-    
-    cpu_points.push((*iteration_count as f64, *current_cpu_y_val));
-    if cpu_points.len() > cpu_rect.width as usize {
-        cpu_points.remove(0);
-        for p in cpu_points.iter_mut() {
-            p.0 -= 1.0;
-        }
-    }
+    state.current_cpu_y_val = state.current_cpu_y_val * 0.8 + target_cpu_y * 0.2;
+    push_graph_point(&mut state.cpu_points, state.current_cpu_y_val, cpu_rect.width as usize - forecast_columns);
     
     // Clear CPU graph area
     for y in cpu_rect.y..(cpu_rect.y + cpu_rect.height) {
@@ -106,7 +850,21 @@ pub async fn draw_dashboard(
     }
     
     canvas.set_color(cpu_color)?;
-    canvas.draw_braille_line(&cpu_points, &cpu_rect)?;
+    let overlay_rect = Rect { x: cpu_rect.x, y: cpu_rect.y + 1, width: cpu_rect.width, height: cpu_rect.height - 1 };
+    match (state.overlay, &cpu_view, &cpu_forecast) {
+        (PanelOverlay::Alerts, _, _) => {
+            let failures = state.notifications.as_ref().map(|n| n.failures()).unwrap_or_default();
+            draw_alerts_panel(canvas, &overlay_rect, &state.alerts, &failures, snapshot.timestamp)?;
+        }
+        (PanelOverlay::Events, _, _) => draw_events_panel(canvas, &overlay_rect, &state.events)?,
+        (PanelOverlay::Learning, _, _) => draw_learning_panel(canvas, &overlay_rect, &state.learner)?,
+        (PanelOverlay::Off, Some(columns), _) => draw_history_graph(canvas, columns, cpu_rect, cpu_color)?,
+        (PanelOverlay::Off, None, Some(points)) => {
+            let band = forecast_band(&state.cpu_points, &points[..forecast_columns], cpu_rect);
+            canvas.draw_braille_forecast(&state.cpu_points, &band, cpu_rect, cpu_color, Color::DarkGrey)?;
+        }
+        (PanelOverlay::Off, None, None) => canvas.draw_braille_line(&state.cpu_points, cpu_rect)?,
+    }
     
     // === MEMORY SECTION ===
     canvas.set_cursor(0, mem_rect.y)?;
@@ -118,15 +876,8 @@ pub async fn draw_dashboard(
     
     // Memory Graph
     let target_mem_y = (memory_percentage / 100.0) * mem_rect.height as f64;
-    *current_mem_y_val = *current_mem_y_val * 0.8 + target_mem_y * 0.2;
-    
-    mem_points.push((*iteration_count as f64, *current_mem_y_val));
-    if mem_points.len() > mem_rect.width as usize {
-        mem_points.remove(0);
-        for p in mem_points.iter_mut() {
-            p.0 -= 1.0;
-        }
-    }
+    state.current_mem_y_val = state.current_mem_y_val * 0.8 + target_mem_y * 0.2;
+    push_graph_point(&mut state.mem_points, state.current_mem_y_val, mem_rect.width as usize - forecast_columns);
     
     // Clear memory graph area
     for y in mem_rect.y..(mem_rect.y + mem_rect.height) {
@@ -142,7 +893,23 @@ pub async fn draw_dashboard(
         Color::Blue 
     };
     canvas.set_color(mem_color)?;
-    canvas.draw_braille_line(&mem_points, &mem_rect)?;
+    match (&mem_view, &mem_forecast) {
+        (Some(columns), _) => draw_history_graph(canvas, columns, mem_rect, mem_color)?,
+        (None, Some(points)) => {
+            let band = forecast_band(&state.mem_points, &points[..forecast_columns], mem_rect);
+            canvas.draw_braille_forecast(&state.mem_points, &band, mem_rect, mem_color, Color::DarkGrey)?;
+        }
+        (None, None) => canvas.draw_braille_line(&state.mem_points, mem_rect)?,
+    }
+    draw_time_view(
+        canvas,
+        &state.view,
+        snapshot.timestamp,
+        cpu_view.as_deref().unwrap_or_default(),
+        mem_view.as_deref().unwrap_or_default(),
+        cpu_rect.y + cpu_rect.height,
+        mem_rect.y + mem_rect.height,
+    )?;
     
     // === SYSTEM INFO SECTION ===
     canvas.set_cursor(0, network_start_y)?;
@@ -179,48 +946,128 @@ pub async fn draw_dashboard(
     canvas.set_cursor(0, network_start_y + 3)?;
     canvas.set_color(Color::Blue)?;
     canvas.draw_str("Disks: ")?;
-    for (name, total, _available, used_pct) in disk_info.iter().take(3) { // Show max 3 disks
-        let disk_color = if *used_pct > 90.0 {
+    for disk in disk_info.iter().take(3) { // Show max 3 disks
+        let used_pct = disk.used_percentage();
+        let disk_color = if used_pct > 90.0 {
             Color::Red
-        } else if *used_pct > 75.0 {
+        } else if used_pct > 75.0 {
             Color::Yellow
         } else {
             Color::Green
         };
         canvas.set_color(disk_color)?;
         canvas.draw_str(&format!("{}:{:.1}%({:.1}GB) ", 
-            name.chars().take(3).collect::<String>(), 
+            disk.name.chars().take(3).collect::<String>(), 
             used_pct, 
-            *total as f64 / 1_073_741_824.0))?;
+            disk.total as f64 / 1_073_741_824.0))?;
+    }
+
+    // === EXHAUSTION SECTION ===
+    let estimates = state.exhaustion.estimates();
+    let alert_horizon = Duration::from_secs(dashboard_config.exhaustion.alert_within_secs);
+    canvas.set_cursor(0, network_start_y + 4)?;
+    canvas.draw_str(&" ".repeat(cpu_rect.width as usize))?;
+    if !estimates.is_empty() {
+        canvas.set_cursor(0, network_start_y + 4)?;
+        canvas.set_color(Color::White)?;
+        canvas.draw_str("Exhaustion: ")?;
+        for estimate in estimates.iter().take(3) {
+            canvas.set_color(if estimate.is_within(alert_horizon) { Color::Red } else { Color::Yellow })?;
+            canvas.draw_str(&format!("{}  ", estimate.describe()))?;
+        }
     }
     
     // === ANALYTICS SECTION ===
+    let analytics_y = network_start_y + 5;
+    canvas.set_cursor(0, analytics_y)?;
+    canvas.draw_str(&" ".repeat(cpu_rect.width as usize))?;
     if dashboard_config.show_correlations || dashboard_config.show_predictions {
-        canvas.set_cursor(0, network_start_y + 1)?;
+        canvas.set_cursor(0, analytics_y)?;
         canvas.set_color(Color::Yellow)?;
         
         // Correlation analysis (if enabled)
-        if dashboard_config.show_correlations && *iteration_count % 10 == 0 && cpu_history.len() > 5 {
-            if let Some(correlation) = calculate_correlation(&cpu_history, &dummy_metric_history) {
-                canvas.draw_str(&format!("Correlation: {:.3}", correlation))?;
+        if dashboard_config.show_correlations {
+            let aligned = |a: &str, b: &str| state.metrics.aligned(a, b);
+            let matrix = CorrelationMatrix::compute(correlation_names(&state.metrics, snapshot), aligned);
+            let pairs = matrix.strong_pairs(STRONG_CORRELATION, CORRELATION_MAX_LAG, aligned);
+            if let Some(pair) = pairs.first() {
+                canvas.draw_str(&format!("Corr: {}~{} {:+.2}", short_label(&pair.first), short_label(&pair.second), pair.correlation))?;
+            }
+
+            let panel = Rect { x: cpu_rect.x + cpu_rect.width + 2, y: 0, width: 72, height: 18 };
+            if canvas.fits(&panel) {
+                draw_correlation_panel(canvas, &panel, &matrix, &pairs)?;
             }
         }
         
         // Prediction (if enabled)
-        if dashboard_config.show_predictions {
-            if let Some(predicted_cpu) = predict_next_value(&cpu_history, 5) {
-                canvas.set_cursor(25, network_start_y + 1)?;
-                canvas.draw_str(&format!("Predicted CPU: {:.1}%", predicted_cpu))?;
-            }
+        if dashboard_config.show_predictions
+            && let Some(next) = cpu_forecast.as_ref().and_then(|points| points.first()) {
+            let next = next.clamped(0.0, 100.0);
+            canvas.set_cursor(30, analytics_y)?;
+            canvas.set_color(Color::Yellow)?;
+            canvas.draw_str(&format!("Forecast CPU ({}): {:.1}% [{:.1}-{:.1}]",
+                dashboard_config.forecast.model.label(), next.value, next.lower, next.upper))?;
         }
     }
     
-    // Custom metric
-    let mut metrics = HashMap::new();
-    metrics.insert("cpu_usage", cpu_usage as f64);
-    if let Some(custom_value) = evaluate_metric_formula("cpu_usage * 1.5 + 5.0", &metrics) {
+    // === CUSTOM METRICS SECTION ===
+    if let (Some(metric), Some(value)) = (state.custom_metrics.metrics.first(), custom_values.first()) {
         canvas.set_cursor(50, network_start_y + 1)?;
-        canvas.draw_str(&format!("Custom: {:.1}", custom_value))?;
+        match value.value {
+            Ok(v) => {
+                canvas.set_color(level_color(value.level))?;
+                canvas.draw_str(&format!("{}: {}", metric.config.name, metric.config.format_value(v)))?;
+            }
+            Err(_) => {
+                canvas.set_color(Color::DarkGrey)?;
+                canvas.draw_str(&format!("{}: n/a", metric.config.name))?;
+            }
+        }
+    }
+
+    let panel = Rect {
+        x: cpu_rect.x + cpu_rect.width + 2,
+        y: 19,
+        width: 72,
+        height: state.custom_metrics.metrics.len() as u16 + 1,
+    };
+    if !state.custom_metrics.is_empty() && canvas.fits(&panel) {
+        draw_custom_metrics_panel(canvas, &panel, &state.custom_metrics, &custom_values, &state.metrics)?;
+    }
+
+    // === ALERTS SECTION ===
+    let alerts_panel = Rect {
+        x: panel.x,
+        y: if state.custom_metrics.is_empty() { panel.y } else { panel.y + panel.height + 1 },
+        width: 72,
+        height: 10,
+    };
+    if !state.alerts.rules.is_empty() && canvas.fits(&alerts_panel) {
+        let failures = state.notifications.as_ref().map(|n| n.failures()).unwrap_or_default();
+        draw_alerts_panel(canvas, &alerts_panel, &state.alerts, &failures, snapshot.timestamp)?;
+    }
+
+    // === EVENTS SECTION ===
+    let events_panel = Rect {
+        x: panel.x,
+        y: if state.alerts.rules.is_empty() { alerts_panel.y } else { alerts_panel.y + alerts_panel.height + 1 },
+        width: 72,
+        height: 8,
+    };
+    if canvas.fits(&events_panel) {
+        draw_events_panel(canvas, &events_panel, &state.events)?;
+    }
+
+    // === LEARNING SECTION ===
+    let learning_panel = Rect {
+        x: panel.x,
+        y: events_panel.y + events_panel.height + 1,
+        width: 72,
+        height: state.learner.config.metrics.len() as u16 + 1,
+    };
+    if state.learner.config.enabled && canvas.fits(&learning_panel) {
+        draw_learning_panel(canvas, &learning_panel, &state.learner)?;
     }
     
     // === PROCESS SECTION ===
@@ -241,6 +1088,11 @@ pub async fn draw_dashboard(
             canvas.draw_str(&format!("  {:5} {:20} {:6.1}% {:8}KB", pid, name, cpu, memory / 1024))?;
         }
     }
+    // Rows left over from fewer processes or a larger max_processes before a reload
+    for i in processes.len().min(dashboard_config.max_processes)..7 {
+        canvas.set_cursor(0, proc_start_y + 1 + i as u16)?;
+        canvas.draw_str(&" ".repeat(80))?;
+    }
     
     // === PERFORMANCE SECTION ===
     let (fps, frame_time, adaptive) = perf_monitor.get_performance_stats();
@@ -252,7 +1104,7 @@ pub async fn draw_dashboard(
     // === FOOTER ===
     canvas.set_cursor(0, proc_start_y + 9)?;
     canvas.set_color(Color::DarkGrey)?;
-    canvas.draw_str(&format!("Config: {} | Controls: q=quit, h=help, p=pause, a=adaptive", 
+    canvas.draw_str(&format!("Config: {} | Controls: q=quit, h=help, p=pause, a=adaptive, [/]=pan, z/x=zoom, c=cursor, o=panels", 
         dashboard_config.name))?;
     
     Ok(())
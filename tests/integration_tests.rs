@@ -1,5 +1,6 @@
-use grainx::analytics::{calculate_correlation, predict_next_value, evaluate_metric_formula};
+use grainx::analytics::{calculate_correlation, lagged_correlation, predict_next_value, evaluate_metric_formula};
 use grainx::config::DashboardConfig;
+use grainx::forecast::{forecast, ForecastConfig, ForecastModel};
 use grainx::performance::PerformanceMonitor;
 use std::collections::HashMap;
 
@@ -30,6 +31,16 @@ fn test_correlation_empty_data() {
     assert!(correlation.is_none(), "Empty data should return None");
 }
 
+#[test]
+fn test_lagged_correlation_detects_follower() {
+    let leader: Vec<f64> = (0..30).map(|i| if i % 6 < 2 { 90.0 } else { 10.0 }).collect();
+    let follower: Vec<f64> = (0..30).map(|i| if (i + 4) % 6 < 2 { 5.0 } else { 1.0 }).collect();
+
+    let (lag, correlation) = lagged_correlation(&leader, &follower, 3).unwrap();
+    assert_eq!(lag, 2, "Follower should trail the leader by two samples");
+    assert!((correlation - 1.0).abs() < 0.001);
+}
+
 #[test]
 fn test_prediction_simple_average() {
     let history = vec![10.0, 20.0, 30.0, 40.0, 50.0];
@@ -47,6 +58,19 @@ fn test_prediction_insufficient_data() {
     assert!(prediction.is_none(), "Insufficient data should return None");
 }
 
+#[test]
+fn test_forecast_models_extrapolate_trend() {
+    let history: Vec<f64> = (0..30).map(|i| 20.0 + i as f64).collect();
+
+    for model in [ForecastModel::LinearRegression, ForecastModel::Holt] {
+        let config = ForecastConfig { model, horizon: 3, ..ForecastConfig::default() };
+        let points = forecast(&history, &config).unwrap();
+        assert_eq!(points.len(), 3);
+        assert!((points[2].value - 52.0).abs() < 0.5, "{:?} predicted {}", model, points[2].value);
+        assert!(points[2].lower <= points[2].value && points[2].value <= points[2].upper);
+    }
+}
+
 #[test]
 fn test_metric_formula_evaluation() {
     let mut metrics = HashMap::new();
@@ -131,4 +155,264 @@ fn test_performance_monitor_toggle_adaptive() {
     let toggled_state = perf.get_performance_stats().2;
     
     assert_ne!(initial_state, toggled_state, "Adaptive state should toggle");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_batch_mode_writes_sample_lines() {
+    let mut config = DashboardConfig::default_config();
+    config.notifiers.clear();
+    config.learning.enabled = false;
+    config.storage.enabled = false;
+    let mut output = Vec::new();
+    grainx::batch::run_batch(&config, Some(1), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let first = output.lines().next().unwrap();
+    assert!(first.contains(" sample host="), "unexpected batch line: {}", first);
+    assert!(first.contains(" cpu="));
+}
+
+#[test]
+fn test_replayed_recording_fires_alerts() {
+    use grainx::recording::{Player, Recorder, Recording};
+    use grainx::ui::DashboardState;
+
+    let path = std::env::temp_dir().join(format!("grainx-replay-{}.grx", std::process::id())).display().to_string();
+    let mut snapshot = grainx::monitor::SystemMonitor::new().snapshot();
+    let start = snapshot.timestamp;
+    let mut recorder = Recorder::create(&path).unwrap();
+    for second in 0..40 {
+        snapshot.timestamp = start + chrono::Duration::seconds(second);
+        snapshot.cpu_usage = 95.0;
+        recorder.record(&snapshot, &[]).unwrap();
+    }
+    drop(recorder);
+
+    // The same recording always drives the dashboard to the same state
+    let config = DashboardConfig::default_config().for_replay();
+    let mut state = DashboardState::new(&config).unwrap();
+    let mut player = Player::new(Recording::load(&path).unwrap());
+    for frame in player.advance(std::time::Duration::from_secs(60)) {
+        state.update(&frame.snapshot);
+    }
+    assert!(player.is_finished());
+    assert!(state.alerts.firing().iter().any(|rule| rule.config.name == "High CPU"));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_exhaustion_forecast_as_alert_condition() {
+    use grainx::alerts::AlertRuleConfig;
+    use grainx::ui::DashboardState;
+
+    let mut config = DashboardConfig::default_config().for_replay();
+    config.alerts = vec![AlertRuleConfig {
+        name: "RAM running out".to_string(),
+        condition: "mem_full_in_s < 3600".to_string(),
+        severity: Default::default(),
+        clear: None,
+    }];
+    let mut state = DashboardState::new(&config).unwrap();
+    let mut snapshot = grainx::monitor::SystemMonitor::new().snapshot();
+    let start = snapshot.timestamp;
+    snapshot.total_memory = 1000;
+    // One percentage point per minute from 50%
+    for minute in 0..12 {
+        snapshot.timestamp = start + chrono::Duration::minutes(minute);
+        snapshot.used_memory = 500 + minute as u64 * 10;
+        state.update(&snapshot);
+    }
+    assert!(state.alerts.firing().iter().any(|rule| rule.config.name == "RAM running out"));
+}
+
+#[test]
+fn test_server_runs_alerts_per_agent_host() {
+    use grainx::network::{Agent, AgentConfig, Server, ServerConfig};
+    use grainx::recording::Frame;
+    use grainx::ui::DashboardState;
+
+    let server = Server::start(ServerConfig { listen: "127.0.0.1:0".to_string(), ..ServerConfig::default() }).unwrap();
+    let agent_config = AgentConfig { server: server.local_addr().to_string(), ..AgentConfig::default() };
+    let mut snapshot = grainx::monitor::SystemMonitor::new().snapshot();
+    let start = snapshot.timestamp;
+    for (host, cpu) in [("busy", 95.0), ("idle", 5.0)] {
+        let mut agent = Agent::new(agent_config.clone(), host).unwrap();
+        for second in 0..40 {
+            snapshot.timestamp = start + chrono::Duration::seconds(second);
+            snapshot.cpu_usage = cpu;
+            assert!(agent.send(&Frame { snapshot: snapshot.clone(), events: Vec::new() }));
+        }
+    }
+
+    let config = DashboardConfig::default_config().for_remote_host();
+    let mut states = std::collections::BTreeMap::new();
+    let mut received = 0;
+    let started = std::time::Instant::now();
+    while received < 80 {
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "only {} frames arrived", received);
+        for (host, frames) in server.drain_frames() {
+            let state = states.entry(host).or_insert_with(|| DashboardState::new(&config).unwrap());
+            for frame in frames {
+                state.update(&frame.snapshot);
+                received += 1;
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(states["busy"].alerts.firing().iter().any(|rule| rule.config.name == "High CPU"));
+    assert!(states["idle"].alerts.firing().is_empty());
+}
+
+/// A CA with a server certificate for localhost and a client certificate,
+/// written as PEM files to a fresh directory.
+fn tls_files(test: &str) -> std::path::PathBuf {
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+
+    let dir = std::env::temp_dir().join(format!("grainx-tls-{}-{}", std::process::id(), test));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, pem: String| std::fs::write(dir.join(name), pem).unwrap();
+
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "grainx test CA");
+    let ca_key = KeyPair::generate().unwrap();
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    write("ca.pem", ca.pem());
+
+    for (name, names) in [("server", vec!["localhost".to_string(), "127.0.0.1".to_string()]), ("client", vec!["web-1".to_string()])] {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(names).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+        write(&format!("{}.pem", name), cert.pem());
+        write(&format!("{}.key", name), key.serialize_pem());
+    }
+
+    // A second CA that signed nothing above
+    let mut other_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    other_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    other_params.distinguished_name.push(DnType::CommonName, "grainx other CA");
+    write("other-ca.pem", other_params.self_signed(&KeyPair::generate().unwrap()).unwrap().pem());
+    dir
+}
+
+fn tls_config(dir: &std::path::Path, cert: &str, ca: &str) -> grainx::tls::TlsConfig {
+    let path = |name: String| dir.join(name).display().to_string();
+    grainx::tls::TlsConfig {
+        enabled: true,
+        cert: if cert.is_empty() { String::new() } else { path(format!("{}.pem", cert)) },
+        key: if cert.is_empty() { String::new() } else { path(format!("{}.key", cert)) },
+        ca: if ca.is_empty() { String::new() } else { path(format!("{}.pem", ca)) },
+        server_name: String::new(),
+    }
+}
+
+#[test]
+fn test_agent_and_server_over_tls() {
+    use grainx::network::{Agent, AgentConfig, Server, ServerConfig};
+    use grainx::recording::Frame;
+
+    let dir = tls_files("server-only");
+    let server = Server::start(ServerConfig {
+        listen: "127.0.0.1:0".to_string(),
+        token: "s3cret".to_string(),
+        tls: tls_config(&dir, "server", ""),
+        ..ServerConfig::default()
+    }).unwrap();
+    let agent_config = AgentConfig {
+        server: server.local_addr().to_string(),
+        token: "s3cret".to_string(),
+        tls: tls_config(&dir, "", "ca"),
+        ..AgentConfig::default()
+    };
+    let frame = Frame { snapshot: grainx::monitor::SystemMonitor::new().snapshot(), events: Vec::new() };
+
+    let mut agent = Agent::new(agent_config.clone(), "web-1").unwrap();
+    assert!(agent.send(&frame), "{:?}", agent.last_error);
+
+    // A server certificate from an unknown CA is not trusted
+    let untrusted = AgentConfig { tls: tls_config(&dir, "", "other-ca"), ..agent_config.clone() };
+    let mut agent = Agent::new(untrusted, "web-2").unwrap();
+    assert!(!agent.send(&frame));
+    let error = agent.last_error.clone().unwrap();
+    assert!(error.starts_with("tls handshake failed:") && error.contains("UnknownIssuer"), "{}", error);
+
+    // Without TLS the agent is told what went wrong
+    let plain = AgentConfig { tls: Default::default(), ..agent_config.clone() };
+    let mut agent = Agent::new(plain, "web-3").unwrap();
+    assert!(!agent.send(&frame));
+    assert!(agent.last_error.clone().unwrap().contains("received TLS data on a connection without TLS"), "{:?}", agent.last_error);
+
+    let started = std::time::Instant::now();
+    let mut rejections = Vec::new();
+    while rejections.len() < 2 {
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "only {:?} were rejected", rejections);
+        rejections.extend(server.drain_rejections().into_iter().map(|(_, reason)| reason));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(rejections.iter().all(|reason| reason.starts_with("tls handshake failed:")), "{:?}", rejections);
+    let hosts: Vec<String> = server.hosts().into_iter().map(|status| status.host).collect();
+    assert_eq!(hosts, ["web-1"]);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_mutual_tls_requires_client_certificate() {
+    use grainx::network::{Agent, AgentConfig, Server, ServerConfig};
+    use grainx::recording::Frame;
+
+    let dir = tls_files("mutual");
+    let server = Server::start(ServerConfig {
+        listen: "127.0.0.1:0".to_string(),
+        tls: tls_config(&dir, "server", "ca"),
+        ..ServerConfig::default()
+    }).unwrap();
+    let frame = Frame { snapshot: grainx::monitor::SystemMonitor::new().snapshot(), events: Vec::new() };
+    let agent = |cert: &str| AgentConfig {
+        server: server.local_addr().to_string(),
+        tls: tls_config(&dir, cert, "ca"),
+        ..AgentConfig::default()
+    };
+
+    let mut anonymous = Agent::new(agent(""), "web-1").unwrap();
+    assert!(!anonymous.send(&frame));
+    assert!(anonymous.last_error.clone().unwrap().contains("CertificateRequired"), "{:?}", anonymous.last_error);
+
+    let mut trusted = Agent::new(agent("client"), "web-1").unwrap();
+    assert!(trusted.send(&frame), "{:?}", trusted.last_error);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_invalid_config_is_reported_and_kept() {
+    let dir = std::env::temp_dir().join(format!("grainx-it-{}-invalid-config", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dashboard_config.json");
+    let broken = DashboardConfig::default_config();
+    let text = serde_json::to_string_pretty(&broken).unwrap().replace("\"cpu_warning_threshold\": 80.0", "\"cpu_warning_threshold\": 180.0");
+    std::fs::write(&path, &text).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_grainx"))
+        .args(["--batch", "-n", "1", "-c", path.to_str().unwrap()])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("cpu_warning_threshold: must be between 0 and 100, got 180 at line 12 column"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+
+    // Only a missing file is replaced by the default; the run keeps its
+    // data files in the scratch directory
+    std::fs::remove_file(&path).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_grainx"))
+        .args(["--batch", "-n", "1", "-c", path.to_str().unwrap()])
+        .current_dir(&dir)
+        .env("XDG_DATA_HOME", &dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(DashboardConfig::load_from_file(path.to_str().unwrap()).is_ok());
+    std::fs::remove_dir_all(&dir).ok();
+}